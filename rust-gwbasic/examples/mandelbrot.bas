@@ -1,7 +1,7 @@
 10 REM Mandelbrot Set Renderer
 20 PRINT "=== Mandelbrot Set ==="
 30 PRINT "Rendering... Please wait..."
-40 SCREEN 1
+40 SCREEN 7
 50 CLS
 60 COLOR 0, 0
 70 REM Screen dimensions
@@ -1,9 +1,9 @@
 10 REM String Operations Test
 20 PRINT "=== String Operations ==="
 30 PRINT ""
-40 LET NAME$ = "Alice"
+40 LET PERSON$ = "Alice"
 50 LET GREETING$ = "Hello"
-60 PRINT GREETING$; ", "; NAME$; "!"
+60 PRINT GREETING$; ", "; PERSON$; "!"
 70 PRINT ""
 80 REM String concatenation
 90 LET MESSAGE$ = "Welcome to GW-BASIC"
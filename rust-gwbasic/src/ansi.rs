@@ -0,0 +1,281 @@
+//! Drawing the text screen on an ANSI terminal
+//!
+//! Output starts out as plain lines, coloured by COLOR, so that programs
+//! that only PRINT leave ordinary scrollback. Once a program places text
+//! itself (CLS, LOCATE, VIEW PRINT or a new SCREEN mode) the renderer takes
+//! over the terminal and from then on redraws only the cells that differ
+//! from what it last drew, then puts the terminal cursor where the text
+//! cursor is.
+
+use crate::text_screen::{Cell, TextScreen};
+use std::fmt::Write;
+
+/// Attribute the terminal shows with its default colours
+const DEFAULT_ATTRIBUTE: u8 = 7;
+
+/// ANSI colour numbers of the eight CGA colours, which go blue, green, red
+/// where ANSI goes red, green, blue
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// Select graphic rendition for a text attribute: the foreground in bits
+/// 0-3 (8-15 are high intensity), the background in bits 4-6 and blinking
+/// in bit 7
+fn sgr(attribute: u8) -> String {
+    if attribute == DEFAULT_ATTRIBUTE {
+        return "\x1B[0m".to_string();
+    }
+    let fg = attribute & 0x0F;
+    let bg = (attribute >> 4) & 0x07;
+    let fg_base = if fg & 0x08 != 0 { 90 } else { 30 };
+    let blink = if attribute & 0x80 != 0 { ";5" } else { "" };
+    format!("\x1B[0;{};{}{}m", fg_base + ANSI_COLORS[fg as usize & 7], 40 + ANSI_COLORS[bg as usize], blink)
+}
+
+/// Code page 437 pictures of the control codes 0-31, as the display
+/// adapter draws them
+const CONTROL_GLYPHS: [char; 32] = [
+    ' ', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Code page 437 characters 128-255: accented letters, box drawing,
+/// blocks, Greek and mathematical symbols
+const HIGH_GLYPHS: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// Terminal character for a character code, drawn as the code page 437
+/// font of the display adapter would
+fn glyph(ch: u8) -> char {
+    match ch {
+        0x00..=0x1F => CONTROL_GLYPHS[ch as usize],
+        0x20..=0x7E => ch as char,
+        0x7F => '⌂',
+        0x80..=0xFF => HIGH_GLYPHS[ch as usize - 0x80],
+    }
+}
+
+/// Escape sequences that bring a terminal in step with a text screen
+#[derive(Debug, Clone)]
+pub struct AnsiRenderer {
+    /// Whether the renderer has taken over the terminal
+    full_screen: bool,
+
+    /// Cells as last drawn; empty if the terminal must be cleared first
+    shown: Vec<Cell>,
+    columns: usize,
+
+    /// Where the terminal cursor is, if known
+    cursor: Option<(usize, usize)>,
+    cursor_visible: Option<bool>,
+
+    /// Attribute of the terminal's current colours
+    attribute: u8,
+}
+
+impl AnsiRenderer {
+    pub fn new() -> Self {
+        AnsiRenderer {
+            full_screen: false,
+            shown: Vec::new(),
+            columns: 0,
+            cursor: None,
+            cursor_visible: None,
+            attribute: DEFAULT_ATTRIBUTE,
+        }
+    }
+
+    pub fn is_full_screen(&self) -> bool {
+        self.full_screen
+    }
+
+    /// Output for `text` just written to `screen`: the text itself while
+    /// output is still plain lines, otherwise the changed cells
+    pub fn write(&mut self, text: &str, screen: &TextScreen) -> String {
+        if self.full_screen {
+            return self.redraw(screen);
+        }
+        let mut out = String::new();
+        if screen.attribute() != self.attribute {
+            out.push_str(&sgr(screen.attribute()));
+            self.attribute = screen.attribute();
+        }
+        // Line breaks and other control codes still act on the terminal
+        out.extend(text.chars().map(|ch| match ch as u32 {
+            0x7F..=0xFF => glyph(ch as u8),
+            _ => ch,
+        }));
+        self.reset_colors(&mut out);
+        out
+    }
+
+    /// Clear the terminal and draw the whole screen, and only changes to it
+    /// from now on
+    pub fn take_over(&mut self, screen: &TextScreen) -> String {
+        if !self.full_screen {
+            self.full_screen = true;
+            self.invalidate();
+        }
+        self.redraw(screen)
+    }
+
+    /// Forget what the terminal shows, as it echoed typed input, so that
+    /// the next redraw starts from a clear terminal
+    pub fn invalidate(&mut self) {
+        self.shown.clear();
+        self.cursor = None;
+    }
+
+    /// Draw the cells that changed since the last redraw, then move the
+    /// cursor to the text cursor
+    pub fn redraw(&mut self, screen: &TextScreen) -> String {
+        let mut out = String::new();
+        let size = screen.columns() * screen.rows();
+        if self.shown.len() != size || self.columns != screen.columns() {
+            out.push_str("\x1B[0m\x1B[2J\x1B[H");
+            self.attribute = DEFAULT_ATTRIBUTE;
+            self.shown = vec![Cell { ch: b' ', attribute: DEFAULT_ATTRIBUTE }; size];
+            self.columns = screen.columns();
+            self.cursor = Some((0, 0));
+        }
+
+        for row in 0..screen.rows() {
+            for column in 0..self.columns {
+                let index = row * self.columns + column;
+                let Some(cell) = screen.cell(row, column) else { continue };
+                if self.shown[index] == cell {
+                    continue;
+                }
+                self.move_cursor(&mut out, row, column);
+                if cell.attribute != self.attribute {
+                    out.push_str(&sgr(cell.attribute));
+                    self.attribute = cell.attribute;
+                }
+                out.push(glyph(cell.ch));
+                self.shown[index] = cell;
+                // After the last column the cursor waits to wrap
+                self.cursor = (column + 1 < self.columns).then_some((row, column + 1));
+            }
+        }
+        self.reset_colors(&mut out);
+
+        let (row, column) = screen.cursor();
+        self.move_cursor(&mut out, row, column.min(self.columns - 1));
+        let (visible, _, _) = screen.cursor_shape();
+        if self.cursor_visible != Some(visible) {
+            out.push_str(if visible { "\x1B[?25h" } else { "\x1B[?25l" });
+            self.cursor_visible = Some(visible);
+        }
+        out
+    }
+
+    /// Show the cursor while the user types
+    pub fn show_cursor(&mut self) -> String {
+        if !self.full_screen || self.cursor_visible == Some(true) {
+            return String::new();
+        }
+        self.cursor_visible = Some(true);
+        "\x1B[?25h".to_string()
+    }
+
+    /// Leave the terminal with its default colours, a cursor, and the
+    /// cursor at the start of a line
+    pub fn restore(&mut self) -> String {
+        if !self.full_screen {
+            return String::new();
+        }
+        let mut out = "\x1B[0m\x1B[?25h".to_string();
+        if self.cursor.is_none_or(|(_, column)| column > 0) {
+            out.push_str("\r\n");
+        }
+        out
+    }
+
+    fn move_cursor(&mut self, out: &mut String, row: usize, column: usize) {
+        if self.cursor != Some((row, column)) {
+            write!(out, "\x1B[{};{}H", row + 1, column + 1).ok();
+            self.cursor = Some((row, column));
+        }
+    }
+
+    /// Go back to the default colours, so that anything else written to
+    /// the terminal is not coloured
+    fn reset_colors(&mut self, out: &mut String) {
+        if self.attribute != DEFAULT_ATTRIBUTE {
+            out.push_str("\x1B[0m");
+            self.attribute = DEFAULT_ATTRIBUTE;
+        }
+    }
+}
+
+impl Default for AnsiRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes() {
+        assert_eq!(sgr(7), "\x1B[0m");
+        // Yellow on blue: high-intensity brown, blue background
+        assert_eq!(sgr(0x1E), "\x1B[0;93;44m");
+        // Blinking red on cyan
+        assert_eq!(sgr(0x84 | 0x30), "\x1B[0;31;46;5m");
+    }
+
+    #[test]
+    fn test_glyphs() {
+        // Box drawing, blocks and shades come out as on the PC screen
+        assert_eq!(glyph(196), '─');
+        assert_eq!(glyph(205), '═');
+        assert_eq!(glyph(201), '╔');
+        assert_eq!(glyph(219), '█');
+        assert_eq!([glyph(176), glyph(177), glyph(178)], ['░', '▒', '▓']);
+        assert_eq!(glyph(1), '☺');
+        assert_eq!(glyph(0), ' ');
+        assert_eq!(glyph(b'A'), 'A');
+        assert_eq!(glyph(130), 'é');
+        assert_eq!(glyph(227), 'π');
+    }
+
+    #[test]
+    fn test_plain_lines() {
+        let mut screen = TextScreen::new(80, 25);
+        let mut renderer = AnsiRenderer::new();
+        assert_eq!(renderer.write("HELLO\n", &screen), "HELLO\n");
+        assert_eq!(renderer.write("\u{C9}\u{CD}\u{BB}\n", &screen), "╔═╗\n");
+        screen.set_attribute(0x0C);
+        assert_eq!(renderer.write("RED", &screen), "\x1B[0;91;40mRED\x1B[0m");
+    }
+
+    #[test]
+    fn test_redraw_changes() {
+        let mut screen = TextScreen::new(10, 4);
+        let mut renderer = AnsiRenderer::new();
+        let first = renderer.take_over(&screen);
+        assert_eq!(first, "\x1B[0m\x1B[2J\x1B[H\x1B[?25l");
+
+        screen.locate(Some(1), Some(2)).unwrap();
+        screen.put(b'A');
+        screen.put(b'B');
+        screen.locate(Some(2), Some(9)).unwrap();
+        screen.set_attribute(0x1F);
+        screen.put(b'C');
+        screen.locate(Some(0), Some(0)).unwrap();
+        assert_eq!(renderer.redraw(&screen), "\x1B[2;3HAB\x1B[3;10H\x1B[0;97;44mC\x1B[0m\x1B[1;1H");
+
+        // Nothing changed, nothing to draw
+        assert_eq!(renderer.redraw(&screen), "");
+    }
+}
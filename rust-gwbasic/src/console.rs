@@ -0,0 +1,399 @@
+//! Text console for PRINT and other screen output
+//!
+//! Tracks the cursor column so that print zones, `TAB()`, `SPC()`, `POS()`
+//! and wrapping at the current `WIDTH` behave as in GW-BASIC. The console
+//! of the screen also keeps a `TextScreen` up to date with what it writes,
+//! and draws it with an `AnsiRenderer` when standard output is a terminal.
+
+use crate::ansi::AnsiRenderer;
+use crate::error::{Error, Result};
+use crate::text_screen::{TextScreen, TEXT_ROWS};
+use std::io::{self, IsTerminal, Write};
+
+/// Width of a print zone (the `,` separator in PRINT)
+pub const ZONE_WIDTH: usize = 14;
+
+/// Where console output goes
+enum Output {
+    /// Plain text, for a pipe
+    Stdout,
+    /// Plain text to a file or other writer, for the printer
+    Writer(Box<dyn Write>),
+    Buffer(String),
+    /// The text screen drawn on a terminal
+    Terminal(AnsiRenderer),
+    /// Nothing written: the graphics backend draws the text screen
+    Graphics,
+}
+
+/// Line-oriented text output with a cursor column
+pub struct Console {
+    output: Output,
+
+    /// Cursor column, counting from 0
+    column: usize,
+
+    /// Line width set by WIDTH
+    width: usize,
+
+    /// Characters on the screen, for the console of the screen only
+    screen: Option<TextScreen>,
+}
+
+impl Console {
+    /// Console writing to standard output, 80 columns wide
+    pub fn new() -> Self {
+        Console {
+            output: Output::Stdout,
+            column: 0,
+            width: 80,
+            screen: None,
+        }
+    }
+
+    /// Console of a printer (LPRINT) whose lines go to `sink`, such as a
+    /// file, apart from the program's output
+    pub fn printer(sink: Box<dyn Write>) -> Self {
+        Console {
+            output: Output::Writer(sink),
+            column: 0,
+            width: 80,
+            screen: None,
+        }
+    }
+
+    /// Console of the screen, with a text screen of 80 columns by 25 rows.
+    /// It draws the screen if standard output is a terminal, and writes
+    /// plain text otherwise.
+    pub fn screen() -> Self {
+        let output = if io::stdout().is_terminal() {
+            Output::Terminal(AnsiRenderer::new())
+        } else {
+            Output::Stdout
+        };
+        Console {
+            output,
+            column: 0,
+            width: 80,
+            screen: Some(TextScreen::new(80, TEXT_ROWS)),
+        }
+    }
+
+    /// Console collecting its output in memory (see `take_output`)
+    pub fn buffered(width: usize) -> Self {
+        Console {
+            output: Output::Buffer(String::new()),
+            column: 0,
+            width,
+            screen: None,
+        }
+    }
+
+    /// Keep further output in memory instead of writing it to stdout
+    pub fn capture(&mut self) {
+        if let Output::Stdout | Output::Terminal(_) | Output::Graphics = self.output {
+            self.output = Output::Buffer(String::new());
+        }
+    }
+
+    /// Leave drawing the text screen to a graphics backend that shares the
+    /// terminal, writing nothing to stdout
+    pub fn show_in_graphics(&mut self) {
+        if let Output::Stdout | Output::Terminal(_) = self.output {
+            self.output = Output::Graphics;
+        }
+    }
+
+    /// Output collected so far by a buffered console
+    pub fn take_output(&mut self) -> String {
+        match &mut self.output {
+            Output::Buffer(text) => std::mem::take(text),
+            Output::Stdout | Output::Writer(_) | Output::Terminal(_) | Output::Graphics => String::new(),
+        }
+    }
+
+    /// Cursor column, counting from 0
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Cursor row, counting from 0; always 0 without a text screen
+    pub fn row(&self) -> usize {
+        self.screen.as_ref().map_or(0, |screen| screen.cursor().0)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn text_screen(&self) -> Option<&TextScreen> {
+        self.screen.as_ref()
+    }
+
+    pub fn text_screen_mut(&mut self) -> Option<&mut TextScreen> {
+        self.screen.as_mut()
+    }
+
+    /// Set the line width (WIDTH n). A width of 40 or 80 also sets the
+    /// columns of the text screen, which clears it.
+    pub fn set_width(&mut self, width: i32) -> Result<()> {
+        if !(1..=255).contains(&width) {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        self.width = width as usize;
+        if let Some(screen) = &mut self.screen {
+            if (width == 40 || width == 80) && screen.columns() != self.width {
+                screen.resize(self.width, TEXT_ROWS);
+                self.column = 0;
+                self.take_over();
+            }
+        }
+        Ok(())
+    }
+
+    /// Move the cursor to `row` and `column` (counting from 0), keeping
+    /// either where it is if None (LOCATE)
+    pub fn locate(&mut self, row: Option<usize>, column: Option<usize>) -> Result<()> {
+        let Some(screen) = &mut self.screen else {
+            return Ok(());
+        };
+        screen.locate(row, column)?;
+        self.column = screen.cursor().1;
+        self.take_over();
+        Ok(())
+    }
+
+    /// Limit scrolling and CLS to rows `top` to `bottom` (counting from 0),
+    /// or every row but the last for None (VIEW PRINT)
+    pub fn set_view_print(&mut self, rows: Option<(usize, usize)>) -> Result<()> {
+        let Some(screen) = &mut self.screen else {
+            return Ok(());
+        };
+        screen.set_window(rows)?;
+        self.column = 0;
+        self.take_over();
+        Ok(())
+    }
+
+    /// Write text at the cursor, wrapping at the line width
+    pub fn write(&mut self, text: &str) {
+        let mut out = String::with_capacity(text.len());
+        for ch in text.chars() {
+            match ch {
+                '\n' => {
+                    out.push('\n');
+                    self.column = 0;
+                    if let Some(screen) = &mut self.screen {
+                        screen.newline();
+                    }
+                }
+                _ => {
+                    if self.column >= self.width {
+                        out.push('\n');
+                        self.column = 0;
+                        if let Some(screen) = &mut self.screen {
+                            screen.newline();
+                        }
+                    }
+                    out.push(ch);
+                    self.column += 1;
+                    if let Some(screen) = &mut self.screen {
+                        screen.put(ch as u32 as u8);
+                    }
+                }
+            }
+        }
+        self.emit(&out);
+    }
+
+    /// Write one PRINT item, starting a new line first if it would not fit
+    /// in the rest of the current one
+    pub fn write_item(&mut self, text: &str) {
+        let len = text.chars().count();
+        if self.column > 0 && self.column + len > self.width {
+            self.newline();
+        }
+        self.write(text);
+    }
+
+    /// End the current line
+    pub fn newline(&mut self) {
+        self.write("\n");
+    }
+
+    /// Move to the start of the next print zone, or the next line if the
+    /// current zone is the last one that fits
+    pub fn next_zone(&mut self) {
+        let zone = self.column / ZONE_WIDTH + 1;
+        if zone >= self.width / ZONE_WIDTH {
+            self.newline();
+        } else {
+            let pad = zone * ZONE_WIDTH - self.column;
+            self.write(&" ".repeat(pad));
+        }
+    }
+
+    /// Move to column `n` (counting from 1) for `TAB(n)`, on the next line
+    /// if the cursor is already past it
+    pub fn tab(&mut self, n: i32) {
+        let target = (n.max(1) as usize - 1) % self.width;
+        if target < self.column {
+            self.newline();
+        }
+        let pad = target - self.column;
+        self.write(&" ".repeat(pad));
+    }
+
+    /// Write `n` spaces for `SPC(n)`
+    pub fn spc(&mut self, n: i32) {
+        let count = n.max(0) as usize % self.width;
+        self.write(&" ".repeat(count));
+    }
+
+    /// Clear the screen, or the text window of a text screen, and home the
+    /// cursor
+    pub fn clear(&mut self) {
+        if let Some(screen) = &mut self.screen {
+            screen.clear();
+        }
+        self.column = 0;
+        self.take_over();
+    }
+
+    /// Ring the bell (BEEP, or CHR$(7) to the screen), which leaves the
+    /// cursor where it is
+    pub fn bell(&mut self) {
+        match &mut self.output {
+            Output::Buffer(buffer) => buffer.push('\x07'),
+            Output::Writer(_) => {}
+            Output::Stdout | Output::Terminal(_) | Output::Graphics => write_stdout("\x07"),
+        }
+    }
+
+    /// Show the cursor before reading a line of input
+    pub fn waiting_for_input(&mut self) {
+        if let Output::Terminal(renderer) = &mut self.output {
+            write_stdout(&renderer.show_cursor());
+        }
+    }
+
+    /// Note that the user typed `text` and Enter, which the terminal echoed
+    pub fn input_entered(&mut self, text: &str) {
+        if let Some(screen) = &mut self.screen {
+            for ch in text.chars() {
+                screen.put(ch as u32 as u8);
+            }
+            screen.newline();
+        }
+        if let Output::Terminal(renderer) = &mut self.output {
+            renderer.invalidate();
+        }
+        self.column = 0;
+    }
+
+    /// Draw the whole text screen on a terminal from now on, as the
+    /// program is placing text rather than printing lines
+    fn take_over(&mut self) {
+        if let (Output::Terminal(renderer), Some(screen)) = (&mut self.output, &self.screen) {
+            write_stdout(&renderer.take_over(screen));
+        }
+    }
+
+    fn emit(&mut self, text: &str) {
+        match &mut self.output {
+            Output::Stdout => write_stdout(text),
+            Output::Writer(writer) => {
+                writer.write_all(text.as_bytes()).ok();
+                writer.flush().ok();
+            }
+            Output::Buffer(buffer) => buffer.push_str(text),
+            Output::Graphics => {}
+            Output::Terminal(renderer) => {
+                if let Some(screen) = &self.screen {
+                    write_stdout(&renderer.write(text, screen));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        if let Output::Terminal(renderer) = &mut self.output {
+            write_stdout(&renderer.restore());
+        }
+    }
+}
+
+fn write_stdout(text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(text.as_bytes()).ok();
+    stdout.flush().ok();
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_print_zones() {
+        let mut console = Console::buffered(80);
+        for item in ["A", "B", "C", "D", "E", "F"] {
+            console.write_item(item);
+            console.next_zone();
+        }
+        assert_eq!(
+            console.take_output(),
+            format!("A{}B{}C{}D{}E\nF{}", " ".repeat(13), " ".repeat(13), " ".repeat(13), " ".repeat(13), " ".repeat(13))
+        );
+    }
+
+    #[test]
+    fn test_tab_and_spc() {
+        let mut console = Console::buffered(80);
+        console.write("ABCDEF");
+        console.tab(3);
+        console.write("X");
+        console.spc(2);
+        console.write("Y");
+        assert_eq!(console.take_output(), "ABCDEF\n  X  Y");
+        assert_eq!(console.column(), 6);
+    }
+
+    #[test]
+    fn test_wrap_at_width() {
+        let mut console = Console::buffered(10);
+        console.write_item("1234567");
+        console.write_item("abcd");
+        console.write("0123456789XY");
+        assert_eq!(console.take_output(), "1234567\nabcd012345\n6789XY");
+        assert!(console.set_width(0).is_err());
+    }
+
+    #[test]
+    fn test_text_screen() {
+        let mut console = Console::screen();
+        console.capture();
+        console.write("HELLO");
+        console.locate(Some(4), Some(9)).unwrap();
+        console.write("X");
+        assert_eq!((console.row(), console.column()), (4, 10));
+
+        let screen = console.text_screen().unwrap();
+        assert_eq!(screen.row_text(0), "HELLO");
+        assert_eq!(screen.row_text(4), "         X");
+
+        console.set_width(40).unwrap();
+        assert_eq!(console.text_screen().unwrap().columns(), 40);
+        assert_eq!(console.text_screen().unwrap().row_text(0), "");
+    }
+}
@@ -0,0 +1,136 @@
+//! Source locations and error diagnostics for the GW-BASIC interpreter
+
+use crate::error::Error;
+use std::fmt;
+
+/// Location of a statement or token in the program source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// Source line, counting from 1
+    pub line: usize,
+
+    /// BASIC line number, if the statement is part of a numbered line
+    pub line_number: Option<u32>,
+
+    /// Index of the statement within its line, counting from 0
+    pub statement: usize,
+
+    /// First column, counting from 1
+    pub start_column: usize,
+
+    /// Column just past the end
+    pub end_column: usize,
+}
+
+impl Span {
+    /// Span covering both `self` and `other` (which must be on the same line)
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start_column: self.start_column.min(other.start_column),
+            end_column: self.end_column.max(other.end_column),
+            ..self
+        }
+    }
+}
+
+/// An error together with the source text it points into, ready to be shown
+/// to the user or inspected by tools
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The error with its location removed
+    pub error: Error,
+
+    /// Where the error happened
+    pub span: Span,
+
+    /// The full source line the span refers to
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    /// Build a diagnostic for `error` if it carries a location in `source`
+    pub fn new(error: &Error, source: &str) -> Option<Self> {
+        let span = *error.span()?;
+        let source_line = source.lines().nth(span.line.checked_sub(1)?)?.to_string();
+        Some(Diagnostic {
+            error: error.without_span().clone(),
+            span,
+            source_line,
+        })
+    }
+
+    /// Render the error message followed by the source line and a caret
+    /// marker under the offending columns
+    pub fn render(&self) -> String {
+        let gutter = self.span.line.to_string();
+        let pad = " ".repeat(gutter.len());
+
+        let mut location = format!("{}:{}", self.span.line, self.span.start_column);
+        if let Some(num) = self.span.line_number {
+            location.push_str(&format!(" (line {}, statement {})", num, self.span.statement + 1));
+        }
+
+        // Keep tabs in the marker line so the carets line up with the source
+        let indent: String = self
+            .source_line
+            .chars()
+            .take(self.span.start_column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self.span.end_column.saturating_sub(self.span.start_column).max(1);
+
+        format!(
+            "{}\n{} --> {}\n{} |\n{} | {}\n{} | {}{}",
+            self.error,
+            pad,
+            location,
+            pad,
+            gutter,
+            self.source_line.trim_end(),
+            pad,
+            indent,
+            "^".repeat(width)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+/// Format `error` for the user, with a source snippet when it has a location
+pub fn render_error(error: &Error, source: &str) -> String {
+    match Diagnostic::new(error, source) {
+        Some(diagnostic) => diagnostic.render(),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_caret() {
+        let span = Span { line: 2, line_number: Some(20), statement: 0, start_column: 13, end_column: 18 };
+        let error = Error::Located(
+            Box::new(Error::SyntaxError("Expected THEN after IF condition".to_string())),
+            span,
+        );
+        let source = "10 X = 1\n20 IF X > 5 PRINT \"hi\"\n";
+
+        let diagnostic = Diagnostic::new(&error, source).unwrap();
+        assert_eq!(diagnostic.error, Error::SyntaxError("Expected THEN after IF condition".to_string()));
+        assert_eq!(
+            diagnostic.render(),
+            "Syntax error: Expected THEN after IF condition\n  --> 2:13 (line 20, statement 1)\n  |\n2 | 20 IF X > 5 PRINT \"hi\"\n  |             ^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        assert_eq!(render_error(&Error::DivisionByZero, ""), "Division by zero");
+    }
+}
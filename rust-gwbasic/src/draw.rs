@@ -0,0 +1,381 @@
+//! DRAW graphics macro language
+//!
+//! A DRAW string is a list of one-letter commands, each followed by an
+//! optional number. Moves start at the last graphics point:
+//!
+//! - `U`, `D`, `L`, `R` move up, down, left and right, and `E`, `F`, `G`,
+//!   `H` move diagonally (up-right, down-right, down-left, up-left)
+//! - `M x,y` moves to a point, or by an offset when `x` has a sign
+//! - `B` in front of a move moves without drawing, and `N` draws without
+//!   moving the pen
+//! - `A n` rotates moves by n × 90 degrees and `TA n` by n degrees,
+//!   counterclockwise
+//! - `S n` sets the length of a unit move to n/4 pixels
+//! - `C n` sets the colour and `P paint,border` flood fills at the pen
+//! - `X` runs another DRAW string held in a variable
+//!
+//! A number can also be `=` followed by a variable, and a variable is either
+//! the bytes of `VARPTR$(name)` or `name;`. Spaces and `;` between commands
+//! are ignored.
+
+use crate::error::{Error, Result};
+use crate::graphics::Screen;
+use crate::graphics_backend::Fill;
+use crate::value::{string_bytes, Value};
+
+/// Deepest nesting of `X` substrings
+const MAX_DEPTH: usize = 32;
+
+/// Pen settings that carry over from one DRAW statement to the next
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawState {
+    /// Length of a unit move in quarter pixels (`S`)
+    pub scale: u8,
+
+    /// Rotation of moves in degrees, counterclockwise (`A`, `TA`)
+    pub angle: i32,
+
+    /// Colour set by `C`, otherwise the default foreground
+    pub color: Option<u8>,
+}
+
+impl Default for DrawState {
+    fn default() -> Self {
+        DrawState {
+            scale: 4,
+            angle: 0,
+            color: None,
+        }
+    }
+}
+
+/// A variable referenced from a DRAW string
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrawVariable {
+    /// `name;`
+    Name(String),
+
+    /// The three bytes of `VARPTR$(name)`
+    Pointer([u8; 3]),
+}
+
+/// Run a DRAW string, looking up the variables it refers to with `variables`
+pub fn draw(
+    screen: &mut Screen,
+    commands: &str,
+    variables: &mut dyn FnMut(&DrawVariable) -> Result<Value>,
+) -> Result<()> {
+    let mut pen = Pen {
+        state: screen.draw_state(),
+        screen,
+        variables,
+    };
+    let result = pen.run(&string_bytes(commands), 0);
+    let state = pen.state;
+    screen.set_draw_state(state);
+    result
+}
+
+fn illegal_function_call() -> Error {
+    Error::RuntimeError("Illegal function call".to_string())
+}
+
+/// Reads commands and their arguments from a DRAW string
+struct Commands<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Commands<'_> {
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b';')) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    /// The next command letter, in upper case
+    fn next_command(&mut self) -> Option<u8> {
+        self.skip_blanks();
+        self.next_byte().map(|byte| byte.to_ascii_uppercase())
+    }
+
+    /// Skip spaces and check whether the next byte is `expected`
+    fn accept(&mut self, expected: u8) -> bool {
+        while self.peek() == Some(b' ') {
+            self.position += 1;
+        }
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `VARPTR$` bytes, or a name up to the next `;`
+    fn variable(&mut self) -> Result<DrawVariable> {
+        while self.peek() == Some(b' ') {
+            self.position += 1;
+        }
+        if self.peek().is_some_and(|byte| byte.is_ascii_alphabetic()) {
+            let start = self.position;
+            while self.peek().is_some_and(|byte| byte != b';') {
+                self.position += 1;
+            }
+            let name = String::from_utf8_lossy(&self.bytes[start..self.position]).trim().to_string();
+            self.accept(b';');
+            Ok(DrawVariable::Name(name))
+        } else {
+            let pointer = self
+                .bytes
+                .get(self.position..self.position + 3)
+                .ok_or_else(illegal_function_call)?;
+            self.position += 3;
+            Ok(DrawVariable::Pointer([pointer[0], pointer[1], pointer[2]]))
+        }
+    }
+}
+
+/// The graphics pen that DRAW commands move
+struct Pen<'a> {
+    screen: &'a mut Screen,
+    state: DrawState,
+    variables: &'a mut dyn FnMut(&DrawVariable) -> Result<Value>,
+}
+
+impl Pen<'_> {
+    fn run(&mut self, bytes: &[u8], depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(Error::OutOfMemory);
+        }
+        let mut commands = Commands { bytes, position: 0 };
+        let mut blank = false;
+        let mut no_update = false;
+
+        while let Some(command) = commands.next_command() {
+            match command {
+                b'B' => {
+                    blank = true;
+                    continue;
+                }
+                b'N' => {
+                    no_update = true;
+                    continue;
+                }
+                b'U' | b'D' | b'L' | b'R' | b'E' | b'F' | b'G' | b'H' => {
+                    let n = self.number(&mut commands)?.unwrap_or(1) as f64;
+                    let (dx, dy) = match command {
+                        b'U' => (0.0, -n),
+                        b'D' => (0.0, n),
+                        b'L' => (-n, 0.0),
+                        b'R' => (n, 0.0),
+                        b'E' => (n, -n),
+                        b'F' => (n, n),
+                        b'G' => (-n, n),
+                        _ => (-n, -n),
+                    };
+                    self.move_by(dx, dy, blank, no_update)?;
+                }
+                b'M' => {
+                    commands.skip_blanks();
+                    let relative = matches!(commands.peek(), Some(b'+' | b'-'));
+                    let x = self.required_number(&mut commands)?;
+                    if !commands.accept(b',') {
+                        return Err(illegal_function_call());
+                    }
+                    let y = self.required_number(&mut commands)?;
+                    if relative {
+                        self.move_by(x as f64, y as f64, blank, no_update)?;
+                    } else {
+                        self.move_to(x as f64, y as f64, blank, no_update)?;
+                    }
+                }
+                b'A' => {
+                    let n = self.required_number(&mut commands)?;
+                    if !(0..=3).contains(&n) {
+                        return Err(illegal_function_call());
+                    }
+                    self.state.angle = n * 90;
+                }
+                b'T' => {
+                    if !commands.accept(b'A') && !commands.accept(b'a') {
+                        return Err(illegal_function_call());
+                    }
+                    let n = self.required_number(&mut commands)?;
+                    if !(-360..=360).contains(&n) {
+                        return Err(illegal_function_call());
+                    }
+                    self.state.angle = n;
+                }
+                b'S' => {
+                    let n = self.required_number(&mut commands)?;
+                    if !(1..=255).contains(&n) {
+                        return Err(illegal_function_call());
+                    }
+                    self.state.scale = n as u8;
+                }
+                b'C' => {
+                    let n = self.required_number(&mut commands)?;
+                    self.state.color = Some(self.screen.attribute(n)?);
+                }
+                b'P' => {
+                    let paint = self.required_number(&mut commands)?;
+                    if !commands.accept(b',') {
+                        return Err(illegal_function_call());
+                    }
+                    let border = self.required_number(&mut commands)?;
+                    let (paint, border) = (self.screen.attribute(paint)?, self.screen.attribute(border)?);
+                    let (x, y) = self.screen.last_point();
+                    self.screen.paint(x, y, &Fill::Solid(paint), border)?;
+                }
+                b'X' => {
+                    let variable = commands.variable()?;
+                    let substring = (self.variables)(&variable)?.as_string_result()?;
+                    self.run(&string_bytes(&substring), depth + 1)?;
+                }
+                _ => return Err(illegal_function_call()),
+            }
+            blank = false;
+            no_update = false;
+        }
+        Ok(())
+    }
+
+    /// An optional number, which may be `=variable`
+    fn number(&mut self, commands: &mut Commands) -> Result<Option<i32>> {
+        while commands.peek() == Some(b' ') {
+            commands.position += 1;
+        }
+        let negative = match commands.peek() {
+            Some(b'+') => {
+                commands.position += 1;
+                false
+            }
+            Some(b'-') => {
+                commands.position += 1;
+                true
+            }
+            _ => false,
+        };
+
+        let value = if commands.accept(b'=') {
+            let variable = commands.variable()?;
+            let value = (self.variables)(&variable)?;
+            if value.is_string() {
+                return Err(Error::TypeError("Type mismatch".to_string()));
+            }
+            Some(value.as_double()?.round() as i32)
+        } else {
+            let mut digits: Option<i32> = None;
+            while let Some(digit @ b'0'..=b'9') = commands.peek() {
+                commands.position += 1;
+                let value = digits.unwrap_or(0);
+                digits = Some(
+                    value
+                        .checked_mul(10)
+                        .and_then(|value| value.checked_add((digit - b'0') as i32))
+                        .ok_or_else(illegal_function_call)?,
+                );
+            }
+            digits
+        };
+        Ok(value.map(|value| if negative { -value } else { value }))
+    }
+
+    fn required_number(&mut self, commands: &mut Commands) -> Result<i32> {
+        self.number(commands)?.ok_or_else(illegal_function_call)
+    }
+
+    /// Move the pen by (dx, dy) units, rotated and scaled
+    fn move_by(&mut self, dx: f64, dy: f64, blank: bool, no_update: bool) -> Result<()> {
+        let scale = self.state.scale as f64 / 4.0;
+        let (sin, cos) = (self.state.angle as f64).to_radians().sin_cos();
+        // Screen y grows downwards, so counterclockwise turns use -sin
+        let (x, y) = self.screen.physical_point();
+        let x2 = x + (dx * cos + dy * sin) * scale;
+        let y2 = y + (dy * cos - dx * sin) * scale;
+        self.move_to(x2, y2, blank, no_update)
+    }
+
+    /// Move the pen to a physical point. DRAW works in physical
+    /// coordinates whatever WINDOW is in effect.
+    fn move_to(&mut self, x: f64, y: f64, blank: bool, no_update: bool) -> Result<()> {
+        let start = self.screen.physical_point();
+        let (x1, y1) = self.screen.last_point();
+        let (x2, y2) = self.screen.set_physical_point(x, y);
+        if !blank {
+            self.screen.line(x1, y1, x2, y2, self.state.color, 0xFFFF)?;
+        }
+        if no_update {
+            self.screen.set_physical_point(start.0, start.1);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics_backend::AsciiBackend;
+
+    fn no_variables(_: &DrawVariable) -> Result<Value> {
+        Err(Error::RuntimeError("no variables".to_string()))
+    }
+
+    #[test]
+    fn test_moves_and_prefixes() {
+        let mut screen = Screen::new(20, 10);
+        draw(&mut screen, "BM2,2 R3 D2 NL2 BU1 E1", &mut no_variables).unwrap();
+        assert_eq!(screen.last_point(), (6, 2));
+        assert_eq!(screen.point(2, 2), Some(7));
+        assert_eq!(screen.point(5, 4), Some(7));
+        assert_eq!(screen.point(3, 4), Some(7));
+        assert_eq!(screen.point(5, 3), Some(7));
+        assert_eq!(screen.point(1, 2), Some(0));
+    }
+
+    #[test]
+    fn test_rotation_scale_and_colour() {
+        let mut screen = Screen::new(20, 10);
+        draw(&mut screen, "BM10,5 A1 S8 C3 R2", &mut no_variables).unwrap();
+        assert_eq!(screen.last_point(), (10, 1));
+        assert_eq!(screen.point(10, 3), Some(3));
+
+        // The pen settings carry over to the next DRAW
+        draw(&mut screen, "TA0 L1", &mut no_variables).unwrap();
+        assert_eq!(screen.last_point(), (8, 1));
+        assert_eq!(screen.point(9, 1), Some(3));
+    }
+
+    #[test]
+    fn test_variables_and_substrings() {
+        let mut screen = Screen::new(20, 10);
+        let mut variables = |variable: &DrawVariable| match variable {
+            DrawVariable::Name(name) if name == "N%" => Ok(Value::Integer(4)),
+            DrawVariable::Pointer([3, 1, 0]) => Ok(Value::String("R=N%;".to_string())),
+            _ => Err(Error::UndefinedError("Variable not defined".to_string())),
+        };
+        draw(&mut screen, "BM0,0 X\u{3}\u{1}\u{0} D=N%;", &mut variables).unwrap();
+        assert_eq!(screen.last_point(), (4, 4));
+        assert!(draw(&mut screen, "Q", &mut variables).is_err());
+        assert!(draw(&mut screen, "A4", &mut variables).is_err());
+    }
+
+    #[test]
+    fn test_paint_command() {
+        let mut screen = Screen::new_with_backend(Box::new(AsciiBackend::new(10, 10)));
+        draw(&mut screen, "C2 BM1,1 R4 D4 L4 U4 BF1 P1,2", &mut no_variables).unwrap();
+        assert_eq!(screen.point(3, 3), Some(1));
+        assert_eq!(screen.point(7, 7), Some(0));
+    }
+}
@@ -1,5 +1,6 @@
 //! Error types for the GW-BASIC interpreter
 
+use crate::diagnostic::Span;
 use std::fmt;
 
 /// Result type alias for GW-BASIC operations
@@ -34,6 +35,35 @@ pub enum Error {
     
     /// Program termination (END statement)
     ProgramEnd,
+
+    /// Another error together with where in the source it happened
+    Located(Box<Error>, Span),
+}
+
+impl Error {
+    /// Attach a location, unless the error already has one
+    pub fn at(self, span: Span) -> Error {
+        match self {
+            Error::Located(..) | Error::ProgramEnd => self,
+            _ => Error::Located(Box::new(self), span),
+        }
+    }
+
+    /// Where the error happened, if known
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Error::Located(_, span) => Some(span),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without its location
+    pub fn without_span(&self) -> &Error {
+        match self {
+            Error::Located(error, _) => error.without_span(),
+            _ => self,
+        }
+    }
 }
 
 impl fmt::Display for Error {
@@ -48,6 +78,7 @@ impl fmt::Display for Error {
             Error::IoError(msg) => write!(f, "I/O error: {}", msg),
             Error::LineNumberError(msg) => write!(f, "Line number error: {}", msg),
             Error::ProgramEnd => write!(f, "Program ended"),
+            Error::Located(error, _) => write!(f, "{}", error),
         }
     }
 }
@@ -69,4 +100,13 @@ mod tests {
         let err = Error::DivisionByZero;
         assert_eq!(err.to_string(), "Division by zero");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_located_error() {
+        let span = Span { line: 1, line_number: Some(10), statement: 0, start_column: 4, end_column: 9 };
+        let err = Error::SyntaxError("unexpected token".to_string()).at(span);
+        assert_eq!(err.to_string(), "Syntax error: unexpected token");
+        assert_eq!(err.span(), Some(&span));
+        assert_eq!(err.without_span(), &Error::SyntaxError("unexpected token".to_string()));
+    }
+}
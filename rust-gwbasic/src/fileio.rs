@@ -58,6 +58,7 @@ impl FileManager {
                 .read(true)
                 .write(true)
                 .create(true)
+                .truncate(false)
                 .open(path)
                 .map_err(|e| Error::IoError(format!("Cannot open random file: {}", e)))?,
         };
@@ -123,9 +124,14 @@ impl FileManager {
     }
 
     pub fn write_line(&mut self, file_num: i32, data: &str) -> Result<()> {
+        self.write(file_num, &format!("{}\n", data))
+    }
+
+    /// Write text without ending the line
+    pub fn write(&mut self, file_num: i32, data: &str) -> Result<()> {
         if let Some(handle) = self.handles.get_mut(&file_num) {
             if let Some(ref mut writer) = handle.writer {
-                writeln!(writer, "{}", data)
+                write!(writer, "{}", data)
                     .map_err(|e| Error::IoError(format!("Error writing to file: {}", e)))?;
                 Ok(())
             } else {
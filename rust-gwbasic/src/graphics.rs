@@ -1,49 +1,708 @@
 //! Graphics module for GW-BASIC
 
-use crate::error::Result;
-use crate::graphics_backend::{GraphicsBackend, AsciiBackend};
+use crate::draw::DrawState;
+use crate::error::{Error, Result};
+use crate::graphics_backend::{ega_rgb, GraphicsBackend, AsciiBackend, CircleShape, Fill, FramebufferBackend, CGA_RGB};
+use crate::snapshot::Snapshot;
+use crate::viewport::{Clipped, Viewport};
+use crate::text_screen::TextScreen;
+
+/// How pixels are packed into bytes, in PAINT tiles and GET/PUT arrays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelLayout {
+    /// Bits per pixel, packed into each byte from the top bit down
+    pub bits_per_pixel: u8,
+
+    /// Colour planes, each holding one bit of every pixel
+    pub planes: u8,
+}
+
+impl PixelLayout {
+    /// Largest colour a pixel can hold
+    pub fn mask(&self) -> u8 {
+        ((1u16 << (self.bits_per_pixel * self.planes.max(1))) - 1) as u8
+    }
+
+    /// Bytes per plane for a row of `width` pixels
+    pub fn plane_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel as usize).div_ceil(8)
+    }
+
+    /// Colours of a row of `width` pixels, stored plane after plane
+    pub fn decode_row(&self, bytes: &[u8], width: usize) -> Vec<u8> {
+        let plane_bytes = self.plane_bytes(width);
+        let byte = |index: usize| bytes.get(index).copied().unwrap_or(0);
+        (0..width)
+            .map(|i| {
+                if self.planes <= 1 {
+                    let bits = self.bits_per_pixel as usize;
+                    let offset = i * bits;
+                    (byte(offset / 8) >> (8 - bits - offset % 8)) & self.mask()
+                } else {
+                    // Bit i of plane p is bit p of pixel i's colour
+                    (0..self.planes as usize)
+                        .map(|p| ((byte(p * plane_bytes + i / 8) >> (7 - i % 8)) & 1) << p)
+                        .sum()
+                }
+            })
+            .collect()
+    }
+
+    /// Bytes for a row of pixel colours, the inverse of `decode_row`
+    pub fn encode_row(&self, pixels: &[u8]) -> Vec<u8> {
+        let plane_bytes = self.plane_bytes(pixels.len());
+        let mut bytes = vec![0; plane_bytes * self.planes.max(1) as usize];
+        for (i, &color) in pixels.iter().enumerate() {
+            if self.planes <= 1 {
+                let bits = self.bits_per_pixel as usize;
+                let offset = i * bits;
+                bytes[offset / 8] |= (color & self.mask()) << (8 - bits - offset % 8);
+            } else {
+                for p in 0..self.planes as usize {
+                    bytes[p * plane_bytes + i / 8] |= ((color >> p) & 1) << (7 - i % 8);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Rows of pixel colours described by a tile string, each row being
+    /// one byte per plane
+    pub fn decode_tile(&self, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let width = 8 / self.bits_per_pixel as usize;
+        bytes
+            .chunks(self.planes.max(1) as usize)
+            .map(|row| self.decode_row(row, width))
+            .collect()
+    }
+}
+
+/// Geometry and colours of a SCREEN mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScreenMode {
+    pub number: u8,
+
+    /// Graphics resolution in pixels, or None for the text-only mode 0
+    pub resolution: Option<(usize, usize)>,
+
+    pub text_columns: usize,
+    pub text_rows: usize,
+
+    /// Attributes, that is colours on the screen at once
+    pub attributes: u16,
+
+    /// Colours an attribute can be given with PALETTE
+    pub colors: u16,
+
+    /// Colour of each attribute after SCREEN or a bare PALETTE
+    pub default_palette: &'static [u8],
+
+    /// Attribute that text and graphics are drawn in until COLOR
+    pub foreground: u8,
+
+    /// Screen pages in video memory
+    pub pages: u8,
+
+    pub layout: PixelLayout,
+}
+
+const EGA_DEFAULT_PALETTE: [u8; 16] = [0, 1, 2, 3, 4, 5, 20, 7, 56, 57, 58, 59, 60, 61, 62, 63];
+const CGA_DEFAULT_PALETTE: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const MONO: PixelLayout = PixelLayout { bits_per_pixel: 1, planes: 1 };
+const EGA_PLANES: PixelLayout = PixelLayout { bits_per_pixel: 1, planes: 4 };
+
+/// The SCREEN modes of GW-BASIC on a CGA or EGA adapter
+pub const SCREEN_MODES: [ScreenMode; 7] = [
+    ScreenMode {
+        number: 0,
+        resolution: None,
+        text_columns: 80,
+        text_rows: 25,
+        attributes: 16,
+        colors: 16,
+        default_palette: &CGA_DEFAULT_PALETTE,
+        foreground: 7,
+        pages: 4,
+        layout: MONO,
+    },
+    ScreenMode {
+        number: 1,
+        resolution: Some((320, 200)),
+        text_columns: 40,
+        text_rows: 25,
+        attributes: 4,
+        colors: 16,
+        // Palette 1: black, cyan, magenta and white
+        default_palette: &[0, 3, 5, 7],
+        foreground: 3,
+        pages: 1,
+        layout: PixelLayout { bits_per_pixel: 2, planes: 1 },
+    },
+    ScreenMode {
+        number: 2,
+        resolution: Some((640, 200)),
+        text_columns: 80,
+        text_rows: 25,
+        attributes: 2,
+        colors: 16,
+        default_palette: &[0, 15],
+        foreground: 1,
+        pages: 1,
+        layout: MONO,
+    },
+    ScreenMode {
+        number: 7,
+        resolution: Some((320, 200)),
+        text_columns: 40,
+        text_rows: 25,
+        attributes: 16,
+        colors: 16,
+        default_palette: &CGA_DEFAULT_PALETTE,
+        foreground: 15,
+        pages: 8,
+        layout: EGA_PLANES,
+    },
+    ScreenMode {
+        number: 8,
+        resolution: Some((640, 200)),
+        text_columns: 80,
+        text_rows: 25,
+        attributes: 16,
+        colors: 16,
+        default_palette: &CGA_DEFAULT_PALETTE,
+        foreground: 15,
+        pages: 4,
+        layout: EGA_PLANES,
+    },
+    ScreenMode {
+        number: 9,
+        resolution: Some((640, 350)),
+        text_columns: 80,
+        text_rows: 25,
+        attributes: 16,
+        colors: 64,
+        default_palette: &EGA_DEFAULT_PALETTE,
+        foreground: 15,
+        pages: 2,
+        layout: EGA_PLANES,
+    },
+    ScreenMode {
+        number: 10,
+        resolution: Some((640, 350)),
+        text_columns: 80,
+        text_rows: 25,
+        attributes: 4,
+        colors: 9,
+        // Black, video, blinking video and intensified video
+        default_palette: &[0, 3, 6, 8],
+        foreground: 3,
+        pages: 2,
+        layout: PixelLayout { bits_per_pixel: 1, planes: 2 },
+    },
+];
+
+impl ScreenMode {
+    /// The mode for SCREEN n, or "Illegal function call" for modes this
+    /// adapter does not have
+    pub fn get(number: i32) -> Result<&'static ScreenMode> {
+        SCREEN_MODES
+            .iter()
+            .find(|mode| mode.number as i32 == number)
+            .ok_or_else(|| Error::RuntimeError("Illegal function call".to_string()))
+    }
+
+    /// Size of the drawing surface: the pixels of a graphics mode, or the
+    /// character cells of text mode
+    pub fn size(&self) -> (usize, usize) {
+        self.resolution.unwrap_or((self.text_columns, self.text_rows))
+    }
+
+    /// RGB value of a colour that attributes can be set to
+    pub fn rgb(&self, color: u8) -> u32 {
+        // The monochrome colours of SCREEN 10, showing blinking ones steady
+        const MONOCHROME: [u32; 9] = [
+            0x000000, 0x000000, 0x000000, 0xAAAAAA, 0xAAAAAA, 0xAAAAAA, 0xAAAAAA, 0xFFFFFF, 0xFFFFFF,
+        ];
+        match self.colors {
+            64 => ega_rgb(color),
+            9 => MONOCHROME[color as usize % 9],
+            _ => CGA_RGB[color as usize % 16],
+        }
+    }
+
+    /// CIRCLE aspect ratio that looks round on a 4:3 display
+    pub fn aspect(&self) -> f64 {
+        let (width, height) = self.size();
+        4.0 * height as f64 / (3.0 * width as f64)
+    }
+}
+
+/// How PUT combines an image with the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PutAction {
+    Pset,
+    Preset,
+    And,
+    Or,
+    Xor,
+}
 
 /// Screen manager that wraps a graphics backend
 pub struct Screen {
     backend: Box<dyn GraphicsBackend>,
+
+    /// Last point referenced by a graphics statement, in logical
+    /// coordinates, used by STEP and by LINE without a start point
+    last_point: (f64, f64),
+
+    /// VIEW and WINDOW settings
+    viewport: Viewport,
+
+    /// Packing of pixels into bytes in the current screen mode
+    pixel_layout: PixelLayout,
+
+    /// Scale, angle and colour of the DRAW pen
+    draw_state: DrawState,
+
+    /// The SCREEN mode
+    mode: ScreenMode,
+
+    /// Colour of each attribute
+    palette: Vec<u8>,
+
+    /// Attribute drawn in when a statement gives no colour
+    foreground: u8,
+
+    /// Video pages. The backend shows the visual page, so the copy kept
+    /// here for that page is stale until another page is shown.
+    pages: Vec<FramebufferBackend>,
+
+    /// Page that graphics are drawn on
+    active_page: usize,
+
+    /// Page on display
+    visual_page: usize,
 }
 
 impl Screen {
     pub fn new(width: usize, height: usize) -> Self {
+        Self::new_with_backend(Box::new(AsciiBackend::new(width, height)))
+    }
+
+    pub fn new_with_backend(backend: Box<dyn GraphicsBackend>) -> Self {
+        // Graphics start out at the centre of the screen
+        let (height, width) = backend.get_size();
+        let viewport = Viewport::full(width, height);
+        let mode = SCREEN_MODES[0];
         Screen {
-            backend: Box::new(AsciiBackend::new(width, height)),
+            backend,
+            last_point: viewport.centre(),
+            viewport,
+            pixel_layout: mode.layout,
+            draw_state: DrawState::default(),
+            mode,
+            palette: mode.default_palette.to_vec(),
+            foreground: mode.foreground,
+            pages: (0..mode.pages).map(|_| FramebufferBackend::new(width, height)).collect(),
+            active_page: 0,
+            visual_page: 0,
         }
     }
 
-    pub fn new_with_backend(backend: Box<dyn GraphicsBackend>) -> Self {
-        Screen { backend }
+    /// A cleared screen in the given mode, drawn by the backend
+    pub fn with_mode(mode: &ScreenMode, backend: Box<dyn GraphicsBackend>) -> Self {
+        let mut screen = Self::new_with_backend(backend);
+        let (height, width) = screen.backend.get_size();
+        screen.pixel_layout = mode.layout;
+        screen.mode = *mode;
+        screen.foreground = mode.foreground;
+        screen.pages = (0..mode.pages).map(|_| FramebufferBackend::new(width, height)).collect();
+        screen.reset_palette();
+        screen
+    }
+
+    /// Colour of each attribute
+    pub fn palette(&self) -> &[u8] {
+        &self.palette
+    }
+
+    /// PALETTE attribute, colour. A colour of -1 leaves the attribute as
+    /// it is.
+    pub fn set_palette(&mut self, attribute: i32, color: i32) -> Result<()> {
+        if !(0..self.mode.attributes as i32).contains(&attribute) || !(-1..self.mode.colors as i32).contains(&color) {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        if color >= 0 {
+            self.palette[attribute as usize] = color as u8;
+            self.show_palette();
+        }
+        Ok(())
+    }
+
+    /// PALETTE USING: the colour of every attribute, -1 for no change
+    pub fn set_palette_using(&mut self, colors: &[i32]) -> Result<()> {
+        let attributes = self.mode.attributes as usize;
+        if colors.len() < attributes || colors[..attributes].iter().any(|&c| !(-1..self.mode.colors as i32).contains(&c)) {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        for (entry, &color) in self.palette.iter_mut().zip(colors) {
+            if color >= 0 {
+                *entry = color as u8;
+            }
+        }
+        self.show_palette();
+        Ok(())
+    }
+
+    /// PALETTE with no arguments: the default colours of the mode
+    pub fn reset_palette(&mut self) {
+        self.palette = self.mode.default_palette.to_vec();
+        self.show_palette();
+    }
+
+    /// COLOR background, palette in SCREEN 1: the background colour and
+    /// one of the two CGA palettes (0: green, red, brown; 1: cyan, magenta,
+    /// white)
+    pub fn set_cga_colors(&mut self, background: Option<i32>, palette: Option<i32>) -> Result<()> {
+        if let Some(background) = background {
+            if !(0..16).contains(&background) {
+                return Err(Error::RuntimeError("Illegal function call".to_string()));
+            }
+            self.palette[0] = background as u8;
+        }
+        if let Some(palette) = palette {
+            let colors = if palette % 2 == 0 { [2, 4, 6] } else { [3, 5, 7] };
+            self.palette[1..4].copy_from_slice(&colors);
+        }
+        self.show_palette();
+        Ok(())
+    }
+
+    /// Pass the colours of the attributes on to the backend
+    fn show_palette(&mut self) {
+        let colors: Vec<u32> = self.palette.iter().map(|&color| self.mode.rgb(color)).collect();
+        self.backend.set_palette(&colors);
+    }
+
+    pub fn mode(&self) -> &ScreenMode {
+        &self.mode
     }
 
+    /// Clear the active page
     pub fn cls(&mut self) {
-        self.backend.cls();
+        self.target().cls();
+    }
+
+    /// Where graphics are drawn: the backend when the active page is on
+    /// display, otherwise the page in memory
+    fn target(&mut self) -> &mut dyn GraphicsBackend {
+        if self.active_page == self.visual_page {
+            self.backend.as_mut()
+        } else {
+            &mut self.pages[self.active_page]
+        }
+    }
+
+    /// The active page, for reading pixels back
+    fn active(&self) -> &dyn GraphicsBackend {
+        if self.active_page == self.visual_page {
+            self.backend.as_ref()
+        } else {
+            &self.pages[self.active_page]
+        }
+    }
+
+    /// Select the pages to draw on and to show (SCREEN ,,apage,vpage)
+    pub fn set_pages(&mut self, active: Option<usize>, visual: Option<usize>) -> Result<()> {
+        let active = active.unwrap_or(self.active_page);
+        let visual = visual.unwrap_or(self.visual_page);
+        if active >= self.pages.len() || visual >= self.pages.len() {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        if visual != self.visual_page {
+            // Keep what was on display and put the new page on the backend
+            self.backend.save_page(self.visual_page, &mut self.pages[self.visual_page])?;
+            self.backend.load_page(visual, &self.pages[visual])?;
+            self.visual_page = visual;
+        }
+        self.active_page = active;
+        Ok(())
+    }
+
+    /// The active and visual page numbers
+    pub fn pages(&self) -> (usize, usize) {
+        (self.active_page, self.visual_page)
+    }
+
+    /// PCOPY: copy one page over another
+    pub fn copy_page(&mut self, from: usize, to: usize) -> Result<()> {
+        if from >= self.pages.len() || to >= self.pages.len() {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        if from == to {
+            return Ok(());
+        }
+        if from == self.visual_page {
+            self.backend.save_page(to, &mut self.pages[to])
+        } else if to == self.visual_page {
+            self.backend.load_page(from, &self.pages[from])
+        } else {
+            self.pages[to] = self.pages[from].clone();
+            Ok(())
+        }
     }
 
     pub fn locate(&mut self, row: usize, col: usize) -> Result<()> {
         self.backend.locate(row, col)
     }
 
+    /// COLOR foreground, background: the foreground is also the attribute
+    /// graphics are drawn in when no colour is given
     pub fn color(&mut self, fg: Option<u8>, bg: Option<u8>) {
+        if let Some(fg) = fg {
+            self.foreground = fg;
+        }
         self.backend.color(fg, bg);
     }
 
+    /// Attribute drawn in when a statement gives no colour
+    pub fn foreground(&self) -> u8 {
+        self.foreground
+    }
+
+    /// A colour argument as an attribute of the mode, or "Illegal function
+    /// call" for one the mode does not have
+    pub fn attribute(&self, color: i32) -> Result<u8> {
+        if (0..self.mode.attributes as i32).contains(&color) {
+            Ok(color as u8)
+        } else {
+            Err(Error::RuntimeError("Illegal function call".to_string()))
+        }
+    }
+
+    /// The backend, clipped to the viewport
+    fn clipped(&mut self) -> Clipped<'_> {
+        let viewport = self.viewport;
+        Clipped {
+            backend: self.target(),
+            viewport,
+        }
+    }
+
     pub fn pset(&mut self, x: i32, y: i32, color: Option<u8>) -> Result<()> {
-        let c = color.unwrap_or(7); // Default to white if not specified
-        self.backend.pset(x, y, c)
+        let c = color.unwrap_or(self.foreground);
+        self.clipped().pset(x, y, c)
+    }
+
+    /// Resolve a logical coordinate pair, relative to the last point for
+    /// STEP, make it the last point and return its screen pixel
+    pub fn resolve(&mut self, x: f64, y: f64, step: bool) -> (i32, i32) {
+        let (x, y) = if step {
+            (self.last_point.0 + x, self.last_point.1 + y)
+        } else {
+            (x, y)
+        };
+        self.last_point = (x, y);
+        self.last_point()
+    }
+
+    /// The screen pixel of the last point referenced
+    pub fn last_point(&self) -> (i32, i32) {
+        let (x, y) = self.physical_point();
+        self.viewport.device(x, y)
+    }
+
+    /// The last point referenced, in physical coordinates
+    pub fn physical_point(&self) -> (f64, f64) {
+        let (x, y) = self.last_point;
+        (self.viewport.physical_x(x), self.viewport.physical_y(y))
+    }
+
+    /// Make a physical point the last point and return its screen pixel
+    pub fn set_physical_point(&mut self, x: f64, y: f64) -> (i32, i32) {
+        self.last_point = (self.viewport.logical_x(x), self.viewport.logical_y(y));
+        self.viewport.device(x, y)
+    }
+
+    /// Set the viewport (VIEW), or reset it to the whole screen. The fill
+    /// colour paints the viewport and the border colour draws a box just
+    /// outside it.
+    pub fn set_view(&mut self, corners: Option<((i32, i32), (i32, i32))>, screen: bool, fill: Option<u8>, border: Option<u8>) -> Result<()> {
+        let (height, width) = self.backend.get_size();
+        self.viewport.set_view(corners, screen, (width, height))?;
+        let (left, top, right, bottom) = self.viewport.clip();
+        if let Some(border) = border {
+            self.target().rect(left - 1, top - 1, right + 1, bottom + 1, border, 0xFFFF)?;
+        }
+        if let Some(fill) = fill {
+            self.target().fill_rect(left, top, right, bottom, fill)?;
+        }
+        self.last_point = self.viewport.centre();
+        Ok(())
+    }
+
+    /// Set the logical coordinates of the viewport (WINDOW), or go back to
+    /// physical coordinates
+    pub fn set_window(&mut self, corners: Option<((f64, f64), (f64, f64))>, screen: bool) -> Result<()> {
+        self.viewport.set_window(corners, screen)?;
+        self.last_point = self.viewport.centre();
+        Ok(())
+    }
+
+    /// PMAP: convert a logical x (0) or y (1) to physical, or a physical x
+    /// (2) or y (3) to logical
+    pub fn pmap(&self, value: f64, function: i32) -> Result<f64> {
+        match function {
+            0 => Ok(self.viewport.physical_x(value).round()),
+            1 => Ok(self.viewport.physical_y(value).round()),
+            2 => Ok(self.viewport.logical_x(value)),
+            3 => Ok(self.viewport.logical_y(value)),
+            _ => Err(Error::RuntimeError("Illegal function call".to_string())),
+        }
+    }
+
+    /// POINT(x, y): the colour at a logical point, or -1 outside the
+    /// viewport
+    pub fn point_logical(&self, x: f64, y: f64) -> i32 {
+        let (x, y) = self.viewport.device(self.viewport.physical_x(x), self.viewport.physical_y(y));
+        if !self.viewport.contains(x, y) {
+            return -1;
+        }
+        self.point(x, y).map_or(-1, i32::from)
+    }
+
+    /// POINT(n): the physical x (0) or y (1), or the logical x (2) or y
+    /// (3), of the last point
+    pub fn last_point_coordinate(&self, function: i32) -> Result<f64> {
+        let (x, y) = self.physical_point();
+        match function {
+            0 => Ok(x.round()),
+            1 => Ok(y.round()),
+            2 => Ok(self.last_point.0),
+            3 => Ok(self.last_point.1),
+            _ => Err(Error::RuntimeError("Illegal function call".to_string())),
+        }
+    }
+
+    /// Colour of a pixel, or None outside the screen
+    pub fn point(&self, x: i32, y: i32) -> Option<u8> {
+        self.active().point(x, y)
+    }
+
+    pub fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Option<u8>, style: u16) -> Result<()> {
+        let c = color.unwrap_or(self.foreground);
+        self.clipped().line(x1, y1, x2, y2, c, style)
+    }
+
+    /// Draw a box (LINE ... ,B)
+    pub fn rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Option<u8>, style: u16) -> Result<()> {
+        let c = color.unwrap_or(self.foreground);
+        self.clipped().rect(x1, y1, x2, y2, c, style)
+    }
+
+    /// Draw a filled box (LINE ... ,BF)
+    pub fn fill_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Option<u8>) -> Result<()> {
+        let c = color.unwrap_or(self.foreground);
+        self.clipped().fill_rect(x1, y1, x2, y2, c)
+    }
+
+    /// Draw a circle whose radius is in logical units along x
+    pub fn circle(&mut self, x: i32, y: i32, radius: f64, color: Option<u8>, shape: CircleShape) -> Result<()> {
+        let c = color.unwrap_or(self.foreground);
+        let radius = (radius * self.viewport.scale().0).abs().round() as i32;
+        self.clipped().circle(x, y, radius, c, shape)
+    }
+
+    pub fn set_pixel_layout(&mut self, layout: PixelLayout) {
+        self.pixel_layout = layout;
+    }
+
+    /// Fill for a PAINT tile string (1 to 64 bytes) and optional
+    /// background tile row
+    pub fn tile(&self, tile: &[u8], background: Option<&[u8]>) -> Result<Fill> {
+        if tile.is_empty() || tile.len() > 64 || background.is_some_and(|b| b.is_empty()) {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        Ok(Fill::Tile {
+            rows: self.pixel_layout.decode_tile(tile),
+            background: background.map(|b| self.pixel_layout.decode_tile(b).remove(0)),
+        })
+    }
+
+    /// Pixels of a rectangle in the GW-BASIC array layout used by GET: the
+    /// width in bits and the height as 16-bit words, then each row packed
+    /// plane after plane
+    pub fn get_image(&self, x1: i32, y1: i32, x2: i32, y2: i32) -> Result<Vec<u8>> {
+        let (left, right) = (x1.min(x2), x1.max(x2));
+        let (top, bottom) = (y1.min(y2), y1.max(y2));
+        if self.point(left, top).is_none() || self.point(right, bottom).is_none() {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        let width = (right - left + 1) as usize;
+        let height = (bottom - top + 1) as usize;
+        let width_bits = (width * self.pixel_layout.bits_per_pixel as usize) as u16;
+
+        let mut image = Vec::new();
+        image.extend_from_slice(&width_bits.to_le_bytes());
+        image.extend_from_slice(&(height as u16).to_le_bytes());
+        for y in top..=bottom {
+            let row: Vec<u8> = (left..=right).map(|x| self.point(x, y).unwrap_or(0)).collect();
+            image.extend(self.pixel_layout.encode_row(&row));
+        }
+        Ok(image)
+    }
+
+    /// Draw an image stored by GET with its top left corner at (x, y)
+    pub fn put_image(&mut self, x: i32, y: i32, image: &[u8], action: PutAction) -> Result<()> {
+        let illegal = || Error::RuntimeError("Illegal function call".to_string());
+        if image.len() < 4 {
+            return Err(illegal());
+        }
+        let layout = self.pixel_layout;
+        let width = u16::from_le_bytes([image[0], image[1]]) as usize / layout.bits_per_pixel as usize;
+        let height = u16::from_le_bytes([image[2], image[3]]) as usize;
+        let row_bytes = layout.plane_bytes(width) * layout.planes.max(1) as usize;
+        let data = &image[4..];
+        if data.len() < row_bytes * height {
+            return Err(illegal());
+        }
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+        if self.point(x, y).is_none() || self.point(right, bottom).is_none() {
+            return Err(illegal());
+        }
+
+        for (row, bytes) in data.chunks(row_bytes).take(height).enumerate() {
+            let py = y + row as i32;
+            for (column, color) in layout.decode_row(bytes, width).into_iter().enumerate() {
+                let px = x + column as i32;
+                let old = self.point(px, py).unwrap_or(0);
+                let new = match action {
+                    PutAction::Pset => color,
+                    PutAction::Preset => !color & layout.mask(),
+                    PutAction::And => old & color,
+                    PutAction::Or => old | color,
+                    PutAction::Xor => old ^ color,
+                };
+                self.clipped().pset(px, py, new)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: Option<u8>) -> Result<()> {
-        let c = color.unwrap_or(7);
-        self.backend.line(x1, y1, x2, y2, c)
+    /// Flood fill from (x, y) up to the border colour
+    pub fn paint(&mut self, x: i32, y: i32, fill: &Fill, border: u8) -> Result<()> {
+        self.clipped().paint(x, y, fill, border)
     }
 
-    pub fn circle(&mut self, x: i32, y: i32, radius: i32, color: Option<u8>) -> Result<()> {
-        let c = color.unwrap_or(7);
-        self.backend.circle(x, y, radius, c)
+    pub fn draw_state(&self) -> DrawState {
+        self.draw_state
+    }
+
+    pub fn set_draw_state(&mut self, state: DrawState) {
+        self.draw_state = state;
     }
 
     pub fn get_cursor(&self) -> (usize, usize) {
@@ -54,10 +713,32 @@ impl Screen {
         self.backend.get_size()
     }
 
+    /// The colours of the visual page, through the current palette
+    pub fn snapshot(&self) -> Snapshot {
+        let (height, width) = self.backend.get_size();
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let attribute = self.backend.point(x, y).unwrap_or(0) as usize;
+                pixels.push(self.mode.rgb(self.palette[attribute % self.palette.len()]));
+            }
+        }
+        Snapshot { width, height, pixels }
+    }
+
     pub fn display(&mut self) {
         self.backend.display();
     }
 
+    /// Whether the backend shows the text screen itself
+    pub fn shows_text(&self) -> bool {
+        self.backend.shows_text()
+    }
+
+    pub fn show_text(&mut self, text: &TextScreen) {
+        self.backend.show_text(text);
+    }
+
     pub fn should_close(&self) -> bool {
         self.backend.should_close()
     }
@@ -94,6 +775,140 @@ mod tests {
         assert_eq!(row, 0);
     }
 
+    #[test]
+    fn test_resolve_step() {
+        let mut screen = Screen::new(80, 25);
+        assert_eq!(screen.last_point(), (40, 12));
+        assert_eq!(screen.resolve(10.0, 5.0, false), (10, 5));
+        assert_eq!(screen.resolve(-3.0, 2.4, true), (7, 7));
+        assert_eq!(screen.last_point(), (7, 7));
+    }
+
+    #[test]
+    fn test_pixel_layout() {
+        let packed = PixelLayout { bits_per_pixel: 2, planes: 1 };
+        assert_eq!(packed.decode_tile(&[0b11_10_01_00]), vec![vec![3, 2, 1, 0]]);
+
+        let mono = PixelLayout { bits_per_pixel: 1, planes: 1 };
+        assert_eq!(mono.decode_tile(&[0xAA, 0x55])[1], vec![0, 1, 0, 1, 0, 1, 0, 1]);
+
+        let planar = PixelLayout { bits_per_pixel: 1, planes: 4 };
+        assert_eq!(planar.decode_tile(&[0x80, 0x80, 0x00, 0x81])[0], vec![11, 0, 0, 0, 0, 0, 0, 8]);
+        assert_eq!(planar.encode_row(&[11, 0, 0, 0, 0, 0, 0, 8]), vec![0x80, 0x80, 0x00, 0x81]);
+    }
+
+    #[test]
+    fn test_get_put_image() {
+        let mut screen = Screen::new(20, 10);
+        screen.set_pixel_layout(PixelLayout { bits_per_pixel: 2, planes: 1 });
+        screen.pset(1, 1, Some(3)).unwrap();
+        screen.pset(5, 2, Some(2)).unwrap();
+        let image = screen.get_image(1, 1, 5, 2).unwrap();
+        assert_eq!(image, vec![10, 0, 2, 0, 0b1100_0000, 0, 0, 0b1000_0000]);
+
+        screen.put_image(10, 5, &image, PutAction::Pset).unwrap();
+        assert_eq!(screen.point(10, 5), Some(3));
+        assert_eq!(screen.point(14, 6), Some(2));
+        screen.put_image(10, 5, &image, PutAction::Xor).unwrap();
+        assert_eq!(screen.point(10, 5), Some(0));
+        screen.put_image(10, 5, &image, PutAction::Preset).unwrap();
+        assert_eq!(screen.point(10, 5), Some(0));
+        assert_eq!(screen.point(11, 5), Some(3));
+
+        assert!(screen.put_image(18, 5, &image, PutAction::Pset).is_err());
+        assert!(screen.get_image(0, 0, 20, 0).is_err());
+    }
+
+    #[test]
+    fn test_view_clips_drawing() {
+        let mut screen = Screen::new(40, 20);
+        screen.set_view(Some(((10, 5), (19, 9))), false, None, Some(2)).unwrap();
+        let (x1, y1) = screen.resolve(-5.0, 2.0, false);
+        let (x2, y2) = screen.resolve(50.0, 2.0, false);
+        assert_eq!((x1, y1), (5, 7));
+        screen.line(x1, y1, x2, y2, Some(3), 0xFFFF).unwrap();
+        assert_eq!(screen.point(10, 7), Some(3));
+        assert_eq!(screen.point(19, 7), Some(3));
+        assert_eq!(screen.point(9, 7), Some(2));
+        assert_eq!(screen.point(21, 7), Some(0));
+
+        screen.set_window(Some(((0.0, 0.0), (9.0, 4.0))), false).unwrap();
+        assert_eq!(screen.resolve(0.0, 0.0, false), (10, 9));
+        assert_eq!(screen.pmap(4.0, 1).unwrap(), 0.0);
+        assert_eq!(screen.point_logical(0.0, 2.0), 3);
+        assert_eq!(screen.point_logical(-1.0, 2.0), -1);
+        assert_eq!(screen.last_point_coordinate(1).unwrap(), 4.0);
+        assert_eq!(screen.last_point_coordinate(3).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_screen_modes() {
+        let mode = ScreenMode::get(1).unwrap();
+        assert_eq!(mode.size(), (320, 200));
+        assert_eq!(mode.text_columns, 40);
+        assert_eq!(mode.default_palette.len(), mode.attributes as usize);
+        assert!((ScreenMode::get(9).unwrap().aspect() - 0.729).abs() < 0.001);
+        assert_eq!(ScreenMode::get(0).unwrap().size(), (80, 25));
+        assert!(ScreenMode::get(3).is_err());
+        assert!(ScreenMode::get(13).is_err());
+
+        let screen = Screen::with_mode(mode, Box::new(AsciiBackend::new(320, 200)));
+        assert_eq!(screen.mode().number, 1);
+        assert_eq!(screen.last_point(), (160, 100));
+    }
+
+    #[test]
+    fn test_pages() {
+        let mode = ScreenMode::get(7).unwrap();
+        let mut screen = Screen::with_mode(mode, Box::new(AsciiBackend::new(320, 200)));
+
+        // Draw on hidden page 1, then show it
+        screen.set_pages(Some(1), None).unwrap();
+        screen.pset(5, 5, Some(4)).unwrap();
+        assert_eq!(screen.point(5, 5), Some(4));
+        screen.set_pages(Some(0), None).unwrap();
+        assert_eq!(screen.point(5, 5), Some(0));
+        screen.set_pages(None, Some(1)).unwrap();
+        assert_eq!(screen.backend.point(5, 5), Some(4));
+
+        // Page 1 is on display, page 0 is drawn on in memory
+        screen.pset(6, 6, Some(2)).unwrap();
+        assert_eq!(screen.backend.point(6, 6), Some(0));
+        screen.copy_page(0, 1).unwrap();
+        assert_eq!(screen.backend.point(6, 6), Some(2));
+        assert_eq!(screen.backend.point(5, 5), Some(0));
+        screen.copy_page(1, 3).unwrap();
+        screen.copy_page(3, 2).unwrap();
+        screen.set_pages(Some(2), Some(2)).unwrap();
+        assert_eq!(screen.point(6, 6), Some(2));
+
+        assert!(screen.set_pages(Some(8), None).is_err());
+        assert!(screen.copy_page(0, 8).is_err());
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut screen = Screen::with_mode(ScreenMode::get(9).unwrap(), Box::new(AsciiBackend::new(640, 350)));
+        assert_eq!(screen.palette()[6], 20);
+        screen.set_palette(6, 63).unwrap();
+        screen.set_palette(7, -1).unwrap();
+        assert_eq!(&screen.palette()[6..8], &[63, 7]);
+        assert!(screen.set_palette(16, 0).is_err());
+        assert!(screen.set_palette(1, 64).is_err());
+
+        let mut colors = vec![-1; 16];
+        colors[0] = 1;
+        screen.set_palette_using(&colors).unwrap();
+        assert_eq!(&screen.palette()[..2], &[1, 1]);
+        assert!(screen.set_palette_using(&colors[..8]).is_err());
+        screen.reset_palette();
+        assert_eq!(screen.palette(), ScreenMode::get(9).unwrap().default_palette);
+
+        let mut cga = Screen::with_mode(ScreenMode::get(1).unwrap(), Box::new(AsciiBackend::new(320, 200)));
+        cga.set_cga_colors(Some(1), Some(0)).unwrap();
+        assert_eq!(cga.palette(), &[1, 2, 4, 6]);
+    }
+
     #[test]
     fn test_locate() {
         let mut screen = Screen::new(80, 25);
@@ -7,7 +7,8 @@ use crate::graphics_backend::GraphicsBackend;
 pub struct AsciiBackend {
     width: usize,
     height: usize,
-    buffer: Vec<Vec<char>>,
+    /// Colour of each pixel, shown as '#' unless it is 0
+    buffer: Vec<Vec<u8>>,
     cursor_x: usize,
     cursor_y: usize,
     fg_color: u8,
@@ -19,13 +20,18 @@ impl AsciiBackend {
         AsciiBackend {
             width,
             height,
-            buffer: vec![vec![' '; width]; height],
+            buffer: vec![vec![0; width]; height],
             cursor_x: 0,
             cursor_y: 0,
             fg_color: 7,
             bg_color: 0,
         }
     }
+
+    /// Text of one row of the buffer
+    pub fn row(&self, y: usize) -> String {
+        self.buffer[y].iter().map(|&c| if c == 0 { ' ' } else { '#' }).collect()
+    }
 }
 
 impl GraphicsBackend for AsciiBackend {
@@ -34,73 +40,19 @@ impl GraphicsBackend for AsciiBackend {
             return Ok(()); // Silently ignore out-of-bounds
         }
 
-        // Color 0 is background (don't draw), others draw '#'
-        if color == 0 {
-            self.buffer[y as usize][x as usize] = ' ';
-        } else {
-            self.buffer[y as usize][x as usize] = '#';
-        }
+        self.buffer[y as usize][x as usize] = color;
         Ok(())
     }
 
-    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
-        // Bresenham's line algorithm
-        let dx = (x2 - x1).abs();
-        let dy = (y2 - y1).abs();
-        let sx = if x1 < x2 { 1 } else { -1 };
-        let sy = if y1 < y2 { 1 } else { -1 };
-        let mut err = dx - dy;
-        let mut x = x1;
-        let mut y = y1;
-
-        loop {
-            self.pset(x, y, color)?;
-            if x == x2 && y == y2 {
-                break;
-            }
-            let e2 = 2 * err;
-            if e2 > -dy {
-                err -= dy;
-                x += sx;
-            }
-            if e2 < dx {
-                err += dx;
-                y += sy;
-            }
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        if x < 0 || y < 0 {
+            return None;
         }
-        Ok(())
-    }
-
-    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8) -> Result<()> {
-        // Midpoint circle algorithm
-        let mut dx = radius;
-        let mut dy = 0;
-        let mut err = 0;
-
-        while dx >= dy {
-            self.pset(x + dx, y + dy, color)?;
-            self.pset(x + dy, y + dx, color)?;
-            self.pset(x - dy, y + dx, color)?;
-            self.pset(x - dx, y + dy, color)?;
-            self.pset(x - dx, y - dy, color)?;
-            self.pset(x - dy, y - dx, color)?;
-            self.pset(x + dy, y - dx, color)?;
-            self.pset(x + dx, y - dy, color)?;
-
-            if err <= 0 {
-                dy += 1;
-                err += 2 * dy + 1;
-            }
-            if err > 0 {
-                dx -= 1;
-                err -= 2 * dx + 1;
-            }
-        }
-        Ok(())
+        self.buffer.get(y as usize)?.get(x as usize).copied()
     }
 
     fn cls(&mut self) {
-        self.buffer = vec![vec![' '; self.width]; self.height];
+        self.buffer = vec![vec![0; self.width]; self.height];
         self.cursor_x = 0;
         self.cursor_y = 0;
     }
@@ -128,12 +80,8 @@ impl GraphicsBackend for AsciiBackend {
 
     fn display(&mut self) {
         println!("\n{}", "=".repeat(self.width + 2));
-        for row in &self.buffer {
-            print!("|");
-            for &ch in row {
-                print!("{}", ch);
-            }
-            println!("|");
+        for y in 0..self.height {
+            println!("|{}|", self.row(y));
         }
         println!("{}", "=".repeat(self.width + 2));
     }
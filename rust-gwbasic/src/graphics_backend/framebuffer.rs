@@ -0,0 +1,80 @@
+//! In-memory framebuffer graphics backend
+
+use crate::error::{Error, Result};
+use crate::graphics_backend::GraphicsBackend;
+
+/// A buffer of colour indices that is never shown, used for video pages
+/// that are not on display and as the screen when there is no display
+#[derive(Clone)]
+pub struct FramebufferBackend {
+    width: usize,
+    height: usize,
+    /// Colour of each pixel, row by row
+    pixels: Vec<u8>,
+    cursor_x: usize,
+    cursor_y: usize,
+}
+
+impl FramebufferBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        FramebufferBackend {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            cursor_x: 0,
+            cursor_y: 0,
+        }
+    }
+
+    /// Colour of each pixel, row by row
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl GraphicsBackend for FramebufferBackend {
+    fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.pixels[y as usize * self.width + x as usize] = color;
+        }
+        Ok(())
+    }
+
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some(self.pixels[y as usize * self.width + x as usize])
+        } else {
+            None
+        }
+    }
+
+    fn cls(&mut self) {
+        self.pixels.fill(0);
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+    }
+
+    fn locate(&mut self, row: usize, col: usize) -> Result<()> {
+        if row >= self.height || col >= self.width {
+            return Err(Error::RuntimeError(format!(
+                "LOCATE position out of range: ({}, {})",
+                row, col
+            )));
+        }
+        self.cursor_y = row;
+        self.cursor_x = col;
+        Ok(())
+    }
+
+    fn color(&mut self, _fg: Option<u8>, _bg: Option<u8>) {}
+
+    fn display(&mut self) {}
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn get_cursor(&self) -> (usize, usize) {
+        (self.cursor_y, self.cursor_x)
+    }
+}
@@ -3,25 +3,159 @@
 //! Provides abstraction for different graphics rendering backends:
 //! - ASCII: Terminal-based rendering with characters
 //! - Window: GUI window with pixel-based rendering
+//! - Framebuffer: colour indices in memory, for pages not on display and
+//!   for running without a display
+//! - SVG: shapes recorded as vector graphics
+//! - Terminal: live half-block, braille or Sixel drawing in the terminal
 
 pub mod ascii;
+pub mod framebuffer;
+pub mod svg;
+pub mod terminal;
 pub mod window;
 
 pub use ascii::AsciiBackend;
+pub use framebuffer::FramebufferBackend;
+pub use svg::SvgBackend;
+pub use terminal::{TerminalBackend, TerminalColors, TerminalStyle};
 pub use window::WindowBackend;
 
 use crate::error::Result;
+use crate::text_screen::TextScreen;
+use std::f64::consts::TAU;
+
+/// The 16 colours of a CGA, as 0xRRGGBB
+pub const CGA_RGB: [u32; 16] = [
+    0x000000, // 0: Black
+    0x0000AA, // 1: Blue
+    0x00AA00, // 2: Green
+    0x00AAAA, // 3: Cyan
+    0xAA0000, // 4: Red
+    0xAA00AA, // 5: Magenta
+    0xAA5500, // 6: Brown
+    0xAAAAAA, // 7: Light Gray
+    0x555555, // 8: Dark Gray
+    0x5555FF, // 9: Light Blue
+    0x55FF55, // 10: Light Green
+    0x55FFFF, // 11: Light Cyan
+    0xFF5555, // 12: Light Red
+    0xFF55FF, // 13: Light Magenta
+    0xFFFF55, // 14: Yellow
+    0xFFFFFF, // 15: White
+];
+
+/// One of the 64 colours of an EGA, as 0xRRGGBB. Bits 0 to 2 of the colour
+/// are the strong blue, green and red signals and bits 3 to 5 the weak ones.
+pub fn ega_rgb(color: u8) -> u32 {
+    let channel = |strong: u8, weak: u8| {
+        0xAA * ((color >> strong) & 1) as u32 + 0x55 * ((color >> weak) & 1) as u32
+    };
+    channel(2, 5) << 16 | channel(1, 4) << 8 | channel(0, 3)
+}
+
+/// Arc angles and aspect ratio of a CIRCLE
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CircleShape {
+    /// Start and end of the arc in radians, counter-clockwise from the
+    /// right. A negative angle also draws a radius line to that end.
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+
+    /// Ratio of the vertical to the horizontal radius, in pixels
+    pub aspect: f64,
+}
+
+impl CircleShape {
+    /// A whole circle or ellipse
+    pub fn full(aspect: f64) -> Self {
+        CircleShape { start: None, end: None, aspect }
+    }
+}
 
 /// Graphics backend trait - abstracts the rendering implementation
 pub trait GraphicsBackend {
     /// Set a pixel at (x, y) with the given color
     fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()>;
 
-    /// Draw a line from (x1, y1) to (x2, y2) with the given color
-    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()>;
+    /// Color of the pixel at (x, y), or None if it is off the screen
+    fn point(&self, x: i32, y: i32) -> Option<u8>;
+
+    /// Draw a line from (x1, y1) to (x2, y2) with the given color. Each
+    /// pixel is drawn only if its bit of the style mask is set, starting at
+    /// bit 15 and repeating every 16 pixels (0xFFFF draws a solid line).
+    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        draw_line(self, x1, y1, x2, y2, color, style)
+    }
+
+    /// Draw the outline of a box with corners (x1, y1) and (x2, y2), with a
+    /// style mask that runs on around the corners
+    fn rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        draw_rect(self, x1, y1, x2, y2, color, style)
+    }
+
+    /// Fill a box with corners (x1, y1) and (x2, y2)
+    fn fill_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
+        draw_fill_rect(self, x1, y1, x2, y2, color)
+    }
+
+    /// Draw a circle, ellipse or arc centred on (x, y). The radius is the
+    /// larger of the two radii, the other one following from the aspect
+    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8, shape: CircleShape) -> Result<()> {
+        draw_circle(self, x, y, radius, color, shape)
+    }
 
-    /// Draw a circle at (x, y) with radius and color
-    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8) -> Result<()>;
+    /// Flood fill the area around (x, y) bounded by the border colour
+    /// (scanline fill). With a tile, a run of pixels that already shows the
+    /// tile's pattern counts as painted, unless that pattern is the
+    /// background tile row.
+    fn paint(&mut self, x: i32, y: i32, fill: &Fill, border: u8) -> Result<()> {
+        let (height, width) = self.get_size();
+        let mut visited = vec![false; width * height];
+        let index = |x: i32, y: i32| y as usize * width + x as usize;
+        let fillable = |backend: &Self, visited: &[bool], x: i32, y: i32| {
+            backend.point(x, y).is_some_and(|c| c != border) && !visited[index(x, y)]
+        };
+
+        let mut seeds = vec![(x, y)];
+        while let Some((x, y)) = seeds.pop() {
+            if !fillable(self, &visited, x, y) {
+                continue;
+            }
+
+            // Widen to the whole run on this line and paint it
+            let mut left = x;
+            while fillable(self, &visited, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while fillable(self, &visited, right + 1, y) {
+                right += 1;
+            }
+            for px in left..=right {
+                visited[index(px, y)] = true;
+                self.pset(px, y, fill.color_at(px, y))?;
+            }
+
+            // Seed each run of the lines above and below
+            for ny in [y - 1, y + 1] {
+                let mut px = left;
+                while px <= right {
+                    if !fillable(self, &visited, px, ny) {
+                        px += 1;
+                        continue;
+                    }
+                    let start = px;
+                    while px <= right && fillable(self, &visited, px, ny) {
+                        px += 1;
+                    }
+                    if !fill.already_painted(self, start, px - 1, ny) {
+                        seeds.push((start, ny));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 
     /// Clear the screen
     fn cls(&mut self);
@@ -50,4 +184,320 @@ pub trait GraphicsBackend {
     fn update(&mut self) -> Result<()> {
         Ok(())
     }
+
+    /// Keep what is on display as video page `number`, copying its pixels
+    /// into `page` as another page is about to be shown. Backends that
+    /// keep more than pixels can keep the drawing for `load_page`.
+    fn save_page(&mut self, _number: usize, page: &mut FramebufferBackend) -> Result<()> {
+        copy_pixels(self, page)
+    }
+
+    /// Show video page `number`, whose pixels are `page`
+    fn load_page(&mut self, _number: usize, page: &FramebufferBackend) -> Result<()> {
+        copy_pixels(page, self)
+    }
+
+    /// Set the colour shown for each attribute, as 0xRRGGBB. Pixels that
+    /// are already drawn change colour too, as on an EGA. Backends without
+    /// colour ignore it.
+    fn set_palette(&mut self, _colors: &[u32]) {}
+
+    /// Whether the backend draws the text screen over the graphics itself
+    /// (see `show_text`), so the console must not write to the terminal
+    fn shows_text(&self) -> bool {
+        false
+    }
+
+    /// The text on the screen, for backends that show it
+    fn show_text(&mut self, _text: &TextScreen) {}
+}
+
+/// What PAINT fills an area with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fill {
+    Solid(u8),
+
+    /// Rows of pixel colours repeated across and down the screen, and the
+    /// row pattern of the background that may be painted over
+    Tile {
+        rows: Vec<Vec<u8>>,
+        background: Option<Vec<u8>>,
+    },
+}
+
+impl Fill {
+    /// Colour to paint at (x, y); tiles line up with the screen origin
+    pub fn color_at(&self, x: i32, y: i32) -> u8 {
+        match self {
+            Fill::Solid(color) => *color,
+            Fill::Tile { rows, .. } => {
+                let row = &rows[y.rem_euclid(rows.len() as i32) as usize];
+                row[x.rem_euclid(row.len() as i32) as usize]
+            }
+        }
+    }
+
+    /// Whether pixels `left..=right` of line `y` already show this tile
+    fn already_painted<B: GraphicsBackend + ?Sized>(&self, backend: &B, left: i32, right: i32, y: i32) -> bool {
+        let Fill::Tile { rows, background } = self else {
+            return false;
+        };
+        let row = &rows[y.rem_euclid(rows.len() as i32) as usize];
+        if background.as_ref() == Some(row) {
+            return false;
+        }
+        (left..=right).all(|x| backend.point(x, y) == Some(self.color_at(x, y)))
+    }
+}
+
+/// Horizontal and vertical radius of a CIRCLE with the given radius
+/// and aspect
+pub fn ellipse_radii(radius: i32, aspect: f64) -> (i32, i32) {
+    if aspect > 1.0 {
+        ((radius as f64 / aspect).round() as i32, radius)
+    } else {
+        (radius, (radius as f64 * aspect).round() as i32)
+    }
+}
+
+/// The pixels of `GraphicsBackend::line`, drawn through `pset`
+pub(crate) fn draw_line<B: GraphicsBackend + ?Sized>(
+    backend: &mut B,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    color: u8,
+    style: u16,
+) -> Result<()> {
+    let mut mask = style;
+    styled_line(backend, x1, y1, x2, y2, color, &mut mask)
+}
+
+/// The pixels of `GraphicsBackend::rect`, drawn through `pset`
+pub(crate) fn draw_rect<B: GraphicsBackend + ?Sized>(
+    backend: &mut B,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    color: u8,
+    style: u16,
+) -> Result<()> {
+    let mut mask = style;
+    styled_line(backend, x1, y1, x2, y1, color, &mut mask)?;
+    styled_line(backend, x1, y2, x2, y2, color, &mut mask)?;
+    styled_line(backend, x1, y1, x1, y2, color, &mut mask)?;
+    styled_line(backend, x2, y1, x2, y2, color, &mut mask)
+}
+
+/// The pixels of `GraphicsBackend::fill_rect`, drawn through `pset`
+/// Copy every pixel of one screen to another of the same size
+pub(crate) fn copy_pixels<F: GraphicsBackend + ?Sized, T: GraphicsBackend + ?Sized>(from: &F, to: &mut T) -> Result<()> {
+    let (height, width) = from.get_size();
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            to.pset(x, y, from.point(x, y).unwrap_or(0))?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn draw_fill_rect<B: GraphicsBackend + ?Sized>(backend: &mut B, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
+    for y in y1.min(y2)..=y1.max(y2) {
+        styled_line(backend, x1, y, x2, y, color, &mut 0xFFFF)?;
+    }
+    Ok(())
+}
+
+/// The pixels of `GraphicsBackend::circle`, drawn through `pset`
+pub(crate) fn draw_circle<B: GraphicsBackend + ?Sized>(
+    backend: &mut B,
+    x: i32,
+    y: i32,
+    radius: i32,
+    color: u8,
+    shape: CircleShape,
+) -> Result<()> {
+    let (rx, ry) = ellipse_radii(radius, shape.aspect);
+    let start = shape.start.map_or(0.0, f64::abs);
+    let end = shape.end.map_or(TAU, f64::abs);
+    let full = shape.start.is_none() && shape.end.is_none();
+    for (dx, dy) in ellipse_quadrant(rx, ry) {
+        for (px, py) in [(dx, dy), (-dx, dy), (dx, -dy), (-dx, -dy)] {
+            if full || arc_contains(start, end, px, py, rx, ry) {
+                backend.pset(x + px, y + py, color)?;
+            }
+        }
+    }
+
+    // Negative angles join that end of the arc to the centre
+    for angle in [shape.start, shape.end].into_iter().flatten() {
+        if angle < 0.0 {
+            let ex = x + (rx as f64 * angle.cos()).round() as i32;
+            let ey = y - (ry as f64 * angle.abs().sin()).round() as i32;
+            backend.line(x, y, ex, ey, color, 0xFFFF)?;
+        }
+    }
+    Ok(())
+}
+
+/// Points of one quadrant of an ellipse (midpoint algorithm), as offsets
+/// from the centre with both coordinates non-negative
+fn ellipse_quadrant(rx: i32, ry: i32) -> Vec<(i32, i32)> {
+    let (rx, ry) = (rx.abs(), ry.abs());
+    if rx == 0 || ry == 0 {
+        return (0..=rx.max(ry)).map(|i| if rx == 0 { (0, i) } else { (i, 0) }).collect();
+    }
+
+    let (rx2, ry2) = ((rx as f64).powi(2), (ry as f64).powi(2));
+    let mut points = Vec::new();
+    let (mut x, mut y) = (0, ry);
+
+    // Region where the slope is shallower than -1: step in x
+    let mut p = ry2 - rx2 * ry as f64 + rx2 / 4.0;
+    while ry2 * x as f64 <= rx2 * y as f64 {
+        points.push((x, y));
+        x += 1;
+        if p < 0.0 {
+            p += 2.0 * ry2 * x as f64 + ry2;
+        } else {
+            y -= 1;
+            p += 2.0 * ry2 * x as f64 - 2.0 * rx2 * y as f64 + ry2;
+        }
+    }
+
+    // The rest: step in y
+    let mut p = ry2 * (x as f64 + 0.5).powi(2) + rx2 * (y as f64 - 1.0).powi(2) - rx2 * ry2;
+    while y >= 0 {
+        points.push((x, y));
+        y -= 1;
+        if p > 0.0 {
+            p += rx2 - 2.0 * rx2 * y as f64;
+        } else {
+            x += 1;
+            p += 2.0 * ry2 * x as f64 - 2.0 * rx2 * y as f64 + rx2;
+        }
+    }
+    points
+}
+
+/// Whether the ellipse point at offset (dx, dy) lies on the arc from
+/// `start` to `end`, going counter-clockwise (screen y points down)
+fn arc_contains(start: f64, end: f64, dx: i32, dy: i32, rx: i32, ry: i32) -> bool {
+    let angle = (-dy as f64 / ry.max(1) as f64).atan2(dx as f64 / rx.max(1) as f64);
+    let angle = if angle < 0.0 { angle + TAU } else { angle };
+    if start <= end {
+        (start..=end).contains(&angle)
+    } else {
+        angle >= start || angle <= end
+    }
+}
+
+/// Bresenham line through `pset`, consuming one bit of `mask` per pixel.
+/// Lines are drawn along their major axis in increasing order, so a style
+/// looks the same whichever end the line is given from.
+fn styled_line<B: GraphicsBackend + ?Sized>(
+    backend: &mut B,
+    x1: i32,
+    y1: i32,
+    x2: i32,
+    y2: i32,
+    color: u8,
+    mask: &mut u16,
+) -> Result<()> {
+    let steep = (y2 - y1).abs() > (x2 - x1).abs();
+    let (mut a1, mut b1, mut a2, mut b2) = if steep { (y1, x1, y2, x2) } else { (x1, y1, x2, y2) };
+    if a1 > a2 {
+        std::mem::swap(&mut a1, &mut a2);
+        std::mem::swap(&mut b1, &mut b2);
+    }
+
+    let da = a2 - a1;
+    let db = (b2 - b1).abs();
+    let sb = if b1 < b2 { 1 } else { -1 };
+    let mut err = da / 2;
+    let mut b = b1;
+    for a in a1..=a2 {
+        if *mask & 0x8000 != 0 {
+            let (x, y) = if steep { (b, a) } else { (a, b) };
+            backend.pset(x, y, color)?;
+        }
+        *mask = mask.rotate_left(1);
+        err -= db;
+        if err < 0 {
+            b += sb;
+            err += da;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ega_rgb() {
+        assert_eq!(ega_rgb(0), 0x000000);
+        assert_eq!(ega_rgb(20), 0xAA5500);
+        assert_eq!(ega_rgb(57), 0x5555FF);
+        assert_eq!(ega_rgb(63), 0xFFFFFF);
+    }
+
+    #[test]
+    fn test_styled_line_and_box() {
+        let mut backend = AsciiBackend::new(20, 6);
+        backend.line(19, 0, 0, 0, 1, 0xAAAA).unwrap();
+        assert_eq!(backend.row(0), "# # # # # # # # # # ");
+
+        backend.cls();
+        backend.rect(1, 1, 4, 3, 1, 0xFFFF).unwrap();
+        backend.fill_rect(6, 1, 8, 3, 1).unwrap();
+        assert_eq!(backend.row(1), " #### ###           ");
+        assert_eq!(backend.row(2), " #  # ###           ");
+        assert_eq!(backend.row(3), " #### ###           ");
+    }
+
+    #[test]
+    fn test_circle_arcs() {
+        let mut backend = AsciiBackend::new(11, 11);
+        backend.circle(5, 5, 4, 1, CircleShape::full(1.0)).unwrap();
+        assert_eq!(backend.row(1), "    ###    ");
+        assert_eq!(backend.row(5), " #       # ");
+
+        // Upper half, closed with radius lines into a pie wedge
+        backend.cls();
+        let pie = CircleShape { start: Some(-0.0001), end: Some(-std::f64::consts::PI), aspect: 1.0 };
+        backend.circle(5, 5, 4, 1, pie).unwrap();
+        assert_eq!(backend.row(5), " ######### ");
+        assert_eq!(backend.row(8), "           ");
+
+        // Aspect 0.5 halves the vertical radius
+        backend.cls();
+        backend.circle(5, 5, 4, 1, CircleShape::full(0.5)).unwrap();
+        assert_eq!(backend.row(2), "           ");
+        assert!(backend.row(3).contains('#'));
+    }
+
+    #[test]
+    fn test_paint() {
+        let mut backend = AsciiBackend::new(12, 7);
+        backend.rect(0, 0, 11, 6, 2, 0xFFFF).unwrap();
+        backend.line(5, 0, 5, 6, 2, 0xFFFF).unwrap();
+
+        // Stops at the border, so only the left room is filled
+        backend.paint(2, 2, &Fill::Solid(1), 2).unwrap();
+        assert_eq!(backend.point(4, 5), Some(1));
+        assert_eq!(backend.point(5, 5), Some(2));
+        assert_eq!(backend.point(6, 5), Some(0));
+
+        // A checkerboard tile in the right room
+        let tile = Fill::Tile { rows: vec![vec![3, 0], vec![0, 3]], background: None };
+        backend.paint(8, 3, &tile, 2).unwrap();
+        assert_eq!(backend.point(6, 1), Some(0));
+        assert_eq!(backend.point(7, 1), Some(3));
+        assert_eq!(backend.point(6, 2), Some(3));
+        assert_eq!(backend.point(4, 5), Some(1));
+    }
 }
@@ -0,0 +1,426 @@
+//! SVG graphics backend
+//!
+//! Lines, boxes, circles and arcs are kept as SVG shapes, in the order they
+//! were drawn, and single pixels (PSET, PAINT, styled lines) as small
+//! squares. Pixel (x, y) is the unit square from (x, y) to (x + 1, y + 1), so
+//! strokes run through pixel centres. Colours are looked up in the palette
+//! when the SVG is written. Each video page keeps its own shapes, so that
+//! flipping pages brings back the drawing rather than its pixels.
+
+use crate::error::{Error, Result};
+use crate::graphics_backend::{
+    draw_circle, draw_line, draw_rect, ellipse_radii, CircleShape, FramebufferBackend, GraphicsBackend, CGA_RGB,
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+/// Something drawn, in screen pixels
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Point { x: i32, y: i32 },
+    Line { x1: i32, y1: i32, x2: i32, y2: i32 },
+    Rect { left: i32, top: i32, right: i32, bottom: i32 },
+    Fill { left: i32, top: i32, right: i32, bottom: i32 },
+    Ellipse { x: i32, y: i32, rx: i32, ry: i32 },
+
+    /// Counter-clockwise from `start` to `end`, in radians
+    Arc { x: i32, y: i32, rx: i32, ry: i32, start: f64, end: f64 },
+}
+
+/// A shape, its colour and the pixels it covers
+#[derive(Debug, Clone)]
+struct Element {
+    shape: Shape,
+    color: u8,
+    pixels: Vec<(i32, i32)>,
+}
+
+/// Collects the pixels a primitive sets, without drawing them
+#[derive(Default)]
+struct PixelList {
+    pixels: Vec<(i32, i32)>,
+}
+
+impl GraphicsBackend for PixelList {
+    fn pset(&mut self, x: i32, y: i32, _color: u8) -> Result<()> {
+        self.pixels.push((x, y));
+        Ok(())
+    }
+
+    fn point(&self, _x: i32, _y: i32) -> Option<u8> {
+        None
+    }
+
+    fn cls(&mut self) {}
+
+    fn locate(&mut self, _row: usize, _col: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn color(&mut self, _fg: Option<u8>, _bg: Option<u8>) {}
+
+    fn display(&mut self) {}
+
+    fn get_size(&self) -> (usize, usize) {
+        (0, 0)
+    }
+
+    fn get_cursor(&self) -> (usize, usize) {
+        (0, 0)
+    }
+}
+
+/// Backend that records what is drawn as SVG, for crisp vector output of
+/// line drawings
+pub struct SvgBackend {
+    /// The same drawing as pixels, for reading back
+    pixels: FramebufferBackend,
+    elements: Vec<Element>,
+    /// Shapes and pixels of video pages out of view, as they were last
+    /// shown
+    pages: HashMap<usize, (Vec<Element>, FramebufferBackend)>,
+    /// Colour shown for each colour index
+    palette: Vec<u32>,
+    /// File written when the screen is displayed
+    output: Option<PathBuf>,
+    width: usize,
+    height: usize,
+}
+
+impl SvgBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        SvgBackend {
+            pixels: FramebufferBackend::new(width, height),
+            elements: Vec::new(),
+            pages: HashMap::new(),
+            palette: CGA_RGB.to_vec(),
+            output: None,
+            width,
+            height,
+        }
+    }
+
+    /// Write the SVG to `path` whenever the screen is displayed, as at END
+    pub fn with_output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.output = Some(path.into());
+        self
+    }
+
+    /// Record a shape, given the pixels `draw` sets for it
+    fn add(&mut self, shape: Shape, color: u8, draw: impl FnOnce(&mut PixelList) -> Result<()>) -> Result<()> {
+        let mut list = PixelList::default();
+        draw(&mut list)?;
+        let (width, height) = (self.width as i32, self.height as i32);
+        list.pixels.retain(|&(x, y)| (0..width).contains(&x) && (0..height).contains(&y));
+        if list.pixels.is_empty() {
+            return Ok(());
+        }
+        for &(x, y) in &list.pixels {
+            self.pixels.pset(x, y, color)?;
+        }
+        self.elements.push(Element { shape, color, pixels: list.pixels });
+        Ok(())
+    }
+
+    fn rgb(&self, color: u8) -> String {
+        format!("#{:06X}", self.palette[color as usize % self.palette.len()])
+    }
+
+    /// The drawing as an SVG document. Shapes that later ones cover
+    /// completely are left out, and runs of single pixels of one colour
+    /// along a row are joined.
+    pub fn to_svg(&self) -> String {
+        // Walk back from the last shape, keeping those that still show
+        let mut covered = vec![false; self.width * self.height];
+        let mut visible = Vec::new();
+        for element in self.elements.iter().rev() {
+            let mut shows = false;
+            for &(x, y) in &element.pixels {
+                let index = y as usize * self.width + x as usize;
+                shows |= !covered[index];
+                covered[index] = true;
+            }
+            if shows {
+                visible.push(element);
+            }
+        }
+        visible.reverse();
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.width,
+            h = self.height
+        );
+        let _ = writeln!(svg, r#"<rect width="{}" height="{}" fill="{}"/>"#, self.width, self.height, self.rgb(0));
+        let _ = writeln!(svg, r#"<g fill="none" stroke-width="1" stroke-linecap="square">"#);
+
+        let mut elements = visible.into_iter().peekable();
+        while let Some(element) = elements.next() {
+            let color = self.rgb(element.color);
+            let _ = match element.shape {
+                Shape::Point { x, y } => {
+                    // Join the pixels that follow on along the row
+                    let mut run = 1;
+                    while let Some(next) = elements.peek() {
+                        if next.color != element.color || next.shape != (Shape::Point { x: x + run, y }) {
+                            break;
+                        }
+                        run += 1;
+                        elements.next();
+                    }
+                    writeln!(svg, r#"<rect x="{}" y="{}" width="{}" height="1" fill="{}"/>"#, x, y, run, color)
+                }
+                Shape::Line { x1, y1, x2, y2 } => writeln!(
+                    svg,
+                    r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{}"/>"#,
+                    centre(x1),
+                    centre(y1),
+                    centre(x2),
+                    centre(y2),
+                    color
+                ),
+                Shape::Rect { left, top, right, bottom } => writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" stroke="{}"/>"#,
+                    centre(left),
+                    centre(top),
+                    right - left,
+                    bottom - top,
+                    color
+                ),
+                Shape::Fill { left, top, right, bottom } => writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                    left,
+                    top,
+                    right - left + 1,
+                    bottom - top + 1,
+                    color
+                ),
+                Shape::Ellipse { x, y, rx, ry } => writeln!(
+                    svg,
+                    r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" stroke="{}"/>"#,
+                    centre(x),
+                    centre(y),
+                    rx,
+                    ry,
+                    color
+                ),
+                Shape::Arc { x, y, rx, ry, start, end } => {
+                    let point = |angle: f64| {
+                        (centre(x) + rx as f64 * angle.cos(), centre(y) - ry as f64 * angle.sin())
+                    };
+                    let ((sx, sy), (ex, ey)) = (point(start), point(end));
+                    let sweep = (end - start).rem_euclid(std::f64::consts::TAU);
+                    let large = (sweep > std::f64::consts::PI) as u8;
+                    writeln!(
+                        svg,
+                        r#"<path d="M{:.2},{:.2} A{},{} 0 {} 0 {:.2},{:.2}" stroke="{}"/>"#,
+                        sx, sy, rx, ry, large, ex, ey, color
+                    )
+                }
+            };
+        }
+        svg.push_str("</g>\n</svg>\n");
+        svg
+    }
+
+    /// Write the SVG to a file
+    pub fn save(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let path = path.into();
+        fs::write(&path, self.to_svg()).map_err(|e| Error::IoError(format!("Cannot write {}: {}", path.display(), e)))
+    }
+}
+
+/// Centre of the pixel at `v` along an axis
+fn centre(v: i32) -> f64 {
+    v as f64 + 0.5
+}
+
+impl GraphicsBackend for SvgBackend {
+    fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()> {
+        self.add(Shape::Point { x, y }, color, |list| list.pset(x, y, color))
+    }
+
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        self.pixels.point(x, y)
+    }
+
+    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        if style != 0xFFFF {
+            return draw_line(self, x1, y1, x2, y2, color, style);
+        }
+        self.add(Shape::Line { x1, y1, x2, y2 }, color, |list| draw_line(list, x1, y1, x2, y2, color, style))
+    }
+
+    fn rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        if style != 0xFFFF {
+            return draw_rect(self, x1, y1, x2, y2, color, style);
+        }
+        let shape = Shape::Rect { left: x1.min(x2), top: y1.min(y2), right: x1.max(x2), bottom: y1.max(y2) };
+        self.add(shape, color, |list| draw_rect(list, x1, y1, x2, y2, color, style))
+    }
+
+    fn fill_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
+        let (left, top, right, bottom) = (x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2));
+        let shape = Shape::Fill { left, top, right, bottom };
+        self.add(shape, color, |list| {
+            list.pixels.extend((top..=bottom).flat_map(|y| (left..=right).map(move |x| (x, y))));
+            Ok(())
+        })
+    }
+
+    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8, shape: CircleShape) -> Result<()> {
+        let (rx, ry) = ellipse_radii(radius, shape.aspect);
+        let (rx, ry) = (rx.abs(), ry.abs());
+        if rx == 0 || ry == 0 {
+            // Flat ellipses are lines
+            return draw_circle(self, x, y, radius, color, shape);
+        }
+
+        // The curve on its own; radius lines are drawn as lines
+        let curve = CircleShape { start: shape.start.map(f64::abs), end: shape.end.map(f64::abs), ..shape };
+        let element = match (curve.start, curve.end) {
+            (None, None) => Shape::Ellipse { x, y, rx, ry },
+            (start, end) => Shape::Arc {
+                x,
+                y,
+                rx,
+                ry,
+                start: start.unwrap_or(0.0),
+                end: end.unwrap_or(std::f64::consts::TAU),
+            },
+        };
+        self.add(element, color, |list| draw_circle(list, x, y, radius, color, curve))?;
+
+        for angle in [shape.start, shape.end].into_iter().flatten() {
+            if angle < 0.0 {
+                let ex = x + (rx as f64 * angle.cos()).round() as i32;
+                let ey = y - (ry as f64 * angle.abs().sin()).round() as i32;
+                self.line(x, y, ex, ey, color, 0xFFFF)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cls(&mut self) {
+        self.elements.clear();
+        self.pixels.cls();
+    }
+
+    fn locate(&mut self, row: usize, col: usize) -> Result<()> {
+        self.pixels.locate(row, col)
+    }
+
+    fn color(&mut self, _fg: Option<u8>, _bg: Option<u8>) {}
+
+    fn display(&mut self) {
+        if let Some(path) = &self.output {
+            if let Err(e) = self.save(path.clone()) {
+                eprintln!("Error writing SVG: {}", e);
+            }
+        }
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn get_cursor(&self) -> (usize, usize) {
+        self.pixels.get_cursor()
+    }
+
+    fn save_page(&mut self, number: usize, page: &mut FramebufferBackend) -> Result<()> {
+        *page = self.pixels.clone();
+        self.pages.insert(number, (self.elements.clone(), self.pixels.clone()));
+        Ok(())
+    }
+
+    fn load_page(&mut self, number: usize, page: &FramebufferBackend) -> Result<()> {
+        // The page's shapes, unless it was drawn on while out of view
+        if let Some((elements, pixels)) = self.pages.get(&number) {
+            if pixels.pixels() == page.pixels() {
+                self.elements = elements.clone();
+                self.pixels = pixels.clone();
+                return Ok(());
+            }
+        }
+        self.cls();
+        let (height, width) = page.get_size();
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                match page.point(x, y) {
+                    Some(0) | None => {}
+                    Some(color) => self.pset(x, y, color)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn set_palette(&mut self, colors: &[u32]) {
+        self.palette = colors.to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg_shapes() {
+        let mut svg = SvgBackend::new(100, 50);
+        svg.line(0, 0, 99, 49, 1, 0xFFFF).unwrap();
+        svg.rect(10, 10, 20, 20, 2, 0xFFFF).unwrap();
+        svg.circle(50, 25, 10, 4, CircleShape::full(1.0)).unwrap();
+        for x in 30..35 {
+            svg.pset(x, 40, 14).unwrap();
+        }
+        assert_eq!(svg.point(20, 15), Some(2));
+
+        let text = svg.to_svg();
+        assert!(text.contains(r##"<line x1="0.5" y1="0.5" x2="99.5" y2="49.5" stroke="#0000AA"/>"##));
+        assert!(text.contains(r##"<rect x="10.5" y="10.5" width="10" height="10" stroke="#00AA00"/>"##));
+        assert!(text.contains(r##"<ellipse cx="50.5" cy="25.5" rx="10" ry="10" stroke="#AA0000"/>"##));
+        assert!(text.contains(r##"<rect x="30" y="40" width="5" height="1" fill="#FFFF55"/>"##));
+    }
+
+    #[test]
+    fn test_svg_pages() {
+        let mut svg = SvgBackend::new(100, 50);
+        let mut pages = [FramebufferBackend::new(100, 50), FramebufferBackend::new(100, 50)];
+        svg.line(0, 0, 99, 49, 1, 0xFFFF).unwrap();
+
+        // Show page 1, drawn on while hidden, then page 0 again
+        svg.save_page(0, &mut pages[0]).unwrap();
+        pages[1].pset(5, 5, 2).unwrap();
+        svg.load_page(1, &pages[1]).unwrap();
+        assert_eq!(svg.point(5, 5), Some(2));
+        assert!(!svg.to_svg().contains("<line"));
+        svg.save_page(1, &mut pages[1]).unwrap();
+        svg.load_page(0, &pages[0]).unwrap();
+
+        let text = svg.to_svg();
+        assert!(text.contains("<line"));
+        assert_eq!(text.matches("<rect").count(), 1);
+    }
+
+    #[test]
+    fn test_svg_overdraw() {
+        let mut svg = SvgBackend::new(100, 50);
+        svg.line(0, 0, 10, 0, 1, 0xFFFF).unwrap();
+        svg.circle(50, 25, 5, 2, CircleShape::full(1.0)).unwrap();
+        svg.fill_rect(40, 15, 60, 35, 3).unwrap();
+        svg.set_palette(&[0x000000, 0x123456, 0, 0x654321]);
+
+        // The box hides the circle but not the line
+        let text = svg.to_svg();
+        assert!(text.contains("#123456"));
+        assert!(!text.contains("<ellipse"));
+        assert!(text.contains(r##"<rect x="40" y="15" width="21" height="21" fill="#654321"/>"##));
+    }
+}
@@ -0,0 +1,562 @@
+//! Live terminal graphics backend
+//!
+//! Draws the screen in the terminal as it changes, either with Unicode
+//! characters coloured by ANSI escapes or as a Sixel image. Half blocks
+//! (`▀`) show two pixels per character cell, one above the other, in any
+//! two colours; braille patterns show 2x4 pixels per cell, lit or not, in
+//! one colour per cell. Only cells that changed are written again, at most
+//! every `REFRESH_INTERVAL`, and once more when the screen is displayed.
+//!
+//! Images too big for the terminal are scaled down to fit, each character
+//! cell then showing the colour most of the pixels under it have. Text
+//! printed by the program is drawn over the graphics, as the backend owns
+//! the terminal; with Sixel it is listed below the image.
+
+use crate::error::{Error, Result};
+use crate::graphics_backend::{GraphicsBackend, CGA_RGB};
+use crate::text_screen::TextScreen;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Least time between two refreshes while a program draws
+const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How the screen is drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalStyle {
+    /// Two pixels per cell, with upper half block characters
+    HalfBlock,
+
+    /// 2x4 pixels per cell, with braille patterns
+    Braille,
+
+    /// A Sixel image, for terminals that show them
+    Sixel,
+}
+
+/// Colour escapes the terminal understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalColors {
+    /// The xterm 256-colour palette
+    Ansi256,
+
+    /// 24-bit RGB
+    TrueColor,
+}
+
+impl TerminalColors {
+    /// True colour if COLORTERM says the terminal has it, else 256 colours
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM") {
+            Ok(value) if value == "truecolor" || value == "24bit" => TerminalColors::TrueColor,
+            _ => TerminalColors::Ansi256,
+        }
+    }
+
+    /// Escape sequence setting the foreground (38) or background (48)
+    fn escape(&self, layer: u8, rgb: u32) -> String {
+        let (r, g, b) = ((rgb >> 16) & 0xFF, (rgb >> 8) & 0xFF, rgb & 0xFF);
+        match self {
+            TerminalColors::TrueColor => format!("\x1b[{};2;{};{};{}m", layer, r, g, b),
+            TerminalColors::Ansi256 => format!("\x1b[{};5;{}m", layer, ansi256(r, g, b)),
+        }
+    }
+}
+
+/// Columns and rows of the terminal: from COLUMNS and LINES if set, else
+/// from `stty size`, else 80x24
+fn terminal_size() -> (usize, usize) {
+    let from_env = |name| std::env::var(name).ok().and_then(|value| value.parse::<usize>().ok());
+    if let (Some(columns), Some(rows)) = (from_env("COLUMNS"), from_env("LINES")) {
+        return (columns, rows);
+    }
+    let stty = File::open("/dev/tty")
+        .ok()
+        .and_then(|tty| Command::new("stty").arg("size").stdin(tty).output().ok())
+        .and_then(|output| {
+            let text = String::from_utf8(output.stdout).ok()?;
+            let mut numbers = text.split_whitespace().map(|n| n.parse::<usize>().ok());
+            let rows = numbers.next()??;
+            Some((numbers.next()??, rows))
+        });
+    match stty {
+        Some((columns, rows)) if columns > 0 && rows > 0 => (columns, rows),
+        _ => (80, 24),
+    }
+}
+
+/// Nearest colour of the 6x6x6 cube of the xterm 256-colour palette
+fn ansi256(r: u32, g: u32, b: u32) -> u8 {
+    const LEVELS: [u32; 6] = [0, 95, 135, 175, 215, 255];
+    let nearest = |c: u32| (0..6).min_by_key(|&i| LEVELS[i].abs_diff(c)).unwrap_or(0) as u8;
+    16 + 36 * nearest(r) + 6 * nearest(g) + nearest(b)
+}
+
+/// A character and its colours, as 0xRRGGBB
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    /// None for a blank cell, where only the background shows
+    fg: Option<u32>,
+    bg: u32,
+    glyph: char,
+}
+
+/// Backend that draws the screen in the terminal as the program runs
+pub struct TerminalBackend {
+    width: usize,
+    height: usize,
+    /// Colour of each pixel, row by row
+    pixels: Vec<u8>,
+    /// Colour shown for each colour index
+    palette: Vec<u32>,
+    style: TerminalStyle,
+    colors: TerminalColors,
+    /// Screen pixels per pixel drawn, at least 1, so the image fits the
+    /// terminal
+    scale: f64,
+    /// Characters of the text screen, row by row, and its size
+    text: Vec<(u8, u8)>,
+    text_size: (usize, usize),
+    /// What each character cell was last written as
+    shown: Vec<Option<Cell>>,
+    /// Whether anything changed since the last refresh
+    dirty: bool,
+    last_refresh: Option<Instant>,
+    cursor_x: usize,
+    cursor_y: usize,
+}
+
+impl TerminalBackend {
+    /// A backend for a screen of `width` by `height` pixels, scaled down to
+    /// fit the terminal it runs in
+    pub fn new(width: usize, height: usize, style: TerminalStyle, colors: TerminalColors) -> Self {
+        Self::with_terminal_size(width, height, style, colors, terminal_size())
+    }
+
+    /// A backend drawing in a terminal of `size` columns and rows, leaving
+    /// the last row for the cursor
+    pub fn with_terminal_size(
+        width: usize,
+        height: usize,
+        style: TerminalStyle,
+        colors: TerminalColors,
+        size: (usize, usize),
+    ) -> Self {
+        let (cell_width, cell_height) = cell_size(style);
+        let (columns, rows) = (size.0.max(1), size.1.saturating_sub(1).max(1));
+        // Sixel images are in pixels, which the terminal does not tell
+        let scale = match style {
+            TerminalStyle::Sixel => 1.0,
+            _ => (width as f64 / (columns * cell_width) as f64)
+                .max(height as f64 / (rows * cell_height) as f64)
+                .max(1.0),
+        };
+        TerminalBackend {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            palette: CGA_RGB.to_vec(),
+            style,
+            colors,
+            scale,
+            text: Vec::new(),
+            text_size: (0, 0),
+            shown: Vec::new(),
+            dirty: false,
+            last_refresh: None,
+            cursor_x: 0,
+            cursor_y: 0,
+        }
+    }
+
+    /// Character cells across and down that the image takes
+    fn grid_size(&self) -> (usize, usize) {
+        let (cell_width, cell_height) = cell_size(self.style);
+        let width = (self.width as f64 / self.scale).ceil() as usize;
+        let height = (self.height as f64 / self.scale).ceil() as usize;
+        (width.div_ceil(cell_width), height.div_ceil(cell_height))
+    }
+
+    /// Colour index of a pixel, background outside the screen
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x]
+        } else {
+            0
+        }
+    }
+
+    /// Colour index of a pixel of the scaled image: the colour most of the
+    /// screen pixels under it have, other than the background if any do
+    fn sample(&self, x: usize, y: usize) -> u8 {
+        if self.scale == 1.0 {
+            return self.pixel(x, y);
+        }
+        let range = |n: usize| {
+            let start = (n as f64 * self.scale) as usize;
+            start..((n + 1) as f64 * self.scale).max(start as f64 + 1.0) as usize
+        };
+        let mut counts = [0usize; 256];
+        for sy in range(y) {
+            for sx in range(x) {
+                counts[self.pixel(sx, sy) as usize] += 1;
+            }
+        }
+        (1..256).max_by_key(|&c| (counts[c], std::cmp::Reverse(c))).filter(|&c| counts[c] > 0).unwrap_or(0) as u8
+    }
+
+    /// Characters of the text screen placed on the character cells of the
+    /// image, with their colour index
+    fn text_overlay(&self, columns: usize, rows: usize) -> Vec<Option<(char, u8)>> {
+        let mut overlay = vec![None; columns * rows];
+        let (text_columns, text_rows) = self.text_size;
+        for (index, &(ch, attribute)) in self.text.iter().enumerate() {
+            if !(0x21..=0x7E).contains(&ch) && ch < 0xA1 {
+                continue;
+            }
+            // The cell under the middle of the character
+            let (row, column) = (index / text_columns, index % text_columns);
+            let cell = ((2 * row + 1) * rows / (2 * text_rows)) * columns + (2 * column + 1) * columns / (2 * text_columns);
+            overlay[cell] = Some((ch as char, attribute & 0x0F));
+        }
+        overlay
+    }
+
+    fn rgb(&self, color: u8) -> u32 {
+        self.palette[color as usize % self.palette.len()]
+    }
+
+    /// What one character cell shows
+    fn cell(&self, column: usize, row: usize) -> Cell {
+        match self.style {
+            TerminalStyle::HalfBlock | TerminalStyle::Sixel => {
+                let (top, bottom) = (self.sample(column, row * 2), self.sample(column, row * 2 + 1));
+                let (fg, bg) = (self.rgb(top), self.rgb(bottom));
+                if fg == bg {
+                    Cell { fg: None, bg, glyph: ' ' }
+                } else {
+                    Cell { fg: Some(fg), bg, glyph: '\u{2580}' }
+                }
+            }
+            TerminalStyle::Braille => {
+                // Dot bits of each sub-pixel, column by column
+                const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+                let (x, y) = (column * 2, row * 4);
+                let mut pattern = 0;
+                let mut counts = [0usize; 256];
+                for (dx, dots) in DOTS.iter().enumerate() {
+                    for (dy, &dot) in dots.iter().enumerate() {
+                        let color = self.sample(x + dx, y + dy);
+                        if color != 0 {
+                            pattern |= dot;
+                            counts[color as usize] += 1;
+                        }
+                    }
+                }
+                if pattern == 0 {
+                    return Cell { fg: None, bg: self.rgb(0), glyph: ' ' };
+                }
+                // The cell takes the colour most of its lit dots have
+                let color = (1..256).max_by_key(|&c| (counts[c], std::cmp::Reverse(c))).unwrap_or(0) as u8;
+                let glyph = char::from_u32(0x2800 + pattern).unwrap_or(' ');
+                Cell { fg: Some(self.rgb(color)), bg: self.rgb(0), glyph }
+            }
+        }
+    }
+
+    /// Terminal output that brings the display up to date: the cells that
+    /// changed, or the whole Sixel image
+    fn render(&mut self) -> String {
+        let mut out = String::new();
+        if self.last_refresh.is_none() {
+            out.push_str("\x1b[2J");
+        }
+        if self.style == TerminalStyle::Sixel {
+            out.push_str("\x1b[H");
+            out.push_str(&self.sixel());
+            // The text, below the image
+            out.push_str("\r\n\x1b[J");
+            let (text_columns, _) = self.text_size;
+            let lines: Vec<String> = self
+                .text
+                .chunks(text_columns.max(1))
+                .map(|row| row.iter().map(|&(ch, _)| ch as char).collect::<String>().trim_end().to_string())
+                .collect();
+            let used = lines.iter().rposition(|line| !line.is_empty()).map_or(0, |last| last + 1);
+            out.push_str(&lines[..used].join("\r\n"));
+            return out;
+        }
+
+        let (columns, rows) = self.grid_size();
+        self.shown.resize(columns * rows, None);
+        let overlay = self.text_overlay(columns, rows);
+        let mut written_to = None;
+        // Colours last set, so runs of cells alike need no new escapes
+        let (mut fg, mut bg) = (None, None);
+        for row in 0..rows {
+            for column in 0..columns {
+                let mut cell = self.cell(column, row);
+                if let Some((glyph, color)) = overlay[row * columns + column] {
+                    cell = Cell { fg: Some(self.rgb(color)), glyph, ..cell };
+                }
+                let shown = &mut self.shown[row * columns + column];
+                if shown.as_ref() == Some(&cell) {
+                    continue;
+                }
+                // Move there unless the last cell written was just before
+                if written_to != Some((row, column)) {
+                    let _ = write!(out, "\x1b[{};{}H", row + 1, column + 1);
+                }
+                if cell.fg.is_some() && cell.fg != fg {
+                    out.push_str(&self.colors.escape(38, cell.fg.unwrap_or(0)));
+                    fg = cell.fg;
+                }
+                if Some(cell.bg) != bg {
+                    out.push_str(&self.colors.escape(48, cell.bg));
+                    bg = Some(cell.bg);
+                }
+                out.push(cell.glyph);
+                *shown = Some(cell);
+                written_to = Some((row, column + 1));
+            }
+        }
+        if written_to.is_some() {
+            out.push_str("\x1b[0m");
+            let _ = write!(out, "\x1b[{};1H", rows + 1);
+        }
+        out
+    }
+
+    /// The screen as a Sixel image, one colour register per palette entry
+    fn sixel(&self) -> String {
+        let mut out = String::from("\x1bPq");
+        let _ = write!(out, "\"1;1;{};{}", self.width, self.height);
+        for (register, &rgb) in self.palette.iter().enumerate() {
+            let percent = |shift: u32| ((rgb >> shift) & 0xFF) * 100 / 255;
+            let _ = write!(out, "#{};2;{};{};{}", register, percent(16), percent(8), percent(0));
+        }
+
+        for band in (0..self.height).step_by(6) {
+            let mut colors: Vec<usize> = (band..(band + 6).min(self.height))
+                .flat_map(|y| self.pixels[y * self.width..(y + 1) * self.width].iter())
+                .map(|&color| color as usize % self.palette.len())
+                .collect();
+            colors.sort_unstable();
+            colors.dedup();
+
+            for (index, &color) in colors.iter().enumerate() {
+                if index > 0 {
+                    out.push('$');
+                }
+                let _ = write!(out, "#{}", color);
+                let sixels: Vec<u8> = (0..self.width)
+                    .map(|x| {
+                        let bits = (0..6)
+                            .filter(|dy| band + dy < self.height)
+                            .filter(|dy| self.pixel(x, band + dy) as usize % self.palette.len() == color)
+                            .fold(0, |bits, dy| bits | 1 << dy);
+                        63 + bits
+                    })
+                    .collect();
+                push_run_length(&mut out, &sixels);
+            }
+            out.push('-');
+        }
+        out.push_str("\x1b\\");
+        out
+    }
+
+    /// Write what changed to the terminal
+    fn refresh(&mut self) {
+        let text = self.render();
+        self.dirty = false;
+        self.last_refresh = Some(Instant::now());
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(text.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    /// Note a change, refreshing if it has been a while
+    fn changed(&mut self) {
+        self.dirty = true;
+        if self.last_refresh.is_none_or(|time| time.elapsed() >= REFRESH_INTERVAL) {
+            self.refresh();
+        }
+    }
+}
+
+/// Pixels per character cell across and down
+fn cell_size(style: TerminalStyle) -> (usize, usize) {
+    match style {
+        TerminalStyle::HalfBlock => (1, 2),
+        TerminalStyle::Braille => (2, 4),
+        TerminalStyle::Sixel => (1, 1),
+    }
+}
+
+/// Sixel characters with runs of four or more written as `!count char`
+fn push_run_length(out: &mut String, sixels: &[u8]) {
+    let mut rest = sixels;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&sixel| sixel == first).count();
+        if run >= 4 {
+            let _ = write!(out, "!{}{}", run, first as char);
+        } else {
+            out.extend(std::iter::repeat_n(first as char, run));
+        }
+        rest = &rest[run..];
+    }
+}
+
+impl GraphicsBackend for TerminalBackend {
+    fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+            if *pixel != color {
+                *pixel = color;
+                self.changed();
+            }
+        }
+        Ok(())
+    }
+
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some(self.pixels[y as usize * self.width + x as usize])
+        } else {
+            None
+        }
+    }
+
+    fn cls(&mut self) {
+        self.pixels.fill(0);
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.changed();
+    }
+
+    fn locate(&mut self, row: usize, col: usize) -> Result<()> {
+        if row >= self.height || col >= self.width {
+            return Err(Error::RuntimeError(format!(
+                "LOCATE position out of range: ({}, {})",
+                row, col
+            )));
+        }
+        self.cursor_y = row;
+        self.cursor_x = col;
+        Ok(())
+    }
+
+    fn color(&mut self, _fg: Option<u8>, _bg: Option<u8>) {}
+
+    fn display(&mut self) {
+        if self.dirty {
+            self.refresh();
+        }
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    fn get_cursor(&self) -> (usize, usize) {
+        (self.cursor_y, self.cursor_x)
+    }
+
+    fn set_palette(&mut self, colors: &[u32]) {
+        self.palette = colors.to_vec();
+        self.changed();
+    }
+
+    fn shows_text(&self) -> bool {
+        true
+    }
+
+    fn show_text(&mut self, text: &TextScreen) {
+        let (columns, rows) = (text.columns(), text.rows());
+        let cells = (0..rows)
+            .flat_map(|row| (0..columns).filter_map(move |column| text.cell(row, column)))
+            .map(|cell| (cell.ch, cell.attribute));
+        if self.text_size == (columns, rows) && cells.clone().eq(self.text.iter().copied()) {
+            return;
+        }
+        self.text = cells.collect();
+        self.text_size = (columns, rows);
+        self.changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend that has already been shown once, so rendering gives only
+    /// the cells that changed since
+    fn shown(style: TerminalStyle, width: usize, height: usize) -> TerminalBackend {
+        let mut backend = TerminalBackend::with_terminal_size(width, height, style, TerminalColors::TrueColor, (80, 25));
+        backend.render();
+        backend.last_refresh = Some(Instant::now());
+        backend
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let mut backend = shown(TerminalStyle::HalfBlock, 4, 4);
+        backend.pixels[4 + 2] = 4;
+        // Only cell (2, 0) changed: black above red
+        assert_eq!(backend.render(), "\x1b[1;3H\x1b[38;2;0;0;0m\x1b[48;2;170;0;0m\u{2580}\x1b[0m\x1b[3;1H");
+        assert_eq!(backend.render(), "");
+    }
+
+    #[test]
+    fn test_braille() {
+        let mut backend = shown(TerminalStyle::Braille, 4, 4);
+        backend.pixels[0] = 15;
+        backend.pixels[3 * 4 + 1] = 15;
+        assert_eq!(backend.render(), "\x1b[1;1H\x1b[38;2;255;255;255m\x1b[48;2;0;0;0m\u{2881}\x1b[0m\x1b[2;1H");
+        assert_eq!(ansi256(0xAA, 0x55, 0x00), 16 + 36 * 3 + 6);
+    }
+
+    #[test]
+    fn test_sixel() {
+        let mut backend = TerminalBackend::with_terminal_size(5, 2, TerminalStyle::Sixel, TerminalColors::Ansi256, (80, 25));
+        backend.palette = vec![0x000000, 0xFFFFFF];
+        backend.pixels[1] = 1;
+        backend.pixels[5 + 1] = 1;
+        assert_eq!(
+            backend.sixel(),
+            "\x1bPq\"1;1;5;2#0;2;0;0;0#1;2;100;100;100#0B?BBB$#1?B???-\x1b\\"
+        );
+        let mut out = String::new();
+        push_run_length(&mut out, b"????~");
+        assert_eq!(out, "!4?~");
+    }
+
+    #[test]
+    fn test_fit_to_terminal() {
+        // SCREEN 2 in half blocks on an 80x25 terminal
+        let mut backend = TerminalBackend::with_terminal_size(640, 200, TerminalStyle::HalfBlock, TerminalColors::TrueColor, (80, 25));
+        assert_eq!(backend.grid_size(), (80, 13));
+        // A one-pixel line still shows once scaled down
+        for x in 0..640 {
+            backend.pixels[100 * 640 + x] = 1;
+        }
+        assert_eq!(backend.sample(40, 12), 1);
+        assert_eq!(backend.sample(40, 0), 0);
+    }
+
+    #[test]
+    fn test_text_over_graphics() {
+        let mut backend = shown(TerminalStyle::HalfBlock, 160, 50);
+        let mut text = TextScreen::new(80, 25);
+        text.put(b'H');
+        text.put(b'I');
+        backend.show_text(&text);
+        assert!(backend.dirty);
+        // Scaled down to 80 cells across, one to a text column
+        assert_eq!(backend.render(), "\x1b[1;1H\x1b[38;2;170;170;170m\x1b[48;2;0;0;0mHI\x1b[0m\x1b[14;1H");
+    }
+}
@@ -1,34 +1,18 @@
 //! GUI window graphics backend using minifb
 
 use crate::error::{Error, Result};
-use crate::graphics_backend::GraphicsBackend;
+use crate::graphics_backend::{GraphicsBackend, CGA_RGB};
 use minifb::{Window, WindowOptions};
 use std::time::Duration;
 
-/// Color palette for GW-BASIC (EGA/CGA colors)
-const PALETTE: [u32; 16] = [
-    0x000000, // 0: Black
-    0x0000AA, // 1: Blue
-    0x00AA00, // 2: Green
-    0x00AAAA, // 3: Cyan
-    0xAA0000, // 4: Red
-    0xAA00AA, // 5: Magenta
-    0xAA5500, // 6: Brown
-    0xAAAAAA, // 7: Light Gray
-    0x555555, // 8: Dark Gray
-    0x5555FF, // 9: Light Blue
-    0x55FF55, // 10: Light Green
-    0x55FFFF, // 11: Light Cyan
-    0xFF5555, // 12: Light Red
-    0xFF55FF, // 13: Light Magenta
-    0xFFFF55, // 14: Yellow
-    0xFFFFFF, // 15: White
-];
-
 /// GUI window backend that renders in a real window
 pub struct WindowBackend {
     window: Window,
     buffer: Vec<u32>,
+    /// Colour index of each pixel, for reading pixels back
+    pixels: Vec<u8>,
+    /// Colour shown for each colour index
+    palette: Vec<u32>,
     width: usize,
     height: usize,
     cursor_x: usize,
@@ -55,11 +39,13 @@ impl WindowBackend {
         // Limit update rate
         window.limit_update_rate(Some(Duration::from_micros(16600))); // ~60 FPS
 
-        let buffer = vec![PALETTE[0]; width * height];
+        let buffer = vec![CGA_RGB[0]; width * height];
 
         Ok(WindowBackend {
             window,
             buffer,
+            pixels: vec![0; width * height],
+            palette: CGA_RGB.to_vec(),
             width,
             height,
             cursor_x: 0,
@@ -70,85 +56,38 @@ impl WindowBackend {
         })
     }
 
-    fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
         if x < self.width && y < self.height {
-            self.buffer[y * self.width + x] = color;
+            self.buffer[y * self.width + x] = self.get_color(color);
+            self.pixels[y * self.width + x] = color;
         }
     }
 
     fn get_color(&self, color_index: u8) -> u32 {
-        PALETTE[(color_index as usize) % PALETTE.len()]
+        self.palette[(color_index as usize) % self.palette.len()]
     }
 }
 
 impl GraphicsBackend for WindowBackend {
     fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()> {
         if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
-            let pixel_color = self.get_color(color);
-            self.set_pixel(x as usize, y as usize, pixel_color);
+            self.set_pixel(x as usize, y as usize, color);
         }
         Ok(())
     }
 
-    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
-        // Bresenham's line algorithm
-        let dx = (x2 - x1).abs();
-        let dy = (y2 - y1).abs();
-        let sx = if x1 < x2 { 1 } else { -1 };
-        let sy = if y1 < y2 { 1 } else { -1 };
-        let mut err = dx - dy;
-        let mut x = x1;
-        let mut y = y1;
-
-        loop {
-            self.pset(x, y, color)?;
-            if x == x2 && y == y2 {
-                break;
-            }
-            let e2 = 2 * err;
-            if e2 > -dy {
-                err -= dy;
-                x += sx;
-            }
-            if e2 < dx {
-                err += dx;
-                y += sy;
-            }
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            Some(self.pixels[y as usize * self.width + x as usize])
+        } else {
+            None
         }
-        Ok(())
-    }
-
-    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8) -> Result<()> {
-        // Midpoint circle algorithm
-        let mut dx = radius;
-        let mut dy = 0;
-        let mut err = 0;
-
-        while dx >= dy {
-            self.pset(x + dx, y + dy, color)?;
-            self.pset(x + dy, y + dx, color)?;
-            self.pset(x - dy, y + dx, color)?;
-            self.pset(x - dx, y + dy, color)?;
-            self.pset(x - dx, y - dy, color)?;
-            self.pset(x - dy, y - dx, color)?;
-            self.pset(x + dy, y - dx, color)?;
-            self.pset(x + dx, y - dy, color)?;
-
-            if err <= 0 {
-                dy += 1;
-                err += 2 * dy + 1;
-            }
-            if err > 0 {
-                dx -= 1;
-                err -= 2 * dx + 1;
-            }
-        }
-        Ok(())
     }
 
     fn cls(&mut self) {
         let bg = self.get_color(self.bg_color);
         self.buffer.fill(bg);
+        self.pixels.fill(self.bg_color);
         self.cursor_x = 0;
         self.cursor_y = 0;
     }
@@ -192,6 +131,16 @@ impl GraphicsBackend for WindowBackend {
         !self.window.is_open() || !self.window.get_keys().is_empty()
     }
 
+    fn set_palette(&mut self, colors: &[u32]) {
+        if colors.is_empty() {
+            return;
+        }
+        self.palette = colors.to_vec();
+        for (rgb, &index) in self.buffer.iter_mut().zip(&self.pixels) {
+            *rgb = self.palette[index as usize % self.palette.len()];
+        }
+    }
+
     fn update(&mut self) -> Result<()> {
         // Update window with scaled buffer
         self.window
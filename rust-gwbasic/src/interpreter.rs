@@ -1,19 +1,37 @@
 //! Interpreter for GW-BASIC
 
+use crate::console::Console;
+use crate::diagnostic::Span;
+use crate::draw::{draw, DrawVariable};
 use crate::error::{Error, Result};
-use crate::parser::{AstNode, BinaryOperator, UnaryOperator};
-use crate::value::Value;
-use crate::graphics::Screen;
-use crate::graphics_backend::WindowBackend;
+use crate::lexer::Lexer;
+use crate::parser::{AstNode, BinaryOperator, Coord, LineBox, Parser, PrintItem, UnaryOperator};
+use crate::print_using::format_using;
+use crate::tokenized;
+use crate::value::{string_bytes, Value};
+use crate::graphics::{PutAction, Screen, ScreenMode};
+use crate::graphics_backend::{
+    AsciiBackend, CircleShape, Fill, FramebufferBackend, GraphicsBackend, SvgBackend, TerminalBackend, TerminalColors,
+    TerminalStyle, WindowBackend,
+};
+use crate::recording::Recorder;
+use crate::snapshot::Snapshot;
+use crate::text_screen::TEXT_ROWS;
 use crate::fileio::{FileManager, FileMode};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::io::{self, Write};
+use std::io;
+use std::path::PathBuf;
 
 /// Graphics mode selection
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum GraphicsMode {
     Ascii,
     Gui,
+    Headless,
+    /// SVG written to this file at the end
+    Svg(PathBuf),
+    Terminal(TerminalStyle, TerminalColors),
 }
 
 /// The GW-BASIC interpreter
@@ -21,6 +39,9 @@ pub struct Interpreter {
     /// Variable storage
     variables: HashMap<String, Value>,
 
+    /// Variables handed out by VARPTR$, indexed by their made-up address
+    var_pointers: Vec<String>,
+
     /// Array storage (key: "name_idx1_idx2_...", value: Value)
     arrays: HashMap<String, Value>,
 
@@ -30,6 +51,18 @@ pub struct Interpreter {
     /// Program lines indexed by line number
     lines: HashMap<u32, Vec<AstNode>>,
 
+    /// Source text of each program line, used by LIST and SAVE
+    line_text: HashMap<u32, String>,
+
+    /// Source location of each statement of each program line
+    line_spans: HashMap<u32, Vec<Span>>,
+
+    /// Whether the program was loaded from a protected file
+    protected: bool,
+
+    /// Whether each line number is printed as it runs (TRON)
+    trace: bool,
+
     /// Current execution position
     current_line: Option<u32>,
 
@@ -45,6 +78,13 @@ pub struct Interpreter {
     /// Screen/Graphics manager
     screen: Screen,
 
+    /// Text output, tracking the cursor column for PRINT
+    console: Console,
+
+    /// Printer output for LPRINT: standard output unless captured or sent
+    /// elsewhere with `set_printer`
+    printer: Console,
+
     /// Graphics mode preference
     graphics_mode: GraphicsMode,
 
@@ -54,6 +94,9 @@ pub struct Interpreter {
     /// DATA storage
     data_items: Vec<Value>,
     data_pointer: usize,
+
+    /// Frames of the screen being recorded, if any
+    recorder: Option<Recorder>,
 }
 
 #[derive(Debug, Clone)]
@@ -73,43 +116,159 @@ struct WhileLoopState {
 impl Interpreter {
     /// Create a new interpreter
     pub fn new() -> Self {
-        Interpreter {
-            variables: HashMap::new(),
-            arrays: HashMap::new(),
-            array_dims: HashMap::new(),
-            lines: HashMap::new(),
-            current_line: None,
-            call_stack: Vec::new(),
-            for_stack: Vec::new(),
-            while_stack: Vec::new(),
-            screen: Screen::default(),
-            graphics_mode: GraphicsMode::Ascii,
-            file_manager: FileManager::new(),
-            data_items: Vec::new(),
-            data_pointer: 0,
-        }
+        Self::with_screen(Screen::default(), GraphicsMode::Ascii)
     }
 
     /// Create a new interpreter with GUI window backend
     pub fn new_with_gui() -> Result<Self> {
         let backend = WindowBackend::new(640, 480)?;
-        let screen = Screen::new_with_backend(Box::new(backend));
+        Ok(Self::with_screen(Screen::new_with_backend(Box::new(backend)), GraphicsMode::Gui))
+    }
+
+    /// Create a new interpreter that draws into memory only, for running
+    /// without a display and taking screenshots
+    pub fn new_headless() -> Self {
+        let backend = FramebufferBackend::new(640, 480);
+        Self::with_screen(Screen::new_with_backend(Box::new(backend)), GraphicsMode::Headless)
+    }
 
-        Ok(Interpreter {
+    /// Create a new interpreter that records what it draws as SVG, written
+    /// to `path` when the program ends
+    pub fn new_with_svg(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let backend = SvgBackend::new(640, 480).with_output(path.clone());
+        Self::with_screen(Screen::new_with_backend(Box::new(backend)), GraphicsMode::Svg(path))
+    }
+
+    /// Create a new interpreter that draws graphics live in the terminal
+    pub fn new_with_terminal(style: TerminalStyle, colors: TerminalColors) -> Self {
+        let backend = TerminalBackend::new(640, 480, style, colors);
+        Self::with_screen(Screen::new_with_backend(Box::new(backend)), GraphicsMode::Terminal(style, colors))
+    }
+
+    fn with_screen(screen: Screen, graphics_mode: GraphicsMode) -> Self {
+        let mut console = Console::screen();
+        if screen.shows_text() {
+            console.show_in_graphics();
+        }
+        Interpreter {
             variables: HashMap::new(),
+            var_pointers: Vec::new(),
             arrays: HashMap::new(),
             array_dims: HashMap::new(),
             lines: HashMap::new(),
+            line_text: HashMap::new(),
+            line_spans: HashMap::new(),
+            protected: false,
+            trace: false,
             current_line: None,
             call_stack: Vec::new(),
             for_stack: Vec::new(),
             while_stack: Vec::new(),
             screen,
-            graphics_mode: GraphicsMode::Gui,
+            console,
+            printer: Console::new(),
+            graphics_mode,
             file_manager: FileManager::new(),
             data_items: Vec::new(),
             data_pointer: 0,
-        })
+            recorder: None,
+        }
+    }
+
+    /// The colours currently on display
+    pub fn screenshot(&self) -> Snapshot {
+        self.screen.snapshot()
+    }
+
+    /// Take frames of the screen with `recorder` from now on
+    pub fn start_recording(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Stop recording, taking a last frame of the screen as it is now
+    pub fn finish_recording(&mut self) -> Option<Recorder> {
+        self.record_frame();
+        self.recorder.take()
+    }
+
+    /// Take a frame if recording, as the screen is cleared or flipped
+    fn record_frame(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.capture(&self.screen);
+        }
+    }
+
+    /// After each statement: hand the text screen to a backend that draws
+    /// it, and count the statement towards the recording interval
+    fn statement_done(&mut self) {
+        if let Some(text) = self.console.text_screen() {
+            if self.screen.shows_text() {
+                self.screen.show_text(text);
+            }
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.tick(&self.screen);
+        }
+    }
+
+    /// Bring the display up to date before reading a line typed in
+    fn wait_for_input(&mut self) {
+        if self.screen.shows_text() {
+            if let Some(text) = self.console.text_screen() {
+                self.screen.show_text(text);
+            }
+            self.screen.display();
+        }
+        self.console.waiting_for_input();
+    }
+
+    /// Keep text and printer output in memory instead of writing it to stdout
+    pub fn capture_output(&mut self) {
+        self.console.capture();
+        self.printer.capture();
+    }
+
+    /// Note a line the user typed at a prompt printed outside the
+    /// interpreter, which the terminal echoed, so the text screen keeps up
+    pub fn input_entered(&mut self, line: &str) {
+        self.console.input_entered(line);
+    }
+
+    /// Text output captured since the last call (see `capture_output`)
+    pub fn take_output(&mut self) -> String {
+        self.console.take_output()
+    }
+
+    /// VARPTR$: the type of a variable and its address. There is no real
+    /// memory, so the address is the variable's index in `var_pointers`.
+    fn varptr_string(&mut self, name: &str) -> Value {
+        let address = match self.var_pointers.iter().position(|known| known == name) {
+            Some(address) => address,
+            None => {
+                self.var_pointers.push(name.to_string());
+                self.var_pointers.len() - 1
+            }
+        };
+        let type_code = match name.chars().last() {
+            Some('%') => 2,
+            Some('$') => 3,
+            Some('#') => 8,
+            _ => 4,
+        };
+        let [low, high] = (address as u16).to_le_bytes();
+        Value::String([type_code, low, high].iter().map(|&byte| byte as char).collect())
+    }
+
+    /// Send printer (LPRINT) output to `sink`, where capturing output
+    /// leaves it
+    pub fn set_printer(&mut self, sink: Box<dyn std::io::Write>) {
+        self.printer = Console::printer(sink);
+    }
+
+    /// Printer (LPRINT) output captured since the last call
+    pub fn take_printer_output(&mut self) -> String {
+        self.printer.take_output()
     }
 
     /// Execute a program AST
@@ -117,16 +276,91 @@ impl Interpreter {
         match ast {
             AstNode::Program(nodes) => {
                 for node in nodes {
+                    let stored = matches!(node, AstNode::Line(..));
                     self.execute_node(node)?;
+                    if !stored {
+                        self.statement_done();
+                    }
                 }
             }
             _ => {
                 self.execute_node(ast)?;
+                self.statement_done();
             }
         }
         Ok(())
     }
 
+    /// Remember the source text of the numbered lines in `source` so the
+    /// program can be listed and saved again
+    pub fn record_source(&mut self, source: &str) {
+        for text in source.lines() {
+            let text = text.trim_start();
+            let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                continue;
+            }
+            if let Ok(num) = text[..digits].parse::<u32>() {
+                let body = text[digits..].trim_end();
+                let body = body.strip_prefix(' ').unwrap_or(body);
+                self.line_text.insert(num, body.to_string());
+            }
+        }
+    }
+
+    /// Source listing of the stored program, one numbered line per row
+    pub fn listing(&self) -> Result<String> {
+        let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
+        line_nums.sort();
+
+        let mut listing = String::new();
+        for num in line_nums {
+            let text = self.line_text.get(&num).ok_or_else(|| {
+                Error::RuntimeError(format!("No source text for line {}", num))
+            })?;
+            listing.push_str(&format!("{} {}\n", num, text));
+        }
+        Ok(listing)
+    }
+
+    /// Load a program from source text or a tokenized image, replacing
+    /// the current one
+    pub fn load_program(&mut self, bytes: &[u8]) -> Result<()> {
+        let source = tokenized::decode_program(bytes)?;
+        let tokens = Lexer::new(&source).tokenize()?;
+        // Lines with syntax errors are kept and reported when they are run
+        let (ast, _) = Parser::new(tokens).parse_with_errors();
+
+        self.clear_program();
+        self.execute(ast)?;
+        self.record_source(&source);
+        self.protected = tokenized::detect_format(bytes) == tokenized::ProgramFormat::Protected;
+        Ok(())
+    }
+
+    /// Treat the stored program as loaded from a protected file, so that it
+    /// can be run but not listed or saved in plain form; NEW lifts this
+    pub fn protect(&mut self) {
+        self.protected = true;
+    }
+
+    /// Forget the stored program and everything it set up (NEW)
+    fn clear_program(&mut self) {
+        self.lines.clear();
+        self.line_text.clear();
+        self.line_spans.clear();
+        self.protected = false;
+        self.trace = false;
+        self.variables.clear();
+        self.arrays.clear();
+        self.array_dims.clear();
+        self.for_stack.clear();
+        self.while_stack.clear();
+        self.call_stack.clear();
+        self.data_items.clear();
+        self.data_pointer = 0;
+    }
+
     /// Run the stored line-numbered program
     pub fn run_stored_program(&mut self) -> Result<()> {
         // Get sorted line numbers
@@ -168,24 +402,33 @@ impl Interpreter {
                     break;
                 }
             };
+            if self.trace {
+                self.console.write(&format!("[{}]", current));
+            }
 
             // Execute all statements on this line
-            for stmt in statements {
+            for (index, stmt) in statements.into_iter().enumerate() {
                 match self.execute_node(stmt) {
-                    Ok(_) => {},
+                    Ok(_) => self.statement_done(),
                     Err(Error::ProgramEnd) => {
                         // END statement reached - display graphics before exiting
                         self.screen.display();
 
                         // If using GUI window, keep it open until user closes
-                        while !self.screen.should_close() {
+                        while matches!(self.graphics_mode, GraphicsMode::Gui) && !self.screen.should_close() {
                             self.screen.update()?;
                             std::thread::sleep(std::time::Duration::from_millis(16));
                         }
 
                         return Ok(());
                     }
-                    Err(e) => return Err(e),
+                    Err(e) => {
+                        let span = self.line_spans.get(&current).and_then(|spans| spans.get(index));
+                        return Err(match span {
+                            Some(span) => e.at(*span),
+                            None => e,
+                        });
+                    }
                 }
             }
 
@@ -215,13 +458,20 @@ impl Interpreter {
                 }
                 Ok(())
             }
-            AstNode::Line(num, statements) => {
+            AstNode::Line(num, statements, spans) => {
                 self.lines.insert(num, statements);
+                self.line_spans.insert(num, spans);
                 Ok(())
             }
             
             // Basic I/O
-            AstNode::Print(exprs) => self.execute_print(exprs),
+            AstNode::Print(items) => self.execute_print(&items, None),
+            AstNode::Lprint(items) => {
+                let mut printer = std::mem::take(&mut self.printer);
+                let result = self.execute_print(&items, Some(&mut printer));
+                self.printer = printer;
+                result
+            }
             AstNode::Input(vars) => self.execute_input(vars),
             AstNode::Let(name, expr) => self.execute_let(name, *expr),
             AstNode::ArrayAssign(name, indices, expr) => self.execute_array_assign(name, indices, *expr),
@@ -261,6 +511,7 @@ impl Interpreter {
             // Data
             AstNode::Dim(name, dimensions) => self.execute_dim(name, dimensions),
             AstNode::Rem(_) => Ok(()), // Comments are no-ops
+            AstNode::Invalid(error) => Err(error.without_span().clone()),
             AstNode::Read(vars) => {
                 for var in vars {
                     if self.data_pointer >= self.data_items.len() {
@@ -286,106 +537,164 @@ impl Interpreter {
             
             // Screen/Graphics
             AstNode::Cls => {
+                self.record_frame();
                 self.screen.cls();
-                println!("\x1B[2J\x1B[1;1H"); // ANSI clear screen
+                self.console.clear();
                 Ok(())
             }
-            AstNode::Locate(row, col) => {
-                let r = self.evaluate_expression(&row)?.as_integer()? as usize;
-                let c = self.evaluate_expression(&col)?.as_integer()? as usize;
-                self.screen.locate(r.saturating_sub(1), c.saturating_sub(1))?;
-                Ok(())
+            AstNode::Locate([row, col, cursor, start, stop]) => {
+                // Rows and columns count from 1; 0 or too far is an error
+                let position = |value: Option<i32>| -> Result<Option<usize>> {
+                    match value {
+                        Some(n) if n < 1 => Err(Error::RuntimeError("Illegal function call".to_string())),
+                        n => Ok(n.map(|n| n as usize - 1)),
+                    }
+                };
+                let row = position(self.evaluate_optional_integer(row)?)?;
+                let col = position(self.evaluate_optional_integer(col)?)?;
+                let cursor = match self.evaluate_optional_integer(cursor)? {
+                    Some(n @ 0..=1) => Some(n == 1),
+                    Some(_) => return Err(Error::RuntimeError("Illegal function call".to_string())),
+                    None => None,
+                };
+                let mut scan_line = |node| match self.evaluate_optional_integer(node)? {
+                    Some(n @ 0..=31) => Ok(Some(n as u8)),
+                    Some(_) => Err(Error::RuntimeError("Illegal function call".to_string())),
+                    None => Ok(None),
+                };
+                let (start, stop) = (scan_line(start)?, scan_line(stop)?);
+                if let Some(text) = self.console.text_screen_mut() {
+                    text.set_cursor(cursor, start, stop)?;
+                }
+                self.console.locate(row, col)
             }
             AstNode::Color(fg, bg) => {
-                let fg_val = if let Some(f) = fg {
-                    Some(self.evaluate_expression(&f)?.as_integer()? as u8)
-                } else {
-                    None
-                };
-                let bg_val = if let Some(b) = bg {
-                    Some(self.evaluate_expression(&b)?.as_integer()? as u8)
+                let fg_val = self.evaluate_optional_integer(fg)?;
+                let bg_val = self.evaluate_optional_integer(bg)?;
+                // SCREEN 1 takes a background colour and a CGA palette
+                let mode = *self.screen.mode();
+                if mode.number == 1 {
+                    return self.screen.set_cga_colors(fg_val, bg_val);
+                }
+                // Text mode has blinking foregrounds 16-31; graphics modes
+                // take an attribute and a colour
+                let (fg_range, bg_range) = if mode.number == 0 {
+                    (0..32, 0..16)
                 } else {
-                    None
+                    (0..mode.attributes as i32, 0..mode.colors as i32)
                 };
+                if fg_val.is_some_and(|fg| !fg_range.contains(&fg)) || bg_val.is_some_and(|bg| !bg_range.contains(&bg)) {
+                    return Err(Error::RuntimeError("Illegal function call".to_string()));
+                }
+                let (fg_val, bg_val) = (fg_val.map(|fg| fg as u8), bg_val.map(|bg| bg as u8));
                 self.screen.color(fg_val, bg_val);
+                if let Some(text) = self.console.text_screen_mut() {
+                    let old = text.attribute();
+                    let attribute = if self.screen.mode().number == 0 {
+                        // Foreground 16-31 blinks; the background has 8 colours
+                        let fg = fg_val.map_or(old & 0x8F, |fg| (fg & 0x0F) | ((fg & 0x10) << 3));
+                        let bg = bg_val.map_or(old & 0x70, |bg| (bg & 0x07) << 4);
+                        fg | bg
+                    } else {
+                        fg_val.map_or(old, |fg| fg & 0x0F)
+                    };
+                    text.set_attribute(attribute);
+                }
                 Ok(())
             }
-            AstNode::Screen(mode) => {
-                // Screen mode change
-                let m = self.evaluate_expression(&mode)?.as_integer()?;
-                let (width, height) = match m {
-                    1 => (320, 200),  // SCREEN 1: 320x200 graphics mode (CGA)
-                    2 => (640, 200),  // SCREEN 2: 640x200 high-res monochrome
-                    _ => (80, 25),    // SCREEN 0 or others: 80x25 text mode
+            AstNode::Screen(mode, color_switch, active_page, visual_page) => {
+                let mode = match self.evaluate_optional_integer(mode)? {
+                    Some(number) => *ScreenMode::get(number)?,
+                    None => *self.screen.mode(),
                 };
-
-                // Create screen with appropriate backend based on graphics_mode
-                self.screen = match self.graphics_mode {
-                    GraphicsMode::Gui => {
-                        match WindowBackend::new(width, height) {
-                            Ok(backend) => Screen::new_with_backend(Box::new(backend)),
-                            Err(_) => {
-                                eprintln!("Warning: Failed to create GUI window, falling back to ASCII mode");
-                                Screen::new(width, height)
-                            }
+                // The colour switch only turns the colour burst of a
+                // composite monitor on or off
+                if let Some(switch) = self.evaluate_optional_integer(color_switch)? {
+                    if !(0..=255).contains(&switch) {
+                        return Err(Error::RuntimeError("Illegal function call".to_string()));
+                    }
+                }
+                let mut pages = [None, None];
+                for (page, node) in pages.iter_mut().zip([active_page, visual_page]) {
+                    if let Some(number) = self.evaluate_optional_integer(node)? {
+                        if !(0..mode.pages as i32).contains(&number) {
+                            return Err(Error::RuntimeError("Illegal function call".to_string()));
                         }
+                        *page = Some(number as usize);
                     }
-                    GraphicsMode::Ascii => Screen::new(width, height),
-                };
+                }
+                // The old screen is gone with its mode, and is not recorded
+                if mode.number != self.screen.mode().number {
+                    self.set_screen_mode(&mode)?;
+                } else {
+                    self.record_frame();
+                }
+                // The visual page follows the active page unless given
+                let [active, visual] = pages;
+                self.screen.set_pages(active, visual.or(active))?;
+                self.record_frame();
                 Ok(())
             }
-            AstNode::Width(width) => {
-                // Screen width change - simplified
-                let _w = self.evaluate_expression(&width)?;
+            AstNode::Pcopy(from, to) => {
+                let from = self.evaluate_expression(&from)?.as_integer()?;
+                let to = self.evaluate_expression(&to)?.as_integer()?;
+                if from < 0 || to < 0 {
+                    return Err(Error::RuntimeError("Illegal function call".to_string()));
+                }
+                self.screen.copy_page(from as usize, to as usize)?;
+                self.record_frame();
                 Ok(())
             }
-            AstNode::Pset(x, y, color) => {
-                let x_val = self.evaluate_expression(&x)?.as_integer()?;
-                let y_val = self.evaluate_expression(&y)?.as_integer()?;
-                let c_val = if let Some(c) = color {
-                    Some(self.evaluate_expression(&c)?.as_integer()? as u8)
-                } else {
-                    None
+            AstNode::Width(width) => {
+                let w = self.evaluate_expression(&width)?.as_integer()?;
+                self.console.set_width(w)
+            }
+            AstNode::Pset(coord, color) => {
+                let (x, y) = self.evaluate_coord(&coord)?;
+                let c = self.evaluate_color(color)?;
+                self.screen.pset(x, y, c)
+            }
+            AstNode::DrawLine(start, end, color, shape, style) => {
+                // The end point can STEP from the start point
+                let (x1, y1) = match start {
+                    Some(start) => self.evaluate_coord(&start)?,
+                    None => self.screen.last_point(),
                 };
-                self.screen.pset(x_val, y_val, c_val)?;
-                Ok(())
-            }
-            AstNode::DrawLine(x1, y1, x2, y2, color) => {
-                let x1_val = self.evaluate_expression(&x1)?.as_integer()?;
-                let y1_val = self.evaluate_expression(&y1)?.as_integer()?;
-                let x2_val = self.evaluate_expression(&x2)?.as_integer()?;
-                let y2_val = self.evaluate_expression(&y2)?.as_integer()?;
-                let c_val = if let Some(c) = color {
-                    Some(self.evaluate_expression(&c)?.as_integer()? as u8)
-                } else {
-                    None
+                let (x2, y2) = self.evaluate_coord(&end)?;
+                let c = self.evaluate_color(color)?;
+                let style = match style {
+                    Some(style) => self.evaluate_expression(&style)?.as_integer()? as u16,
+                    None => 0xFFFF,
                 };
-                self.screen.line(x1_val, y1_val, x2_val, y2_val, c_val)?;
-                Ok(())
-            }
-            AstNode::Circle(x, y, radius, color) => {
-                let x_val = self.evaluate_expression(&x)?.as_integer()?;
-                let y_val = self.evaluate_expression(&y)?.as_integer()?;
-                let r_val = self.evaluate_expression(&radius)?.as_integer()?;
-                let c_val = if let Some(c) = color {
-                    Some(self.evaluate_expression(&c)?.as_integer()? as u8)
-                } else {
-                    None
+                match shape {
+                    None => self.screen.line(x1, y1, x2, y2, c, style),
+                    Some(LineBox::Outline) => self.screen.rect(x1, y1, x2, y2, c, style),
+                    Some(LineBox::Filled) => self.screen.fill_rect(x1, y1, x2, y2, c),
+                }
+            }
+            AstNode::Circle(centre, radius, color, start, end, aspect) => {
+                let (x, y) = self.evaluate_coord(&centre)?;
+                let r = self.evaluate_expression(&radius)?.as_double()?;
+                let c = self.evaluate_color(color)?;
+                let start = self.evaluate_angle(start)?;
+                let end = self.evaluate_angle(end)?;
+                let aspect = match aspect {
+                    Some(aspect) => self.evaluate_expression(&aspect)?.as_double()?,
+                    None => self.screen.mode().aspect(),
                 };
-                self.screen.circle(x_val, y_val, r_val, c_val)?;
-                Ok(())
+                self.screen.circle(x, y, r, c, CircleShape { start, end, aspect })
             }
             
             // Sound
             AstNode::Beep => {
-                println!("\x07"); // ASCII bell character
+                self.console.bell();
                 Ok(())
             }
             AstNode::Sound(freq, duration) => {
                 let _f = self.evaluate_expression(&freq)?;
                 let _d = self.evaluate_expression(&duration)?;
-                // Simulated - would play sound
-                println!("\x07");
+                // No sound device: ring the terminal bell instead
+                self.console.bell();
                 Ok(())
             }
             
@@ -411,20 +720,16 @@ impl Interpreter {
                 }
                 Ok(())
             }
-            AstNode::PrintFile(file_num, exprs) => {
+            AstNode::PrintFile(file_num, items) => {
                 let num = self.evaluate_expression(&file_num)?.as_integer()?;
-                let mut output = String::new();
-                for expr in exprs {
-                    let val = self.evaluate_expression(&expr)?;
-                    output.push_str(&val.to_string());
-                }
                 if num == 0 {
                     // Screen output
-                    println!("{}", output);
-                } else {
-                    self.file_manager.write_line(num, &output)?;
+                    return self.execute_print(&items, None);
                 }
-                Ok(())
+                // Files have no line width
+                let mut output = Console::buffered(usize::MAX);
+                self.execute_print(&items, Some(&mut output))?;
+                self.file_manager.write(num, &output.take_output())
             }
             AstNode::InputFile(file_num, vars) => {
                 let num = self.evaluate_expression(&file_num)?.as_integer()?;
@@ -447,11 +752,11 @@ impl Interpreter {
             }
             AstNode::LineInput(vars) => {
                 for var in vars {
-                    use std::io::{self, Write};
-                    print!("? ");
-                    io::stdout().flush().ok();
+                    self.console.write("? ");
                     let mut input = String::new();
+                    self.wait_for_input();
                     io::stdin().read_line(&mut input).ok();
+                    self.console.input_entered(input.trim_end_matches(['\r', '\n']));
                     self.variables.insert(var, Value::String(input.trim().to_string()));
                 }
                 Ok(())
@@ -465,6 +770,9 @@ impl Interpreter {
             
             // Program Control
             AstNode::List(start, end) => {
+                if self.protected {
+                    return Err(Error::RuntimeError("Illegal function call".to_string()));
+                }
                 let mut line_nums: Vec<u32> = self.lines.keys().copied().collect();
                 line_nums.sort();
                 
@@ -480,23 +788,23 @@ impl Interpreter {
                         }
                     }
                     
-                    if let Some(statements) = self.lines.get(&line_num) {
-                        print!("{} ", line_num);
+                    let listed = if let Some(text) = self.line_text.get(&line_num) {
+                        format!("{} {}\n", line_num, text)
+                    } else if let Some(statements) = self.lines.get(&line_num) {
+                        let mut listed = format!("{} ", line_num);
                         for stmt in statements {
-                            print!("{:?} ", stmt);
+                            listed.push_str(&format!("{:?} ", stmt));
                         }
-                        println!();
-                    }
+                        listed + "\n"
+                    } else {
+                        continue;
+                    };
+                    self.console.write(&listed);
                 }
                 Ok(())
             }
             AstNode::New => {
-                self.lines.clear();
-                self.variables.clear();
-                self.for_stack.clear();
-                self.call_stack.clear();
-                self.data_items.clear();
-                self.data_pointer = 0;
+                self.clear_program();
                 Ok(())
             }
             AstNode::Run(start_line) => {
@@ -584,102 +892,190 @@ impl Interpreter {
             }
             
             // Program management
-            AstNode::Load(_filename) => {
-                // Load program from file - stub implementation
-                println!("LOAD: Feature not yet fully implemented");
-                Ok(())
+            AstNode::Load(filename, run) => {
+                let path = program_path(&filename);
+                let bytes = std::fs::read(&path)
+                    .map_err(|e| Error::IoError(format!("Cannot load {}: {}", path, e)))?;
+
+                // LOAD inside a running program ends it; the new program
+                // only runs if ,R was given
+                let running = self.current_line.is_some();
+                self.load_program(&bytes)?;
+                if run {
+                    self.run_stored_program()?;
+                }
+                if running {
+                    Err(Error::ProgramEnd)
+                } else {
+                    Ok(())
+                }
             }
-            AstNode::Save(_filename) => {
-                // Save program to file - stub implementation
-                println!("SAVE: Feature not yet fully implemented");
-                Ok(())
+            AstNode::Save(filename, format) => {
+                let path = program_path(&filename);
+                let listing = self.listing()?;
+                // A protected program can only be saved in protected form
+                let bytes = match format.as_deref() {
+                    Some("A") if self.protected => {
+                        return Err(Error::RuntimeError("Illegal function call".to_string()));
+                    }
+                    Some("A") => {
+                        let mut text = listing.replace('\n', "\r\n").into_bytes();
+                        text.push(0x1A);
+                        text
+                    }
+                    Some(_) => tokenized::protect(&tokenized::tokenize(&listing)?)?,
+                    None if self.protected => tokenized::protect(&tokenized::tokenize(&listing)?)?,
+                    None => tokenized::tokenize(&listing)?,
+                };
+                std::fs::write(&path, bytes)
+                    .map_err(|e| Error::IoError(format!("Cannot save {}: {}", path, e)))
             }
             AstNode::Merge(_filename) => {
-                // Merge program from file - stub implementation
-                println!("MERGE: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Chain(_filename, _line) => {
-                // Chain to another program - stub implementation
-                println!("CHAIN: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Cont => {
-                // Continue execution - stub implementation
-                println!("CONT: Feature not yet fully implemented");
                 Ok(())
             }
             
             // Program editing
             AstNode::Auto(_start, _increment) => {
-                println!("AUTO: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Delete(_start, _end) => {
-                println!("DELETE: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Renum(_new_start, _old_start, _increment) => {
-                println!("RENUM: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Edit(_line) => {
-                println!("EDIT: Feature not yet fully implemented");
                 Ok(())
             }
             AstNode::Tron => {
-                println!("Trace ON");
+                self.trace = true;
                 Ok(())
             }
             AstNode::Troff => {
-                println!("Trace OFF");
+                self.trace = false;
                 Ok(())
             }
             
             // Advanced graphics
-            AstNode::View(_x1, _y1, _x2, _y2) => {
-                println!("VIEW: Setting viewport");
-                Ok(())
-            }
-            AstNode::Window(_x1, _y1, _x2, _y2) => {
-                println!("WINDOW: Setting logical coordinates");
-                Ok(())
-            }
-            AstNode::Preset(x, y, color) => {
-                let x_val = self.evaluate_expression(&x)?.as_integer()?;
-                let y_val = self.evaluate_expression(&y)?.as_integer()?;
-                let c = if let Some(c_expr) = color {
-                    Some(self.evaluate_expression(&c_expr)?.as_integer()? as u8)
-                } else {
-                    None
+            AstNode::ViewPrint(rows) => {
+                let rows = match rows {
+                    Some((top, bottom)) => {
+                        let top = self.evaluate_expression(&top)?.as_integer()?;
+                        let bottom = self.evaluate_expression(&bottom)?.as_integer()?;
+                        if top < 1 || bottom < 1 {
+                            return Err(Error::RuntimeError("Illegal function call".to_string()));
+                        }
+                        Some((top as usize - 1, bottom as usize - 1))
+                    }
+                    None => None,
                 };
-                self.screen.pset(x_val, y_val, c)?;
-                Ok(())
+                self.console.set_view_print(rows)
+            }
+            AstNode::View(corners, screen, fill, border) => {
+                let corners = match corners {
+                    Some((first, second)) => {
+                        let (x1, y1) = self.evaluate_corner(&first)?;
+                        let (x2, y2) = self.evaluate_corner(&second)?;
+                        Some(((x1.round() as i32, y1.round() as i32), (x2.round() as i32, y2.round() as i32)))
+                    }
+                    None => None,
+                };
+                let fill = self.evaluate_color(fill)?;
+                let border = self.evaluate_color(border)?;
+                self.screen.set_view(corners, screen, fill, border)
+            }
+            AstNode::Window(corners, screen) => {
+                let corners = match corners {
+                    Some((first, second)) => Some((self.evaluate_corner(&first)?, self.evaluate_corner(&second)?)),
+                    None => None,
+                };
+                self.screen.set_window(corners, screen)
+            }
+            AstNode::Preset(coord, color) => {
+                // PRESET draws in the background colour unless told otherwise
+                let (x, y) = self.evaluate_coord(&coord)?;
+                let c = self.evaluate_color(color)?.or(Some(0));
+                self.screen.pset(x, y, c)
+            }
+            AstNode::Paint(point, paint, border, background) => {
+                let (x, y) = self.evaluate_coord(&point)?;
+                let paint = match paint {
+                    Some(paint) => Some(self.evaluate_expression(&paint)?),
+                    None => None,
+                };
+                let (fill, default_border) = match paint {
+                    Some(Value::String(tile)) => {
+                        let background = match background {
+                            Some(b) => Some(string_bytes(&self.evaluate_expression(&b)?.as_string_result()?)),
+                            None => None,
+                        };
+                        (self.screen.tile(&string_bytes(&tile), background.as_deref())?, self.screen.foreground())
+                    }
+                    Some(color) => {
+                        let color = self.screen.attribute(color.as_integer()?)?;
+                        (Fill::Solid(color), color)
+                    }
+                    None => (Fill::Solid(self.screen.foreground()), self.screen.foreground()),
+                };
+                let border = self.evaluate_color(border)?.unwrap_or(default_border);
+                self.screen.paint(x, y, &fill, border)
+            }
+            AstNode::Draw(commands) => {
+                let commands = self.evaluate_expression(&commands)?.as_string_result()?;
+                let variables = &self.variables;
+                let pointers = &self.var_pointers;
+                draw(&mut self.screen, &commands, &mut |variable| {
+                    // Names in the string are matched as the lexer reads them
+                    let name = match variable {
+                        DrawVariable::Name(name) => name.to_uppercase(),
+                        DrawVariable::Pointer([_, low, high]) => pointers
+                            .get(u16::from_le_bytes([*low, *high]) as usize)
+                            .ok_or_else(|| Error::RuntimeError("Illegal function call".to_string()))?
+                            .clone(),
+                    };
+                    variables
+                        .get(&name)
+                        .cloned()
+                        .ok_or_else(|| Error::UndefinedError(format!("Variable {} not defined", name)))
+                })
             }
-            AstNode::Paint(_x, _y, _paint_color, _border_color) => {
-                println!("PAINT: Flood fill not yet fully implemented");
-                Ok(())
+            AstNode::GraphicsGet(start, end, array, indices) => {
+                let (x1, y1) = self.evaluate_coord(&start)?;
+                let (x2, y2) = self.evaluate_coord(&end)?;
+                let image = self.screen.get_image(x1, y1, x2, y2)?;
+                self.store_array_bytes(&array, &indices, &image)
             }
-            AstNode::Draw(_draw_string) => {
-                println!("DRAW: Complex shape drawing not yet fully implemented");
-                Ok(())
+            AstNode::GraphicsPut(corner, array, indices, action) => {
+                let (x, y) = self.evaluate_coord(&corner)?;
+                let image = self.array_bytes(&array, &indices)?;
+                self.screen.put_image(x, y, &image, action.unwrap_or(PutAction::Xor))
             }
-            AstNode::GraphicsGet(_x1, _y1, _x2, _y2, _array) => {
-                println!("GET: Graphics block capture not yet fully implemented");
-                Ok(())
+            AstNode::Palette(Some((attribute, color))) => {
+                let attribute = self.evaluate_expression(&attribute)?.as_integer()?;
+                let color = self.evaluate_expression(&color)?.as_integer()?;
+                self.screen.set_palette(attribute, color)
             }
-            AstNode::GraphicsPut(_x, _y, _array, _action) => {
-                println!("PUT: Graphics block display not yet fully implemented");
+            AstNode::Palette(None) => {
+                self.screen.reset_palette();
                 Ok(())
             }
-            AstNode::Palette(_attr, _color) => {
-                println!("PALETTE: Color palette manipulation not yet fully implemented");
-                Ok(())
+            AstNode::PaletteUsing(array, indices) => {
+                let attributes = self.screen.mode().attributes as usize;
+                let mut colors = Vec::new();
+                for key in self.array_keys(&array, &indices)?.into_iter().take(attributes) {
+                    colors.push(self.arrays.get(&key).map_or(Ok(0), Value::as_integer)?);
+                }
+                self.screen.set_palette_using(&colors)
             }
             
             // Sound
             AstNode::Play(_music_string) => {
-                println!("PLAY: Music playback not yet fully implemented");
                 Ok(())
             }
             
@@ -689,118 +1085,88 @@ impl Interpreter {
                 Ok(())
             }
             AstNode::Kill(_filename) => {
-                println!("KILL: File deletion not yet fully implemented");
                 Ok(())
             }
             AstNode::Name(_old_name, _new_name) => {
-                println!("NAME: File rename not yet fully implemented");
                 Ok(())
             }
             AstNode::Files(_filespec) => {
-                println!("FILES: Directory listing not yet fully implemented");
                 Ok(())
             }
             AstNode::Field(_file_number, _field_specs) => {
-                println!("FIELD: Random file buffer definition not yet fully implemented");
                 Ok(())
             }
             AstNode::Lset(_var, _expr) => {
-                println!("LSET: Left-justify in field not yet fully implemented");
                 Ok(())
             }
             AstNode::Rset(_var, _expr) => {
-                println!("RSET: Right-justify in field not yet fully implemented");
                 Ok(())
             }
             AstNode::FileGet(_file_number, _record_number) => {
-                println!("GET: Read record not yet fully implemented");
                 Ok(())
             }
             AstNode::FilePut(_file_number, _record_number) => {
-                println!("PUT: Write record not yet fully implemented");
-                Ok(())
-            }
-            AstNode::PrintUsing(_format, _exprs) => {
-                println!("PRINT USING: Formatted output not yet fully implemented");
                 Ok(())
             }
             AstNode::Write(exprs) => {
-                for (i, expr) in exprs.iter().enumerate() {
-                    let value = self.evaluate_expression(expr)?;
-                    if let Value::String(s) = value {
-                        print!("\"{}\"", s);
-                    } else {
-                        print!("{}", value);
-                    }
-                    if i < exprs.len() - 1 {
-                        print!(",");
+                let mut parts = Vec::new();
+                for expr in exprs {
+                    match self.evaluate_expression(&expr)? {
+                        Value::String(s) => parts.push(format!("\"{}\"", s)),
+                        value => parts.push(value.to_string()),
                     }
                 }
-                println!();
+                self.console.write(&parts.join(","));
+                self.console.newline();
                 Ok(())
             }
             
             // Variable type declarations
             AstNode::DefStr(_start, _end) => {
-                println!("DEFSTR: Variable type declaration not yet fully implemented");
                 Ok(())
             }
             AstNode::DefInt(_start, _end) => {
-                println!("DEFINT: Variable type declaration not yet fully implemented");
                 Ok(())
             }
             AstNode::DefSng(_start, _end) => {
-                println!("DEFSNG: Variable type declaration not yet fully implemented");
                 Ok(())
             }
             AstNode::DefDbl(_start, _end) => {
-                println!("DEFDBL: Variable type declaration not yet fully implemented");
                 Ok(())
             }
             AstNode::OptionBase(_base) => {
-                println!("OPTION BASE: Array base setting not yet fully implemented");
                 Ok(())
             }
             
             // System/Hardware
             AstNode::Key(_key_number, _string) => {
-                println!("KEY: Function key definition not yet fully implemented");
                 Ok(())
             }
             AstNode::KeyOn => {
-                println!("KEY ON: Function key display enabled");
                 Ok(())
             }
             AstNode::KeyOff => {
-                println!("KEY OFF: Function key display disabled");
                 Ok(())
             }
             AstNode::KeyList => {
-                println!("KEY LIST: Listing function keys");
                 Ok(())
             }
             AstNode::OnKey(_key_number, _line_number) => {
-                println!("ON KEY: Function key trap not yet fully implemented");
                 Ok(())
             }
             AstNode::DefSeg(_segment) => {
-                println!("DEF SEG: Segment definition not yet fully implemented");
                 Ok(())
             }
             AstNode::Bload(_filename, _offset) => {
-                println!("BLOAD: Binary load not yet fully implemented");
                 Ok(())
             }
             AstNode::Bsave(_filename, _offset, _length) => {
-                println!("BSAVE: Binary save not yet fully implemented");
                 Ok(())
             }
             AstNode::Call(_address, _params) => {
-                println!("CALL: Machine language call not yet fully implemented");
                 Ok(())
             }
             AstNode::Usr(_address) => {
-                println!("USR: User function call not yet fully implemented");
                 Ok(())
             }
             
@@ -808,19 +1174,124 @@ impl Interpreter {
         }
     }
 
-    fn execute_print(&mut self, exprs: Vec<AstNode>) -> Result<()> {
-        for (i, expr) in exprs.iter().enumerate() {
-            let value = self.evaluate_expression(expr)?;
-            print!("{}", value);
-            
-            if i < exprs.len() - 1 {
-                print!(" ");
+    /// Print a PRINT list to the screen, or to `file` for PRINT#. A newline
+    /// follows unless the list ends with a separator, TAB or SPC.
+    fn execute_print(&mut self, items: &[PrintItem], mut file: Option<&mut Console>) -> Result<()> {
+        let mut newline = true;
+        for item in items {
+            // Evaluate first: the expression may itself look at the cursor
+            match item {
+                PrintItem::Expr(expr) => {
+                    let text = self.evaluate_expression(expr)?.to_print_string();
+                    file.as_deref_mut().unwrap_or(&mut self.console).write_item(&text);
+                    newline = true;
+                }
+                PrintItem::Tab(expr) => {
+                    let n = self.evaluate_expression(expr)?.as_integer()?;
+                    file.as_deref_mut().unwrap_or(&mut self.console).tab(n);
+                    newline = false;
+                }
+                PrintItem::Spc(expr) => {
+                    let n = self.evaluate_expression(expr)?.as_integer()?;
+                    file.as_deref_mut().unwrap_or(&mut self.console).spc(n);
+                    newline = false;
+                }
+                PrintItem::Using(format, values) => {
+                    let format = self.evaluate_expression(format)?;
+                    let Value::String(format) = format else {
+                        return Err(Error::TypeError("Type mismatch".to_string()));
+                    };
+                    let values = values
+                        .iter()
+                        .map(|value| self.evaluate_expression(value))
+                        .collect::<Result<Vec<_>>>()?;
+                    let text = format_using(&format, &values)?;
+                    file.as_deref_mut().unwrap_or(&mut self.console).write(&text);
+                    newline = true;
+                }
+                PrintItem::Comma => {
+                    file.as_deref_mut().unwrap_or(&mut self.console).next_zone();
+                    newline = false;
+                }
+                PrintItem::Semicolon => newline = false,
             }
         }
-        println!();
+        if newline {
+            file.unwrap_or(&mut self.console).newline();
+        }
         Ok(())
     }
 
+    /// Evaluate graphics coordinates and make them the last point referenced
+    fn evaluate_coord(&mut self, coord: &Coord) -> Result<(i32, i32)> {
+        let x = self.evaluate_expression(&coord.x)?.as_double()?;
+        let y = self.evaluate_expression(&coord.y)?.as_double()?;
+        Ok(self.screen.resolve(x, y, coord.step))
+    }
+
+    /// Evaluate a VIEW or WINDOW corner, which does not move the last point
+    fn evaluate_corner(&mut self, coord: &Coord) -> Result<(f64, f64)> {
+        let x = self.evaluate_expression(&coord.x)?.as_double()?;
+        let y = self.evaluate_expression(&coord.y)?.as_double()?;
+        Ok((x, y))
+    }
+
+    /// Evaluate an optional CIRCLE angle, which must be within one turn
+    fn evaluate_angle(&mut self, angle: Option<Box<AstNode>>) -> Result<Option<f64>> {
+        let Some(angle) = angle else {
+            return Ok(None);
+        };
+        let angle = self.evaluate_expression(&angle)?.as_double()?;
+        if angle.abs() > std::f64::consts::TAU {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        Ok(Some(angle))
+    }
+
+    /// Evaluate an optional colour argument
+    fn evaluate_optional_integer(&mut self, node: Option<Box<AstNode>>) -> Result<Option<i32>> {
+        match node {
+            Some(node) => Ok(Some(self.evaluate_expression(&node)?.as_integer()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Switch to another SCREEN mode, which clears the screen and resets
+    /// VIEW, WINDOW and the text width
+    fn set_screen_mode(&mut self, mode: &ScreenMode) -> Result<()> {
+        let (width, height) = mode.size();
+        let backend: Box<dyn GraphicsBackend> = match &self.graphics_mode {
+            GraphicsMode::Gui => match WindowBackend::new(width, height) {
+                Ok(backend) => Box::new(backend),
+                Err(_) => {
+                    eprintln!("Warning: Failed to create GUI window, falling back to ASCII mode");
+                    Box::new(AsciiBackend::new(width, height))
+                }
+            },
+            GraphicsMode::Ascii => Box::new(AsciiBackend::new(width, height)),
+            GraphicsMode::Headless => Box::new(FramebufferBackend::new(width, height)),
+            GraphicsMode::Svg(path) => Box::new(SvgBackend::new(width, height).with_output(path.clone())),
+            GraphicsMode::Terminal(style, colors) => Box::new(TerminalBackend::new(width, height, *style, *colors)),
+        };
+        self.screen = Screen::with_mode(mode, backend);
+        if let Some(text) = self.console.text_screen_mut() {
+            text.resize(mode.text_columns, TEXT_ROWS);
+            text.set_attribute(mode.foreground);
+        }
+        self.console.clear();
+        self.console.set_width(mode.text_columns as i32)
+    }
+
+    fn evaluate_color(&mut self, color: Option<Box<AstNode>>) -> Result<Option<u8>> {
+        match color {
+            Some(c) => {
+                let value = self.evaluate_expression(&c)?.as_integer()?;
+                Ok(Some(self.screen.attribute(value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
     fn execute_let(&mut self, name: String, expr: AstNode) -> Result<()> {
         let value = self.evaluate_expression(&expr)?;
         self.variables.insert(name, value);
@@ -849,6 +1320,91 @@ impl Interpreter {
         Ok(())
     }
 
+    /// Keys of an array's elements in memory order, where the first index
+    /// varies fastest, starting at the given element
+    fn array_keys(&mut self, name: &str, indices: &[AstNode]) -> Result<Vec<String>> {
+        let mut start = Vec::new();
+        for index in indices {
+            start.push(self.evaluate_expression(index)?.as_integer()? as usize);
+        }
+        // Arrays used without DIM have 11 elements per dimension
+        let dims = self
+            .array_dims
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| vec![11; start.len().max(1)]);
+        if start.is_empty() {
+            start = vec![0; dims.len()];
+        }
+        if start.len() != dims.len() || start.iter().zip(&dims).any(|(i, size)| i >= size) {
+            return Err(Error::RuntimeError("Subscript out of range".to_string()));
+        }
+
+        let total: usize = dims.iter().product();
+        let first = start.iter().zip(&dims).rev().fold(0, |offset, (i, size)| offset * size + i);
+        Ok((first..total)
+            .map(|mut offset| {
+                let mut key = name.to_string();
+                for size in &dims {
+                    key.push_str(&format!("_{}", offset % size));
+                    offset /= size;
+                }
+                key
+            })
+            .collect())
+    }
+
+    /// Bytes per element of an array, from its type suffix
+    fn array_element_size(name: &str) -> Result<usize> {
+        match name.chars().last() {
+            Some('%') => Ok(2),
+            Some('#') => Ok(8),
+            Some('$') => Err(Error::TypeError("Type mismatch".to_string())),
+            _ => Ok(4),
+        }
+    }
+
+    /// The raw bytes of an array from the given element onwards, as graphics
+    /// PUT reads them
+    fn array_bytes(&mut self, name: &str, indices: &[AstNode]) -> Result<Vec<u8>> {
+        let size = Self::array_element_size(name)?;
+        let mut bytes = Vec::new();
+        for key in self.array_keys(name, indices)? {
+            let value = self.arrays.get(&key).cloned().unwrap_or(Value::Integer(0));
+            // Take the bits of floats as stored, so that sprite data which
+            // happens to look like NaN survives
+            match (size, value) {
+                (2, value) => bytes.extend_from_slice(&(value.as_integer()? as i16).to_le_bytes()),
+                (4, Value::Single(f)) => bytes.extend_from_slice(&f.to_bits().to_le_bytes()),
+                (4, value) => bytes.extend_from_slice(&(value.as_double()? as f32).to_bits().to_le_bytes()),
+                (_, Value::Double(d)) => bytes.extend_from_slice(&d.to_bits().to_le_bytes()),
+                (_, value) => bytes.extend_from_slice(&value.as_double()?.to_bits().to_le_bytes()),
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Store raw bytes into an array from the given element onwards, as
+    /// graphics GET does
+    fn store_array_bytes(&mut self, name: &str, indices: &[AstNode], bytes: &[u8]) -> Result<()> {
+        let size = Self::array_element_size(name)?;
+        let keys = self.array_keys(name, indices)?;
+        if keys.len() * size < bytes.len() {
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+        for (key, chunk) in keys.into_iter().zip(bytes.chunks(size)) {
+            let mut element = [0u8; 8];
+            element[..chunk.len()].copy_from_slice(chunk);
+            let value = match size {
+                2 => Value::Integer(i16::from_le_bytes([element[0], element[1]]) as i32),
+                4 => Value::Single(f32::from_bits(u32::from_le_bytes([element[0], element[1], element[2], element[3]]))),
+                _ => Value::Double(f64::from_bits(u64::from_le_bytes(element))),
+            };
+            self.arrays.insert(key, value);
+        }
+        Ok(())
+    }
+
     fn execute_if(
         &mut self,
         condition: AstNode,
@@ -1066,11 +1622,13 @@ impl Interpreter {
 
     fn execute_input(&mut self, vars: Vec<String>) -> Result<()> {
         for var in vars {
-            print!("? ");
-            io::stdout().flush().unwrap();
+            self.console.write("? ");
 
             let mut input = String::new();
-            match io::stdin().read_line(&mut input) {
+            self.wait_for_input();
+            let read = io::stdin().read_line(&mut input);
+            self.console.input_entered(input.trim_end_matches(['\r', '\n']));
+            match read {
                 Ok(_) => {
                     let input = input.trim();
 
@@ -1160,21 +1718,21 @@ impl Interpreter {
                 if left.is_string() || right.is_string() {
                     Ok(Value::String(format!("{}{}", left.as_string(), right.as_string())))
                 } else {
-                    Ok(Value::Double(left.as_double()? + right.as_double()?))
+                    Ok(numeric_result(&left, &right, left.as_double()? + right.as_double()?, true))
                 }
             }
             BinaryOperator::Subtract => {
-                Ok(Value::Double(left.as_double()? - right.as_double()?))
+                Ok(numeric_result(&left, &right, left.as_double()? - right.as_double()?, true))
             }
             BinaryOperator::Multiply => {
-                Ok(Value::Double(left.as_double()? * right.as_double()?))
+                Ok(numeric_result(&left, &right, left.as_double()? * right.as_double()?, true))
             }
             BinaryOperator::Divide => {
                 let right_val = right.as_double()?;
                 if right_val == 0.0 {
                     Err(Error::DivisionByZero)
                 } else {
-                    Ok(Value::Double(left.as_double()? / right_val))
+                    Ok(numeric_result(&left, &right, left.as_double()? / right_val, false))
                 }
             }
             BinaryOperator::IntDivide => {
@@ -1194,26 +1752,14 @@ impl Interpreter {
                 }
             }
             BinaryOperator::Power => {
-                Ok(Value::Double(left.as_double()?.powf(right.as_double()?)))
-            }
-            BinaryOperator::Equal => {
-                Ok(Value::Integer(if left.as_double()? == right.as_double()? { -1 } else { 0 }))
-            }
-            BinaryOperator::NotEqual => {
-                Ok(Value::Integer(if left.as_double()? != right.as_double()? { -1 } else { 0 }))
-            }
-            BinaryOperator::LessThan => {
-                Ok(Value::Integer(if left.as_double()? < right.as_double()? { -1 } else { 0 }))
-            }
-            BinaryOperator::GreaterThan => {
-                Ok(Value::Integer(if left.as_double()? > right.as_double()? { -1 } else { 0 }))
-            }
-            BinaryOperator::LessEqual => {
-                Ok(Value::Integer(if left.as_double()? <= right.as_double()? { -1 } else { 0 }))
-            }
-            BinaryOperator::GreaterEqual => {
-                Ok(Value::Integer(if left.as_double()? >= right.as_double()? { -1 } else { 0 }))
-            }
+                Ok(numeric_result(&left, &right, left.as_double()?.powf(right.as_double()?), false))
+            }
+            BinaryOperator::Equal => Ok(truth(compare(&left, &right)?.is_eq())),
+            BinaryOperator::NotEqual => Ok(truth(compare(&left, &right)?.is_ne())),
+            BinaryOperator::LessThan => Ok(truth(compare(&left, &right)?.is_lt())),
+            BinaryOperator::GreaterThan => Ok(truth(compare(&left, &right)?.is_gt())),
+            BinaryOperator::LessEqual => Ok(truth(compare(&left, &right)?.is_le())),
+            BinaryOperator::GreaterEqual => Ok(truth(compare(&left, &right)?.is_ge())),
             BinaryOperator::And => {
                 let l = left.as_integer()?;
                 let r = right.as_integer()?;
@@ -1245,7 +1791,7 @@ impl Interpreter {
     fn evaluate_unary_op(&mut self, op: &UnaryOperator, val: Value) -> Result<Value> {
         match op {
             UnaryOperator::Negate => {
-                Ok(Value::Double(-val.as_double()?))
+                Ok(numeric_result(&val, &val, -val.as_double()?, true))
             }
             UnaryOperator::Not => {
                 Ok(Value::Integer(!val.as_integer()?))
@@ -1276,6 +1822,14 @@ impl Interpreter {
             return Ok(self.arrays.get(&key).cloned().unwrap_or(Value::Integer(0)));
         }
 
+        // VARPTR$ takes a variable rather than its value
+        if name.eq_ignore_ascii_case("VARPTR$") {
+            return match args {
+                [AstNode::Variable(var)] => Ok(self.varptr_string(var)),
+                _ => Err(Error::RuntimeError("Illegal function call".to_string())),
+            };
+        }
+
         // Evaluate all arguments for function calls
         let eval_args: Vec<Value> = args.iter()
             .map(|arg| self.evaluate_expression(arg))
@@ -1469,7 +2023,7 @@ impl Interpreter {
                 ucase_fn(eval_args[0].clone())
             }
             "INPUT$" | "INPUT" => {
-                if eval_args.len() < 1 || eval_args.len() > 2 {
+                if eval_args.is_empty() || eval_args.len() > 2 {
                     return Err(Error::RuntimeError("INPUT$ requires 1 or 2 arguments".to_string()));
                 }
                 let file_num = if eval_args.len() == 2 {
@@ -1576,17 +2130,24 @@ impl Interpreter {
                 }
                 time_fn()
             }
+            "PMAP" => {
+                if eval_args.len() != 2 {
+                    return Err(Error::RuntimeError("PMAP requires 2 arguments".to_string()));
+                }
+                let value = self.screen.pmap(eval_args[0].as_double()?, eval_args[1].as_integer()?)?;
+                Ok(Value::Single(value as f32))
+            }
             "POS" => {
                 if eval_args.len() != 1 {
                     return Err(Error::RuntimeError("POS requires 1 argument".to_string()));
                 }
-                pos_fn(eval_args[0].clone())
+                Ok(Value::Integer(self.console.column() as i32 + 1))
             }
             "CSRLIN" => {
                 if !eval_args.is_empty() {
                     return Err(Error::RuntimeError("CSRLIN requires 0 arguments".to_string()));
                 }
-                csrlin_fn()
+                Ok(Value::Integer(self.console.row() as i32 + 1))
             }
             "EOF" => {
                 if eval_args.len() != 1 {
@@ -1606,22 +2167,33 @@ impl Interpreter {
                 }
                 lof_fn(eval_args[0].clone())
             }
-            "POINT" => {
-                if eval_args.len() != 2 {
-                    return Err(Error::RuntimeError("POINT requires 2 arguments".to_string()));
+            "POINT" => match eval_args.as_slice() {
+                // POINT(n) reads back the last point
+                [function] => {
+                    let value = self.screen.last_point_coordinate(function.as_integer()?)?;
+                    Ok(Value::Single(value as f32))
                 }
-                point_fn(eval_args[0].clone(), eval_args[1].clone())
-            }
+                [x, y] => Ok(Value::Integer(self.screen.point_logical(x.as_double()?, y.as_double()?))),
+                _ => Err(Error::RuntimeError("POINT requires 1 or 2 arguments".to_string())),
+            },
             "SCREEN" => {
                 if eval_args.len() < 2 || eval_args.len() > 3 {
                     return Err(Error::RuntimeError("SCREEN requires 2 or 3 arguments".to_string()));
                 }
-                let color_num = if eval_args.len() == 3 {
-                    Some(eval_args[2].clone())
-                } else {
-                    None
+                // The character code at a row and column, or its attribute
+                // if the third argument is not 0
+                let row = eval_args[0].as_integer()?;
+                let col = eval_args[1].as_integer()?;
+                let attribute = match eval_args.get(2) {
+                    Some(z) => z.as_integer()? != 0,
+                    None => false,
+                };
+                let cell = match (usize::try_from(row - 1), usize::try_from(col - 1)) {
+                    (Ok(row), Ok(col)) => self.console.text_screen().and_then(|text| text.cell(row, col)),
+                    _ => None,
                 };
-                screen_fn(eval_args[0].clone(), eval_args[1].clone(), color_num)
+                let cell = cell.ok_or_else(|| Error::RuntimeError("Illegal function call".to_string()))?;
+                Ok(Value::Integer(if attribute { cell.attribute } else { cell.ch } as i32))
             }
             
             // Error handling functions
@@ -1693,10 +2265,9 @@ impl Interpreter {
                 // Extract index from function name (USR0-USR9)
                 let index = if name.len() > 3 {
                     // Safe: We've already matched against USR0-USR9, so last char is a digit
-                    match name.chars().last().and_then(|c| c.to_digit(10)) {
-                        Some(digit) => Some(Value::Integer(digit as i32)),
-                        None => None,
-                    }
+                    name.chars().last()
+                        .and_then(|c| c.to_digit(10))
+                        .map(|digit| Value::Integer(digit as i32))
                 } else {
                     None
                 };
@@ -1736,6 +2307,51 @@ impl Default for Interpreter {
     }
 }
 
+/// Type an arithmetic result after the wider of its operands: double if
+/// either is double, otherwise single. Integer operations stay integer when
+/// `integer_ok` is set and the result fits, and are promoted to single
+/// otherwise, as integer constants are.
+fn numeric_result(left: &Value, right: &Value, result: f64, integer_ok: bool) -> Value {
+    match (left, right) {
+        (Value::Double(_), _) | (_, Value::Double(_)) => Value::Double(result),
+        (Value::Integer(_), Value::Integer(_))
+            if integer_ok && (-32768.0..=32767.0).contains(&result) =>
+        {
+            Value::Integer(result as i32)
+        }
+        (Value::Integer(_) | Value::Single(_), Value::Integer(_) | Value::Single(_)) => {
+            Value::Single(result as f32)
+        }
+        _ => Value::Double(result),
+    }
+}
+
+/// Order two values for a relational operator: strings by character codes,
+/// numbers by value, and "Type mismatch" for a string against a number
+fn compare(left: &Value, right: &Value) -> Result<Ordering> {
+    match (left, right) {
+        (Value::String(l), Value::String(r)) => Ok(l.cmp(r)),
+        (Value::String(_), _) | (_, Value::String(_)) => Err(Error::TypeError("Type mismatch".to_string())),
+        _ => Ok(left.as_double()?.partial_cmp(&right.as_double()?).unwrap_or(Ordering::Equal)),
+    }
+}
+
+/// BASIC truth value: -1 for true, 0 for false
+fn truth(condition: bool) -> Value {
+    Value::Integer(if condition { -1 } else { 0 })
+}
+
+/// Program files get a .BAS extension when none is given, unless a file
+/// with exactly that name already exists
+fn program_path(filename: &str) -> String {
+    let has_extension = std::path::Path::new(filename).extension().is_some();
+    if has_extension || std::path::Path::new(filename).exists() {
+        filename.to_string()
+    } else {
+        format!("{}.BAS", filename)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2023,4 +2639,29 @@ mod tests {
         // X = 1, so should call 100, then return and set Y = 99
         assert_eq!(interp.variables.get("Y").unwrap().as_integer().unwrap(), 99);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_load_tokenized_program() {
+        let mut interp = Interpreter::new();
+        let image = tokenized::tokenize("10 X = 5\n20 Y = X * 2\n").unwrap();
+
+        interp.load_program(&image).unwrap();
+        interp.run_stored_program().unwrap();
+        assert_eq!(interp.variables.get("Y").unwrap().as_integer().unwrap(), 10);
+        assert_eq!(interp.listing().unwrap(), "10 X = 5\n20 Y = X * 2\n");
+    }
+
+    #[test]
+    fn test_protected_program_cannot_be_listed() {
+        let mut interp = Interpreter::new();
+        let image = tokenized::tokenize("10 X = 7\n").unwrap();
+
+        interp.load_program(&tokenized::protect(&image).unwrap()).unwrap();
+        interp.run_stored_program().unwrap();
+        assert_eq!(interp.variables.get("X").unwrap().as_integer().unwrap(), 7);
+        assert!(interp.execute(AstNode::List(None, None)).is_err());
+
+        interp.execute(AstNode::New).unwrap();
+        assert!(interp.execute(AstNode::List(None, None)).is_ok());
+    }
+}
@@ -1,17 +1,21 @@
 //! Lexical analyzer for GW-BASIC
 
+use crate::diagnostic::Span;
 use crate::error::{Error, Result};
+use crate::tokenized::{self, Constant};
 
 /// Token types in GW-BASIC
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
     // Literals
     Integer(i32),
-    Float(f64),
+    Single(f32),
+    Double(f64),
     String(String),
     
     // Keywords - Control Flow
     Print,
+    Lprint,
     Let,
     If,
     Then,
@@ -73,6 +77,7 @@ pub enum TokenType {
     Draw,
     Get,
     Put,
+    Pcopy,
     
     // Keywords - Sound
     Beep,
@@ -170,6 +175,7 @@ pub enum TokenType {
     // Other
     Identifier(String),
     LineNumber(u32),
+    Unknown(char),  // Character with no meaning, reported by the parser
     Newline,
     Eof,
 }
@@ -180,11 +186,24 @@ pub struct Token {
     pub token_type: TokenType,
     pub line: usize,
     pub column: usize,
+    /// Column just past the last character of the token
+    pub end_column: usize,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, line: usize, column: usize) -> Self {
-        Token { token_type, line, column }
+        Token { token_type, line, column, end_column: column + 1 }
+    }
+
+    /// Location of this token in the source
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line,
+            line_number: None,
+            statement: 0,
+            start_column: self.column,
+            end_column: self.end_column,
+        }
     }
 }
 
@@ -194,6 +213,10 @@ pub struct Lexer {
     position: usize,
     line: usize,
     column: usize,
+    /// Inside a DATA statement no reserved words are recognised
+    in_data: bool,
+    /// Token to hand out before reading more input
+    pending: Option<Token>,
 }
 
 impl Lexer {
@@ -204,13 +227,38 @@ impl Lexer {
             position: 0,
             line: 1,
             column: 1,
+            in_data: false,
+            pending: None,
         }
     }
 
     /// Get the next token from the input
     pub fn next_token(&mut self) -> Result<Token> {
+        if let Some(token) = self.pending.take() {
+            return Ok(token);
+        }
         self.skip_whitespace();
 
+        let (line, column) = (self.line, self.column);
+        match self.read_token() {
+            Ok(mut token) => {
+                if self.pending.is_none() && self.line == token.line && self.column > token.column {
+                    token.end_column = self.column;
+                }
+                Ok(token)
+            }
+            Err(e) => Err(e.at(Span {
+                line,
+                line_number: None,
+                statement: 0,
+                start_column: column,
+                end_column: self.column.max(column + 1),
+            })),
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Token> {
+
         if self.is_at_end() {
             return Ok(Token::new(TokenType::Eof, self.line, self.column));
         }
@@ -225,7 +273,7 @@ impl Lexer {
         }
 
         // Numbers
-        if ch.is_ascii_digit() {
+        if ch.is_ascii_digit() || ch == '&' || (ch == '.' && self.peek_char(1).is_some_and(|c| c.is_ascii_digit())) {
             return self.read_number();
         }
 
@@ -239,6 +287,20 @@ impl Lexer {
             return self.read_identifier();
         }
 
+        // ' is short for :REM, ? for PRINT
+        if ch == '\'' {
+            self.advance();
+            self.skip_rest_of_line();
+            let mut remark = Token::new(TokenType::Rem, start_line, start_column);
+            remark.end_column = self.column;
+            self.pending = Some(remark);
+            return Ok(Token::new(TokenType::Colon, start_line, start_column));
+        }
+        if ch == '?' {
+            self.advance();
+            return Ok(Token::new(TokenType::Print, start_line, start_column));
+        }
+
         // Operators and delimiters
         let token_type = match ch {
             '+' => { self.advance(); TokenType::Plus }
@@ -250,7 +312,7 @@ impl Lexer {
             '(' => { self.advance(); TokenType::LeftParen }
             ')' => { self.advance(); TokenType::RightParen }
             ',' => { self.advance(); TokenType::Comma }
-            ':' => { self.advance(); TokenType::Colon }
+            ':' => { self.advance(); self.in_data = false; TokenType::Colon }
             ';' => { self.advance(); TokenType::Semicolon }
             '$' => { self.advance(); TokenType::Dollar }
             '%' => { self.advance(); TokenType::Percent }
@@ -283,10 +345,13 @@ impl Lexer {
                 self.advance();
                 self.line += 1;
                 self.column = 1;
+                self.in_data = false;
                 return Ok(token);
             }
             _ => {
-                return Err(Error::SyntaxError(format!("Unexpected character: '{}'", ch)));
+                // Left for the parser to report, so the line can still be stored
+                self.advance();
+                TokenType::Unknown(ch)
             }
         };
 
@@ -297,6 +362,10 @@ impl Lexer {
         self.input[self.position]
     }
 
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        self.input.get(self.position + offset).copied()
+    }
+
     fn advance(&mut self) {
         self.position += 1;
         self.column += 1;
@@ -333,34 +402,23 @@ impl Lexer {
         Ok(Token::new(TokenType::LineNumber(num), start_line, start_column))
     }
 
+    /// Read a numeric constant, typed the way CRUNCH types it (see
+    /// `tokenized::read_constant`). &H and &O constants are 16-bit values,
+    /// so `&HFFFF` is -1.
     fn read_number(&mut self) -> Result<Token> {
         let start_line = self.line;
         let start_column = self.column;
-        let mut num_str = String::new();
-        let mut is_float = false;
 
-        while !self.is_at_end() {
-            let ch = self.current_char();
-            if ch.is_ascii_digit() {
-                num_str.push(ch);
-                self.advance();
-            } else if ch == '.' && !is_float {
-                is_float = true;
-                num_str.push(ch);
-                self.advance();
-            } else {
-                break;
-            }
+        let (constant, len) = tokenized::read_constant(&self.input[self.position..])?;
+        for _ in 0..len {
+            self.advance();
         }
-
-        let token_type = if is_float {
-            let val: f64 = num_str.parse()
-                .map_err(|_| Error::SyntaxError(format!("Invalid float: {}", num_str)))?;
-            TokenType::Float(val)
-        } else {
-            let val: i32 = num_str.parse()
-                .map_err(|_| Error::SyntaxError(format!("Invalid integer: {}", num_str)))?;
-            TokenType::Integer(val)
+        let token_type = match constant {
+            Constant::Integer(value) | Constant::Octal(value) | Constant::Hex(value) => {
+                TokenType::Integer(value as i16 as i32)
+            }
+            Constant::Single(value) => TokenType::Single(value),
+            Constant::Double(value) => TokenType::Double(value),
         };
 
         Ok(Token::new(token_type, start_line, start_column))
@@ -373,173 +431,87 @@ impl Lexer {
         self.advance(); // Skip opening quote
         let mut string = String::new();
 
-        while !self.is_at_end() && self.current_char() != '"' {
+        // As in GW-BASIC, the end of the line also ends a string
+        while !self.is_at_end() && self.current_char() != '"' && self.current_char() != '\n' {
             string.push(self.current_char());
             self.advance();
         }
 
-        if self.is_at_end() {
-            return Err(Error::SyntaxError("Unterminated string".to_string()));
+        if !self.is_at_end() && self.current_char() == '"' {
+            self.advance(); // Skip closing quote
         }
-
-        self.advance(); // Skip closing quote
         Ok(Token::new(TokenType::String(string), start_line, start_column))
     }
 
+    /// Read a name or reserved word. Reserved words are recognised without
+    /// surrounding spaces the way CRUNCH does it (see
+    /// `tokenized::match_keyword`): `PRINT"HI"` and `IF(A>B)THEN 100` split
+    /// out their keywords, while a word running on into letters or digits
+    /// is a name, so `TOTAL` and `FORM` stay whole. A type suffix does not
+    /// make a name: `NAME$` is NAME followed by `$`.
     fn read_identifier(&mut self) -> Result<Token> {
         let start_line = self.line;
         let start_column = self.column;
-        let mut ident = String::new();
 
-        while !self.is_at_end() {
-            let ch = self.current_char();
-            if ch.is_alphanumeric() || ch == '_' || ch == '$' || ch == '%' {
-                ident.push(ch);
-                self.advance();
-            } else {
-                break;
+        let run_len = self.name_len(self.position);
+        let run: String = self.input[self.position..self.position + run_len].iter().collect();
+
+        // Whole words (and anything inside DATA) keep their plain meaning.
+        // USR has no token of its own, so USR0 stays a name.
+        let (len, word) = match tokenized::match_keyword(&self.input[self.position..]) {
+            Some((word, _)) if !self.in_data && word != "USR" && keyword_token(&run.to_uppercase()).is_none() => {
+                // TAB( and SPC( leave the parenthesis for the argument list
+                let word = word.trim_end_matches('(');
+                // INPUT$ and VARPTR$ are crunched as INPUT and VARPTR
+                // followed by `$`, but are functions of their own
+                let len = match (word, self.input.get(self.position + word.len())) {
+                    ("INPUT" | "VARPTR", Some('$')) => word.len() + 1,
+                    _ => word.len(),
+                };
+                (len, run.chars().take(len).collect())
             }
+            _ => (run_len, run),
+        };
+        for _ in 0..len {
+            self.advance();
         }
 
-        let token_type = match ident.to_uppercase().as_str() {
-            // Control Flow
-            "PRINT" => TokenType::Print,
-            "LET" => TokenType::Let,
-            "IF" => TokenType::If,
-            "THEN" => TokenType::Then,
-            "ELSE" => TokenType::Else,
-            "FOR" => TokenType::For,
-            "NEXT" => TokenType::Next,
-            "TO" => TokenType::To,
-            "STEP" => TokenType::Step,
-            "WHILE" => TokenType::While,
-            "WEND" => TokenType::Wend,
-            "GOTO" => TokenType::Goto,
-            "GOSUB" => TokenType::Gosub,
-            "RETURN" => TokenType::Return,
-            "END" => TokenType::End,
-            "STOP" => TokenType::Stop,
-            "CONT" => TokenType::Cont,
-            
-            // I/O
-            "INPUT" => TokenType::Input,
-            "WRITE" => TokenType::Write,
-            "LINE" => TokenType::Line,
-            "OPEN" => TokenType::Open,
-            "CLOSE" => TokenType::Close,
-            "LOAD" => TokenType::Load,
-            "SAVE" => TokenType::Save,
-            "RUN" => TokenType::Run,
-            "LIST" => TokenType::List,
-            "NEW" => TokenType::New,
-            
-            // Data
-            "DIM" => TokenType::Dim,
-            "REM" => TokenType::Rem,
-            "DATA" => TokenType::Data,
-            "READ" => TokenType::Read,
-            "RESTORE" => TokenType::Restore,
-            "DEFSTR" => TokenType::Defstr,
-            "DEFINT" => TokenType::Defint,
-            "DEFSNG" => TokenType::Defsng,
-            "DEFDBL" => TokenType::Defdbl,
-            
-            // Array/Memory
-            "ERASE" => TokenType::Erase,
-            "CLEAR" => TokenType::Clear,
-            "SWAP" => TokenType::Swap,
-            
-            // Screen/Graphics
-            "CLS" => TokenType::Cls,
-            "LOCATE" => TokenType::Locate,
-            "COLOR" => TokenType::Color,
-            "SCREEN" => TokenType::Screen,
-            "WIDTH" => TokenType::Width,
-            "VIEW" => TokenType::View,
-            "WINDOW" => TokenType::Window,
-            "PSET" => TokenType::Pset,
-            "PRESET" => TokenType::Preset,
-            "CIRCLE" => TokenType::Circle,
-            "PAINT" => TokenType::Paint,
-            "DRAW" => TokenType::Draw,
-            "GET" => TokenType::Get,
-            "PUT" => TokenType::Put,
-            
-            // Sound
-            "BEEP" => TokenType::Beep,
-            "SOUND" => TokenType::Sound,
-            "PLAY" => TokenType::Play,
-            
-            // System
-            "KEY" => TokenType::Key,
-            "ON" => TokenType::On,
-            "OFF" => TokenType::Off,
-            "WAIT" => TokenType::Wait,
-            "RANDOMIZE" => TokenType::Randomize,
-            "TIMER" => TokenType::Timer,
-            "DATE" => TokenType::Date,
-            "TIME" => TokenType::Time,
-            "POKE" => TokenType::Poke,
-            "PEEK" => TokenType::Peek,
-            "OUT" => TokenType::Out,
-            "INP" => TokenType::Inp,
-            "CALL" => TokenType::Call,
-            "USR" => TokenType::Usr,
-            "BLOAD" => TokenType::Bload,
-            "BSAVE" => TokenType::Bsave,
-            "SEG" => TokenType::Seg,
-            "OPTION" => TokenType::Option,
-            "BASE" => TokenType::Base,
-            "PALETTE" => TokenType::Palette,
-            
-            // File Operations
-            "FILES" => TokenType::Files,
-            "KILL" => TokenType::Kill,
-            "NAME" => TokenType::Name,
-            "MERGE" => TokenType::Merge,
-            "CHAIN" => TokenType::Chain,
-            "FIELD" => TokenType::Field,
-            "LSET" => TokenType::Lset,
-            "RSET" => TokenType::Rset,
-            "RESET" => TokenType::Reset,
-            "USING" => TokenType::Using,
-            "AS" => TokenType::As,
-            "APPEND" => TokenType::Append,
-            "RANDOM" => TokenType::Random,
-            "OUTPUT" => TokenType::Output,
-            "BINARY" => TokenType::Binary,
-            
-            // Error Handling
-            "ERROR" => TokenType::Error,
-            "RESUME" => TokenType::Resume,
-            
-            // Functions
-            "DEF" => TokenType::Def,
-            "FN" => TokenType::Fn,
-            
-            // Program Control
-            "AUTO" => TokenType::Auto,
-            "DELETE" => TokenType::Delete,
-            "RENUM" => TokenType::Renum,
-            "EDIT" => TokenType::Edit,
-            "TRON" => TokenType::Tron,
-            "TROFF" => TokenType::Troff,
-            
-            // Logical Operators
-            "AND" => TokenType::And,
-            "OR" => TokenType::Or,
-            "NOT" => TokenType::Not,
-            "XOR" => TokenType::Xor,
-            "EQV" => TokenType::Eqv,
-            "IMP" => TokenType::Imp,
-            "MOD" => TokenType::Mod,
-            
-            _ => TokenType::Identifier(ident),
-        };
+        // Names are not case sensitive: n% and N% are one variable
+        let word = word.to_uppercase();
+        let token_type = keyword_token(&word).unwrap_or(TokenType::Identifier(word));
+        match token_type {
+            TokenType::Rem => self.skip_rest_of_line(),
+            TokenType::Data => self.in_data = true,
+            _ => {}
+        }
 
         Ok(Token::new(token_type, start_line, start_column))
     }
 
+    /// Length of the name starting at `start`: letters, digits and
+    /// underscores, with an optional `$` or `%` type suffix
+    fn name_len(&self, start: usize) -> usize {
+        let mut len = 0;
+        while let Some(&ch) = self.input.get(start + len) {
+            if ch.is_alphanumeric() || ch == '_' {
+                len += 1;
+            } else {
+                if ch == '$' || ch == '%' {
+                    len += 1;
+                }
+                break;
+            }
+        }
+        len
+    }
+
+    /// Skip to the end of the line, past a remark or a bad token
+    fn skip_rest_of_line(&mut self) {
+        while !self.is_at_end() && self.current_char() != '\n' {
+            self.advance();
+        }
+    }
+
     /// Tokenize entire input into a vector of tokens
     pub fn tokenize(&mut self) -> Result<Vec<Token>> {
         let mut tokens = Vec::new();
@@ -553,6 +525,171 @@ impl Lexer {
         }
         Ok(tokens)
     }
+
+    /// Tokenize entire input, collecting errors instead of stopping at the
+    /// first one. The rest of a line with an error is skipped.
+    pub fn tokenize_with_errors(&mut self) -> (Vec<Token>, Vec<Error>) {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match self.next_token() {
+                Ok(token) => {
+                    let is_eof = token.token_type == TokenType::Eof;
+                    tokens.push(token);
+                    if is_eof {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    errors.push(e);
+                    self.skip_rest_of_line();
+                }
+            }
+        }
+        (tokens, errors)
+    }
+}
+
+/// Token for a word the lexer treats as a keyword, if it is one
+fn keyword_token(word: &str) -> Option<TokenType> {
+    let token_type = match word {
+        // Control Flow
+        "PRINT" => TokenType::Print,
+        "LPRINT" => TokenType::Lprint,
+        "LET" => TokenType::Let,
+        "IF" => TokenType::If,
+        "THEN" => TokenType::Then,
+        "ELSE" => TokenType::Else,
+        "FOR" => TokenType::For,
+        "NEXT" => TokenType::Next,
+        "TO" => TokenType::To,
+        "STEP" => TokenType::Step,
+        "WHILE" => TokenType::While,
+        "WEND" => TokenType::Wend,
+        "GOTO" => TokenType::Goto,
+        "GOSUB" => TokenType::Gosub,
+        "RETURN" => TokenType::Return,
+        "END" => TokenType::End,
+        "STOP" => TokenType::Stop,
+        "CONT" => TokenType::Cont,
+        
+        // I/O
+        "INPUT" => TokenType::Input,
+        "WRITE" => TokenType::Write,
+        "LINE" => TokenType::Line,
+        "OPEN" => TokenType::Open,
+        "CLOSE" => TokenType::Close,
+        "LOAD" => TokenType::Load,
+        "SAVE" => TokenType::Save,
+        "RUN" => TokenType::Run,
+        "LIST" => TokenType::List,
+        "NEW" => TokenType::New,
+        
+        // Data
+        "DIM" => TokenType::Dim,
+        "REM" => TokenType::Rem,
+        "DATA" => TokenType::Data,
+        "READ" => TokenType::Read,
+        "RESTORE" => TokenType::Restore,
+        "DEFSTR" => TokenType::Defstr,
+        "DEFINT" => TokenType::Defint,
+        "DEFSNG" => TokenType::Defsng,
+        "DEFDBL" => TokenType::Defdbl,
+        
+        // Array/Memory
+        "ERASE" => TokenType::Erase,
+        "CLEAR" => TokenType::Clear,
+        "SWAP" => TokenType::Swap,
+        
+        // Screen/Graphics
+        "CLS" => TokenType::Cls,
+        "LOCATE" => TokenType::Locate,
+        "COLOR" => TokenType::Color,
+        "SCREEN" => TokenType::Screen,
+        "WIDTH" => TokenType::Width,
+        "VIEW" => TokenType::View,
+        "WINDOW" => TokenType::Window,
+        "PSET" => TokenType::Pset,
+        "PRESET" => TokenType::Preset,
+        "CIRCLE" => TokenType::Circle,
+        "PAINT" => TokenType::Paint,
+        "DRAW" => TokenType::Draw,
+        "GET" => TokenType::Get,
+        "PUT" => TokenType::Put,
+        "PCOPY" => TokenType::Pcopy,
+        
+        // Sound
+        "BEEP" => TokenType::Beep,
+        "SOUND" => TokenType::Sound,
+        "PLAY" => TokenType::Play,
+        
+        // System
+        "KEY" => TokenType::Key,
+        "ON" => TokenType::On,
+        "OFF" => TokenType::Off,
+        "WAIT" => TokenType::Wait,
+        "RANDOMIZE" => TokenType::Randomize,
+        "TIMER" => TokenType::Timer,
+        "DATE" => TokenType::Date,
+        "TIME" => TokenType::Time,
+        "POKE" => TokenType::Poke,
+        "PEEK" => TokenType::Peek,
+        "OUT" => TokenType::Out,
+        "INP" => TokenType::Inp,
+        "CALL" => TokenType::Call,
+        "USR" => TokenType::Usr,
+        "BLOAD" => TokenType::Bload,
+        "BSAVE" => TokenType::Bsave,
+        "SEG" => TokenType::Seg,
+        "OPTION" => TokenType::Option,
+        "BASE" => TokenType::Base,
+        "PALETTE" => TokenType::Palette,
+        
+        // File Operations
+        "FILES" => TokenType::Files,
+        "KILL" => TokenType::Kill,
+        "NAME" => TokenType::Name,
+        "MERGE" => TokenType::Merge,
+        "CHAIN" => TokenType::Chain,
+        "FIELD" => TokenType::Field,
+        "LSET" => TokenType::Lset,
+        "RSET" => TokenType::Rset,
+        "RESET" => TokenType::Reset,
+        "USING" => TokenType::Using,
+        "AS" => TokenType::As,
+        "APPEND" => TokenType::Append,
+        "RANDOM" => TokenType::Random,
+        "OUTPUT" => TokenType::Output,
+        "BINARY" => TokenType::Binary,
+        
+        // Error Handling
+        "ERROR" => TokenType::Error,
+        "RESUME" => TokenType::Resume,
+        
+        // Functions
+        "DEF" => TokenType::Def,
+        "FN" => TokenType::Fn,
+        
+        // Program Control
+        "AUTO" => TokenType::Auto,
+        "DELETE" => TokenType::Delete,
+        "RENUM" => TokenType::Renum,
+        "EDIT" => TokenType::Edit,
+        "TRON" => TokenType::Tron,
+        "TROFF" => TokenType::Troff,
+        
+        // Logical Operators
+        "AND" => TokenType::And,
+        "OR" => TokenType::Or,
+        "NOT" => TokenType::Not,
+        "XOR" => TokenType::Xor,
+        "EQV" => TokenType::Eqv,
+        "IMP" => TokenType::Imp,
+        "MOD" => TokenType::Mod,
+        
+        _ => return None,
+    };
+    Some(token_type)
 }
 
 #[cfg(test)]
@@ -610,4 +747,173 @@ mod tests {
         assert_eq!(tokens[0].token_type, TokenType::LineNumber(10));
         assert_eq!(tokens[1].token_type, TokenType::Print);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_radix_literals() {
+        let mut lexer = Lexer::new("&HB800 &O777 &17 &HFFFF");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[0].token_type, TokenType::Integer(-18432));
+        assert_eq!(tokens[1].token_type, TokenType::Integer(511));
+        assert_eq!(tokens[2].token_type, TokenType::Integer(15));
+        assert_eq!(tokens[3].token_type, TokenType::Integer(-1));
+    }
+
+    #[test]
+    fn test_typed_literals() {
+        let mut lexer = Lexer::new("PRINT 1.5E-3 2D+10 100! 3# 32767% 40000 .5 123456789 1.5");
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens[1].token_type, TokenType::Single(1.5e-3));
+        assert_eq!(tokens[2].token_type, TokenType::Double(2e10));
+        assert_eq!(tokens[3].token_type, TokenType::Single(100.0));
+        assert_eq!(tokens[4].token_type, TokenType::Double(3.0));
+        assert_eq!(tokens[5].token_type, TokenType::Integer(32767));
+        assert_eq!(tokens[6].token_type, TokenType::Single(40000.0));
+        assert_eq!(tokens[7].token_type, TokenType::Single(0.5));
+        assert_eq!(tokens[8].token_type, TokenType::Double(123456789.0));
+        assert_eq!(tokens[9].token_type, TokenType::Single(1.5));
+    }
+
+    #[test]
+    fn test_integer_suffix_overflow() {
+        let mut lexer = Lexer::new("X = 40000%");
+        assert!(lexer.tokenize().is_err());
+    }
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        Lexer::new(source).tokenize().unwrap().into_iter().map(|t| t.token_type).collect()
+    }
+
+    #[test]
+    fn test_unspaced_keywords() {
+        assert_eq!(
+            token_types("10 FOR I=1TO 10:PRINT\"I\";:NEXT"),
+            vec![
+                TokenType::LineNumber(10),
+                TokenType::For,
+                TokenType::Identifier("I".to_string()),
+                TokenType::Equal,
+                TokenType::Integer(1),
+                TokenType::To,
+                TokenType::Integer(10),
+                TokenType::Colon,
+                TokenType::Print,
+                TokenType::String("I".to_string()),
+                TokenType::Semicolon,
+                TokenType::Colon,
+                TokenType::Next,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            token_types("IF(A>B)THEN 100"),
+            vec![
+                TokenType::If,
+                TokenType::LeftParen,
+                TokenType::Identifier("A".to_string()),
+                TokenType::GreaterThan,
+                TokenType::Identifier("B".to_string()),
+                TokenType::RightParen,
+                TokenType::Then,
+                TokenType::Integer(100),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_names_starting_with_keywords() {
+        // A reserved word running on into a letter or digit is a name
+        for name in ["TOTAL", "FORM", "TARGET", "STEPS", "FORI"] {
+            assert_eq!(token_types(name)[0], TokenType::Identifier(name.to_string()));
+        }
+        assert_eq!(
+            token_types("10 TOTAL = 5"),
+            vec![
+                TokenType::LineNumber(10),
+                TokenType::Identifier("TOTAL".to_string()),
+                TokenType::Equal,
+                TokenType::Integer(5),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suffixed_names_stay_whole() {
+        assert_eq!(token_types("FORM$")[0], TokenType::Identifier("FORM$".to_string()));
+        assert_eq!(token_types("total%")[0], TokenType::Identifier("TOTAL%".to_string()));
+        assert_eq!(token_types("INPUT$(1)")[0], TokenType::Identifier("INPUT$".to_string()));
+        assert_eq!(token_types("USR0(1)")[0], TokenType::Identifier("USR0".to_string()));
+        assert_eq!(token_types("VARPTR$(A)")[0], TokenType::Identifier("VARPTR$".to_string()));
+        // A reserved word with a type suffix is still the reserved word
+        assert_eq!(token_types("NAME$")[..2], [TokenType::Name, TokenType::Dollar]);
+    }
+
+    #[test]
+    fn test_fn_tab_and_shorthands() {
+        assert_eq!(
+            token_types("?FNA(1)TAB(2)"),
+            vec![
+                TokenType::Print,
+                TokenType::Fn,
+                TokenType::Identifier("A".to_string()),
+                TokenType::LeftParen,
+                TokenType::Integer(1),
+                TokenType::RightParen,
+                TokenType::Identifier("TAB".to_string()),
+                TokenType::LeftParen,
+                TokenType::Integer(2),
+                TokenType::RightParen,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remarks_and_data() {
+        assert_eq!(
+            token_types("X=1 'it's \"odd\"\nREM: A"),
+            vec![
+                TokenType::Identifier("X".to_string()),
+                TokenType::Equal,
+                TokenType::Integer(1),
+                TokenType::Colon,
+                TokenType::Rem,
+                TokenType::Newline,
+                TokenType::Rem,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(
+            token_types("DATA FORTY:FORM"),
+            vec![
+                TokenType::Data,
+                TokenType::Identifier("FORTY".to_string()),
+                TokenType::Colon,
+                TokenType::Identifier("FORM".to_string()),
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_columns() {
+        let tokens = Lexer::new("10 PRINT \"HI\"; 100").tokenize().unwrap();
+        let columns: Vec<(usize, usize)> = tokens.iter().map(|t| (t.column, t.end_column)).collect();
+        assert_eq!(columns, vec![(1, 3), (4, 9), (10, 14), (14, 15), (16, 19), (19, 20)]);
+    }
+
+    #[test]
+    fn test_lexer_error_location() {
+        let err = Lexer::new("PRINT 1 + 40000%").tokenize().unwrap_err();
+        assert_eq!(err.span().map(|s| s.start_column), Some(11));
+    }
+
+    #[test]
+    fn test_lexer_recovery() {
+        let (tokens, errors) = Lexer::new("X = 40000%: Y = 1\nPRINT \"OPEN @").tokenize_with_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tokens[2].token_type, TokenType::Newline);
+        assert_eq!(tokens[4].token_type, TokenType::String("OPEN @".to_string()));
+    }
+}
@@ -6,19 +6,33 @@
 pub mod lexer;
 pub mod parser;
 pub mod interpreter;
+pub mod console;
+pub mod ansi;
+pub mod print_using;
 pub mod error;
+pub mod diagnostic;
 pub mod value;
 pub mod functions;
 pub mod graphics;
+pub mod draw;
+pub mod viewport;
 pub mod graphics_backend;
+pub mod snapshot;
+pub mod recording;
+pub mod text_screen;
 pub mod fileio;
+pub mod tokenized;
 
 pub use error::{Error, Result};
+pub use diagnostic::{Diagnostic, Span};
 pub use interpreter::Interpreter;
 pub use lexer::{Lexer, Token, TokenType};
 pub use parser::{Parser, AstNode};
 pub use value::Value;
 pub use graphics::Screen;
+pub use snapshot::Snapshot;
+pub use recording::{RecordInterval, Recorder};
+pub use text_screen::TextScreen;
 pub use fileio::{FileManager, FileMode};
 
 /// Version information for the GW-BASIC interpreter
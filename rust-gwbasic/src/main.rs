@@ -1,18 +1,70 @@
-use rust_gwbasic::{Lexer, Parser, Interpreter};
+use rust_gwbasic::{AstNode, Error, Lexer, Parser, Interpreter, RecordInterval, Recorder};
+use rust_gwbasic::tokenized;
+use rust_gwbasic::graphics_backend::{TerminalColors, TerminalStyle};
+use rust_gwbasic::diagnostic::render_error;
 use std::io::{self, Write};
 use std::fs;
 use std::env;
+use std::time::Duration;
+
+/// Images of the screen to save while running a file
+#[derive(Default)]
+struct Capture {
+    /// Where to save the screen when the program ends
+    screenshot: Option<String>,
+
+    /// Where to save frames recorded while the program runs
+    record: Option<String>,
+    interval: RecordInterval,
+
+    /// Where to write the drawing as SVG when the program ends
+    svg: Option<String>,
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     // Parse command line arguments
     let mut use_gui = false;
+    let mut terminal: Option<TerminalStyle> = None;
+    let mut check = false;
+    let mut capture = Capture::default();
+    let mut printer: Option<String> = None;
     let mut filename: Option<String> = None;
 
-    for arg in &args[1..] {
+    let mut options = args[1..].iter();
+    while let Some(arg) = options.next() {
         if arg == "--gui" || arg == "-g" {
             use_gui = true;
+        } else if arg == "--terminal" || arg == "-t" {
+            terminal = match option_value(arg, options.next()) {
+                "half" | "halfblock" => Some(TerminalStyle::HalfBlock),
+                "braille" => Some(TerminalStyle::Braille),
+                "sixel" => Some(TerminalStyle::Sixel),
+                other => {
+                    eprintln!("Unknown terminal style '{}': use half, braille or sixel", other);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg == "--check" {
+            check = true;
+        } else if arg == "--screenshot" {
+            capture.screenshot = Some(option_value(arg, options.next()).to_string());
+        } else if arg == "--svg" {
+            capture.svg = Some(option_value(arg, options.next()).to_string());
+        } else if arg == "--printer" {
+            printer = Some(option_value(arg, options.next()).to_string());
+        } else if arg == "--record" {
+            capture.record = Some(option_value(arg, options.next()).to_string());
+        } else if arg == "--record-every" {
+            let every = option_value(arg, options.next());
+            capture.interval = match parse_interval(every) {
+                Some(interval) => interval,
+                None => {
+                    eprintln!("--record-every needs a statement count or a time like 50ms");
+                    std::process::exit(1);
+                }
+            };
         } else if !arg.starts_with('-') && filename.is_none() {
             filename = Some(arg.clone());
         } else if arg == "--help" || arg == "-h" {
@@ -23,7 +75,10 @@ fn main() {
 
     // If a filename is provided, run it
     if let Some(file) = filename {
-        run_file(&file, use_gui);
+        if check {
+            check_file(&file);
+        }
+        run_file(&file, use_gui, terminal, printer.as_deref(), &capture);
         return;
     }
 
@@ -44,6 +99,8 @@ fn main() {
             continue;
         }
 
+        interpreter.input_entered(&format!("> {}", input.trim_end_matches(['\r', '\n'])));
+
         let input = input.trim();
         if input.is_empty() {
             continue;
@@ -58,22 +115,28 @@ fn main() {
         let tokens = match lexer.tokenize() {
             Ok(t) => t,
             Err(e) => {
-                eprintln!("Lexer error: {}", e);
+                eprintln!("Lexer error: {}", render_error(&e, input));
                 continue;
             }
         };
 
+        // A numbered line is stored even if it has syntax errors; they are
+        // reported when the line is run
         let mut parser = Parser::new(tokens);
-        let ast = match parser.parse() {
-            Ok(a) => a,
-            Err(e) => {
-                eprintln!("Parser error: {}", e);
-                continue;
-            }
-        };
+        let (ast, errors) = parser.parse_with_errors();
+        if let Some(e) = errors.iter().find(|e| e.span().is_none_or(|span| span.line_number.is_none())) {
+            eprintln!("Parser error: {}", render_error(e, input));
+            continue;
+        }
 
         if let Err(e) = interpreter.execute(ast) {
-            eprintln!("Runtime error: {}", e);
+            // Stored lines were entered separately, so name the line instead
+            match e.span().and_then(|span| span.line_number) {
+                Some(line) => eprintln!("Runtime error: {} in {}", e, line),
+                None => eprintln!("Runtime error: {}", e),
+            }
+        } else {
+            interpreter.record_source(input);
         }
     }
 
@@ -88,23 +151,61 @@ fn print_usage() {
     println!();
     println!("OPTIONS:");
     println!("  -g, --gui      Use GUI window for graphics mode");
+    println!("  -t, --terminal STYLE");
+    println!("                 Draw graphics live in the terminal: half (half blocks),");
+    println!("                 braille or sixel. Uses 24-bit colour if COLORTERM says");
+    println!("                 the terminal has it, else 256 colours");
+    println!("      --check    Report every syntax error in FILE without running it");
+    println!("      --screenshot OUT");
+    println!("                 Run without a display and save the screen to OUT");
+    println!("                 when the program ends (PNG, or PPM for .ppm)");
+    println!("      --svg OUT  Draw LINE, CIRCLE and PSET as vector shapes and write");
+    println!("                 them to OUT as SVG when the program ends");
+    println!("      --printer OUT");
+    println!("                 Write LPRINT output to OUT instead of standard output");
+    println!("      --record OUT");
+    println!("                 Run without a display and save frames of the screen");
+    println!("                 to OUT (animated GIF for .gif, else numbered PNGs)");
+    println!("      --record-every N|Nms");
+    println!("                 Take a frame every N statements (default 100) or N ms;");
+    println!("                 frames are also taken on CLS and page flips");
     println!("  -h, --help     Show this help message");
     println!();
     println!("EXAMPLES:");
     println!("  rust-gwbasic                    Start REPL");
     println!("  rust-gwbasic program.bas        Run program in ASCII mode");
     println!("  rust-gwbasic --gui program.bas  Run program with GUI window");
+    println!("  rust-gwbasic -t half prog.bas   Draw graphics in the terminal");
+    println!("  rust-gwbasic --check prog.bas   Check program for syntax errors");
+    println!("  rust-gwbasic --screenshot out.png prog.bas");
+    println!("                                  Save the final screen as a PNG");
+    println!("  rust-gwbasic --svg plot.svg prog.bas");
+    println!("                                  Save the drawing as an SVG file");
+    println!("  rust-gwbasic --record out.gif --record-every 20ms prog.bas");
+    println!("                                  Record the program as an animated GIF");
 }
 
-fn run_file(filename: &str, use_gui: bool) {
-    // Read the file
-    let content = match fs::read_to_string(filename) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error reading file '{}': {}", filename, e);
+/// The argument after an option that needs one
+fn option_value<'a>(option: &str, value: Option<&'a String>) -> &'a str {
+    match value {
+        Some(value) => value,
+        None => {
+            eprintln!("{} needs a value", option);
             std::process::exit(1);
         }
-    };
+    }
+}
+
+/// A recording interval: a number of statements, or milliseconds with ms
+fn parse_interval(text: &str) -> Option<RecordInterval> {
+    match text.strip_suffix("ms") {
+        Some(millis) => Some(RecordInterval::Time(Duration::from_millis(millis.parse().ok()?))),
+        None => Some(RecordInterval::Statements(text.parse().ok().filter(|&count| count > 0)?)),
+    }
+}
+
+fn run_file(filename: &str, use_gui: bool, terminal: Option<TerminalStyle>, printer: Option<&str>, capture: &Capture) {
+    let (content, format) = read_source(filename);
 
     // Create interpreter with specified graphics backend
     let mut interpreter = if use_gui {
@@ -116,39 +217,122 @@ fn run_file(filename: &str, use_gui: bool) {
                 Interpreter::new()
             }
         }
+    } else if let Some(style) = terminal {
+        Interpreter::new_with_terminal(style, TerminalColors::detect())
+    } else if let Some(path) = &capture.svg {
+        Interpreter::new_with_svg(path)
+    } else if capture.screenshot.is_some() || capture.record.is_some() {
+        Interpreter::new_headless()
     } else {
         Interpreter::new()
     };
 
-    // Tokenize
-    let mut lexer = Lexer::new(&content);
-    let tokens = match lexer.tokenize() {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("Lexer error: {}", e);
-            std::process::exit(1);
+    if let Some(path) = printer {
+        match fs::File::create(path) {
+            Ok(file) => interpreter.set_printer(Box::new(file)),
+            Err(e) => {
+                eprintln!("Error creating printer file '{}': {}", path, e);
+                std::process::exit(1);
+            }
         }
-    };
+    }
 
-    // Parse
-    let mut parser = Parser::new(tokens);
-    let ast = match parser.parse() {
-        Ok(a) => a,
-        Err(e) => {
-            eprintln!("Parser error: {}", e);
-            std::process::exit(1);
+    if capture.record.is_some() {
+        interpreter.start_recording(Recorder::new(capture.interval));
+    }
+
+    // Numbered lines with syntax errors are stored and only fail when run,
+    // but anything else that can't be read stops us here
+    let (ast, errors) = parse_source(&content);
+    let fatal: Vec<&Error> = errors
+        .iter()
+        .filter(|e| e.span().is_none_or(|span| span.line_number.is_none()))
+        .collect();
+    if !fatal.is_empty() {
+        for e in fatal {
+            eprintln!("Syntax error: {}", render_error(e, &content));
         }
-    };
+        std::process::exit(1);
+    }
 
     // Execute (this loads line-numbered programs)
     if let Err(e) = interpreter.execute(ast) {
-        eprintln!("Runtime error: {}", e);
+        // Give the terminal back before reporting
+        drop(interpreter);
+        eprintln!("Runtime error: {}", render_error(&e, &content));
         std::process::exit(1);
     }
+    interpreter.record_source(&content);
+    // Errors in a protected program are reported without its source
+    let protected = format == tokenized::ProgramFormat::Protected;
+    if protected {
+        interpreter.protect();
+    }
+    let shown = if protected { "" } else { content.as_str() };
 
-    // If the program had line numbers, run it now
-    if let Err(e) = interpreter.run_stored_program() {
-        eprintln!("Runtime error: {}", e);
+    // If the program had line numbers, run it now. Screenshots and
+    // recordings are saved even if it stopped with an error.
+    let result = interpreter.run_stored_program();
+    if let Some(path) = &capture.screenshot {
+        if let Err(e) = interpreter.screenshot().save(path) {
+            eprintln!("Error saving screenshot: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let (Some(path), Some(recorder)) = (&capture.record, interpreter.finish_recording()) {
+        if let Err(e) = recorder.save(path) {
+            eprintln!("Error saving recording: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = result {
+        // Give the terminal back before reporting
+        drop(interpreter);
+        eprintln!("Runtime error: {}", render_error(&e, shown));
         std::process::exit(1);
     }
 }
+
+/// Check a program for syntax errors without running it, and exit
+fn check_file(filename: &str) -> ! {
+    let (content, _) = read_source(filename);
+    let (_, mut errors) = parse_source(&content);
+    errors.sort_by_key(|e| e.span().map(|span| (span.line, span.start_column)));
+
+    for e in &errors {
+        eprintln!("{}", render_error(e, &content));
+        eprintln!();
+    }
+    if errors.is_empty() {
+        println!("{}: no syntax errors", filename);
+        std::process::exit(0);
+    }
+    eprintln!("{}: {} syntax error(s)", filename, errors.len());
+    std::process::exit(1);
+}
+
+/// Read a program file (ASCII, tokenized or protected) as source text,
+/// with the format it was in
+fn read_source(filename: &str) -> (String, tokenized::ProgramFormat) {
+    match fs::read(filename) {
+        Ok(bytes) => match tokenized::decode_program(&bytes) {
+            Ok(c) => (c, tokenized::detect_format(&bytes)),
+            Err(e) => {
+                eprintln!("Error decoding file '{}': {}", filename, e);
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("Error reading file '{}': {}", filename, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Tokenize and parse a whole program, collecting every error on the way
+fn parse_source(content: &str) -> (AstNode, Vec<Error>) {
+    let (tokens, mut errors) = Lexer::new(content).tokenize_with_errors();
+    let (ast, parse_errors) = Parser::new(tokens).parse_with_errors();
+    errors.extend(parse_errors);
+    (ast, errors)
+}
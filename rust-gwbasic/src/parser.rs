@@ -1,6 +1,8 @@
 //! Parser for GW-BASIC
 
+use crate::diagnostic::Span;
 use crate::error::{Error, Result};
+use crate::graphics::PutAction;
 use crate::lexer::{Token, TokenType};
 use crate::value::Value;
 
@@ -8,7 +10,8 @@ use crate::value::Value;
 #[derive(Debug, Clone, PartialEq)]
 pub enum AstNode {
     // Statements - Basic I/O
-    Print(Vec<AstNode>),
+    Print(Vec<PrintItem>),
+    Lprint(Vec<PrintItem>),
     Input(Vec<String>),
     Let(String, Box<AstNode>),
     ArrayAssign(String, Vec<AstNode>, Box<AstNode>),  // name, indices, value
@@ -36,21 +39,24 @@ pub enum AstNode {
     
     // Statements - Screen/Graphics
     Cls,
-    Locate(Box<AstNode>, Box<AstNode>),
+    Locate([Option<Box<AstNode>>; 5]), // row, column, cursor, start, stop
     Color(Option<Box<AstNode>>, Option<Box<AstNode>>),
-    Screen(Box<AstNode>),
+    Screen(Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>), // mode, colour switch, active page, visual page
     Width(Box<AstNode>),
-    View(Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>), // x1, y1, x2, y2
-    Window(Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>), // x1, y1, x2, y2
-    Pset(Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>),
-    Preset(Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>),
-    DrawLine(Box<AstNode>, Box<AstNode>, Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>),
-    Circle(Box<AstNode>, Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>),
-    Paint(Box<AstNode>, Box<AstNode>, Option<Box<AstNode>>, Option<Box<AstNode>>), // x, y, paint_color, border_color
-    Draw(String),                            // draw string
-    GraphicsGet(Box<AstNode>, Box<AstNode>, Box<AstNode>, Box<AstNode>, String), // x1, y1, x2, y2, array
-    GraphicsPut(Box<AstNode>, Box<AstNode>, String, Option<String>), // x, y, array, action
-    Palette(Box<AstNode>, Box<AstNode>),    // attribute, color
+    View(Option<(Coord, Coord)>, bool, Option<Box<AstNode>>, Option<Box<AstNode>>), // corners, SCREEN, fill, border
+    Window(Option<(Coord, Coord)>, bool),    // corners, SCREEN
+    ViewPrint(Option<(Box<AstNode>, Box<AstNode>)>), // top and bottom rows
+    Pset(Coord, Option<Box<AstNode>>),
+    Preset(Coord, Option<Box<AstNode>>),
+    DrawLine(Option<Coord>, Coord, Option<Box<AstNode>>, Option<LineBox>, Option<Box<AstNode>>), // start, end, color, box, style
+    Circle(Coord, Box<AstNode>, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>), // centre, radius, color, start, end, aspect
+    Paint(Coord, Option<Box<AstNode>>, Option<Box<AstNode>>, Option<Box<AstNode>>), // point, colour or tile, border, background
+    Draw(Box<AstNode>),                      // command string
+    GraphicsGet(Coord, Coord, String, Vec<AstNode>), // corners, array, first element
+    GraphicsPut(Coord, String, Vec<AstNode>, Option<PutAction>), // corner, array, first element, action
+    Pcopy(Box<AstNode>, Box<AstNode>),       // source page, destination page
+    Palette(Option<(Box<AstNode>, Box<AstNode>)>), // attribute and colour, or None to restore defaults
+    PaletteUsing(String, Vec<AstNode>),     // array, first element
     
     // Statements - Sound
     Beep,
@@ -61,7 +67,7 @@ pub enum AstNode {
     Open(String, Box<AstNode>, String),  // filename, file_number, mode
     Close(Vec<i32>),
     Reset,                                   // close all files
-    PrintFile(Box<AstNode>, Vec<AstNode>),  // file_number, expressions
+    PrintFile(Box<AstNode>, Vec<PrintItem>), // file_number, print list
     InputFile(Box<AstNode>, Vec<String>),   // file_number, variables
     WriteFile(Box<AstNode>, Vec<AstNode>),  // file_number, expressions
     LineInput(Vec<String>),                  // variables
@@ -74,15 +80,14 @@ pub enum AstNode {
     Rset(String, Box<AstNode>),             // variable, expression
     FileGet(Box<AstNode>, Option<Box<AstNode>>), // file_number, optional record_number
     FilePut(Box<AstNode>, Option<Box<AstNode>>), // file_number, optional record_number
-    PrintUsing(String, Vec<AstNode>),        // format string, expressions
     Write(Vec<AstNode>),                     // expressions to screen
     
     // Statements - Program Control
     List(Option<u32>, Option<u32>),         // start_line, end_line
     New,
     Run(Option<u32>),                       // optional start line
-    Load(String),                           // filename
-    Save(String),                           // filename
+    Load(String, bool),                     // filename, run after loading (,R)
+    Save(String, Option<String>),           // filename, optional format letter (A or P)
     Merge(String),                          // filename
     Chain(String, Option<u32>),             // filename, optional line
     Cont,                                   // continue after STOP
@@ -133,8 +138,9 @@ pub enum AstNode {
     FunctionCall(String, Vec<AstNode>),
     
     // Program structure
-    Line(u32, Vec<AstNode>),
+    Line(u32, Vec<AstNode>, Vec<Span>),     // line number, statements, statement spans
     Program(Vec<AstNode>),
+    Invalid(Error),                         // statement that failed to parse, reported when run
 }
 
 /// Binary operators
@@ -167,56 +173,110 @@ pub enum UnaryOperator {
     Not,
 }
 
+/// Graphics coordinates `(x, y)`, or `STEP(x, y)` relative to the last
+/// point referenced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coord {
+    pub x: Box<AstNode>,
+    pub y: Box<AstNode>,
+    pub step: bool,
+}
+
+/// Box option of a LINE statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineBox {
+    Outline,            // B
+    Filled,             // BF
+}
+
+/// One element of a PRINT list
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintItem {
+    Expr(AstNode),
+    Tab(AstNode),       // TAB(n)
+    Spc(AstNode),       // SPC(n)
+    Using(AstNode, Vec<AstNode>),   // USING format, values
+    Comma,              // move to the next print zone
+    Semicolon,          // no spacing
+}
+
 /// Parser that converts tokens into an AST
 pub struct Parser {
     tokens: Vec<Token>,
     position: usize,
+    /// Line number of the line being parsed, for error locations
+    line_number: Option<u32>,
+    /// Syntax errors found so far
+    errors: Vec<Error>,
 }
 
 impl Parser {
     /// Create a new parser from a vector of tokens
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, position: 0 }
+        Parser { tokens, position: 0, line_number: None, errors: Vec::new() }
     }
 
-    /// Parse the entire program
+    /// Parse the entire program, stopping at the first syntax error
     pub fn parse(&mut self) -> Result<AstNode> {
+        let (program, mut errors) = self.parse_with_errors();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors.remove(0))
+        }
+    }
+
+    /// Parse the entire program, collecting every syntax error. A statement
+    /// that fails to parse is skipped up to the next `:` or end of line and
+    /// kept in the program as `AstNode::Invalid`, so its line can still be
+    /// stored and run up to that point, as GW-BASIC does.
+    pub fn parse_with_errors(&mut self) -> (AstNode, Vec<Error>) {
         let mut lines = Vec::new();
 
         while !self.is_at_end() {
             if let TokenType::Eof = self.current_token().token_type {
                 break;
             }
-            lines.push(self.parse_line()?);
+            lines.push(self.parse_line());
         }
 
-        Ok(AstNode::Program(lines))
+        (AstNode::Program(lines), std::mem::take(&mut self.errors))
     }
 
-    fn parse_line(&mut self) -> Result<AstNode> {
+    fn parse_line(&mut self) -> AstNode {
         let line_number = if let TokenType::LineNumber(num) = self.current_token().token_type {
             self.advance();
             Some(num)
         } else {
             None
         };
+        self.line_number = line_number;
 
-        let statements = self.parse_statements()?;
+        // At the top level every error is kept as AstNode::Invalid
+        let (statements, spans) = self.parse_statement_list(true).unwrap_or_default();
 
         if let Some(num) = line_number {
-            Ok(AstNode::Line(num, statements))
+            AstNode::Line(num, statements, spans)
         } else {
             // Direct mode - just return the statements
             if statements.len() == 1 {
-                Ok(statements[0].clone())
+                statements[0].clone()
             } else {
-                Ok(AstNode::Program(statements))
+                AstNode::Program(statements)
             }
         }
     }
 
     fn parse_statements(&mut self) -> Result<Vec<AstNode>> {
+        Ok(self.parse_statement_list(false)?.0)
+    }
+
+    /// Parse statements up to the end of the line, with the span of each.
+    /// At the top level of a line, errors are given the location of the
+    /// token the parser stopped at.
+    fn parse_statement_list(&mut self, top_level: bool) -> Result<(Vec<AstNode>, Vec<Span>)> {
         let mut statements = Vec::new();
+        let mut spans = Vec::new();
 
         while !self.is_at_end() {
             match &self.current_token().token_type {
@@ -238,23 +298,75 @@ impl Parser {
                     self.advance();
                     continue;
                 }
+                TokenType::Else if top_level => {
+                    // ELSE with no IF before it
+                    let start = self.statement_span(statements.len());
+                    let error = Error::SyntaxError("Syntax error".to_string()).at(start);
+                    self.advance();
+                    self.skip_statement();
+                    let end = self.tokens[self.position.saturating_sub(1)].span();
+                    spans.push(start.to(end));
+                    statements.push(AstNode::Invalid(error.clone()));
+                    self.errors.push(error);
+                }
                 TokenType::Else => {
                     // Stop before ELSE, let the caller handle it
                     break;
                 }
                 _ => {
-                    statements.push(self.parse_statement()?);
+                    let start = self.statement_span(statements.len());
+                    match self.parse_statement() {
+                        Ok(statement) => {
+                            let end = self.tokens[self.position.saturating_sub(1)].span();
+                            spans.push(start.to(end));
+                            statements.push(statement);
+                        }
+                        Err(e) if top_level => {
+                            let error = e.at(self.statement_span(statements.len()));
+                            self.skip_statement();
+                            let end = self.tokens[self.position.saturating_sub(1)].span();
+                            spans.push(start.to(end));
+                            statements.push(AstNode::Invalid(error.clone()));
+                            self.errors.push(error);
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
             }
         }
 
-        Ok(statements)
+        Ok((statements, spans))
+    }
+
+    /// Skip to the next `:` or the end of the line
+    fn skip_statement(&mut self) {
+        while !self.is_at_end()
+            && !matches!(
+                self.current_token().token_type,
+                TokenType::Colon | TokenType::Newline | TokenType::LineNumber(_) | TokenType::Eof
+            )
+        {
+            self.advance();
+        }
+    }
+
+    /// Span of the current token, as part of statement `index` of the line
+    fn statement_span(&self, index: usize) -> Span {
+        Span {
+            line_number: self.line_number,
+            statement: index,
+            ..self.current_token().span()
+        }
     }
 
     fn parse_statement(&mut self) -> Result<AstNode> {
         match &self.current_token().token_type {
             // Basic I/O
             TokenType::Print => self.parse_print(),
+            TokenType::Lprint => {
+                self.advance();
+                Ok(AstNode::Lprint(self.parse_print_items()?))
+            }
             TokenType::Input => self.parse_input(),
             TokenType::Let => self.parse_let(),
             
@@ -284,17 +396,17 @@ impl Parser {
             TokenType::List => {
                 self.advance();
                 // Parse optional line range
-                let start = if let TokenType::Integer(n) = self.current_token().token_type {
+                let start = if let Some(n) = self.line_number_literal() {
                     self.advance();
-                    Some(n as u32)
+                    Some(n)
                 } else {
                     None
                 };
                 let end = if let TokenType::Minus = self.current_token().token_type {
                     self.advance();
-                    if let TokenType::Integer(n) = self.current_token().token_type {
+                    if let Some(n) = self.line_number_literal() {
                         self.advance();
-                        Some(n as u32)
+                        Some(n)
                     } else {
                         None
                     }
@@ -307,11 +419,48 @@ impl Parser {
                 self.advance();
                 Ok(AstNode::New)
             }
+            TokenType::Load => {
+                self.advance();
+                let filename = self.parse_filename("LOAD")?;
+                let run = if let TokenType::Comma = self.current_token().token_type {
+                    self.advance();
+                    match &self.current_token().token_type {
+                        TokenType::Identifier(opt) if opt.eq_ignore_ascii_case("R") => {
+                            self.advance();
+                            true
+                        }
+                        _ => return Err(Error::SyntaxError("Expected R after LOAD filename".to_string())),
+                    }
+                } else {
+                    false
+                };
+                Ok(AstNode::Load(filename, run))
+            }
+            TokenType::Save => {
+                self.advance();
+                let filename = self.parse_filename("SAVE")?;
+                let format = if let TokenType::Comma = self.current_token().token_type {
+                    self.advance();
+                    match &self.current_token().token_type {
+                        TokenType::Identifier(opt)
+                            if opt.eq_ignore_ascii_case("A") || opt.eq_ignore_ascii_case("P") =>
+                        {
+                            let opt = opt.to_uppercase();
+                            self.advance();
+                            Some(opt)
+                        }
+                        _ => return Err(Error::SyntaxError("Expected A or P after SAVE filename".to_string())),
+                    }
+                } else {
+                    None
+                };
+                Ok(AstNode::Save(filename, format))
+            }
             TokenType::Run => {
                 self.advance();
-                let start_line = if let TokenType::Integer(n) = self.current_token().token_type {
+                let start_line = if let Some(n) = self.line_number_literal() {
                     self.advance();
-                    Some(n as u32)
+                    Some(n)
                 } else {
                     None
                 };
@@ -357,7 +506,7 @@ impl Parser {
                         }
                     }
                     
-                    Ok(AstNode::PrintFile(Box::new(AstNode::Literal(Value::Integer(0))), expressions))
+                    Ok(AstNode::Write(expressions))
                 }
             }
             TokenType::On => {
@@ -377,15 +526,11 @@ impl Parser {
                 
                 // Parse line numbers
                 let mut lines = vec![];
-                loop {
-                    if let TokenType::Integer(n) = self.current_token().token_type {
-                        lines.push(n as u32);
+                while let Some(n) = self.line_number_literal() {
+                    lines.push(n);
+                    self.advance();
+                    if let TokenType::Comma = self.current_token().token_type {
                         self.advance();
-                        if let TokenType::Comma = self.current_token().token_type {
-                            self.advance();
-                        } else {
-                            break;
-                        }
                     } else {
                         break;
                     }
@@ -441,9 +586,9 @@ impl Parser {
             }
             TokenType::Restore => {
                 self.advance();
-                let line = if let TokenType::Integer(n) = self.current_token().token_type {
+                let line = if let Some(n) = self.line_number_literal() {
                     self.advance();
-                    Some(n as u32)
+                    Some(n)
                 } else {
                     None
                 };
@@ -456,13 +601,18 @@ impl Parser {
                 Ok(AstNode::Cls)
             }
             TokenType::Locate => {
+                // LOCATE [row] [, [col] [, [cursor] [, [start] [, stop]]]]
                 self.advance();
-                let row = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
+                let mut arguments = [None, None, None, None, None];
+                arguments[0] = self.parse_optional_argument()?;
+                for argument in arguments.iter_mut().skip(1) {
+                    if !matches!(self.current_token().token_type, TokenType::Comma) {
+                        break;
+                    }
                     self.advance();
+                    *argument = self.parse_optional_argument()?;
                 }
-                let col = self.parse_expression()?;
-                Ok(AstNode::Locate(Box::new(row), Box::new(col)))
+                Ok(AstNode::Locate(arguments))
             }
             TokenType::Color => {
                 self.advance();
@@ -484,142 +634,128 @@ impl Parser {
                 Ok(AstNode::Color(fg, bg))
             }
             TokenType::Screen => {
+                // SCREEN [mode] [, [colorswitch] [, [apage] [, vpage]]]
                 self.advance();
-                let mode = self.parse_expression()?;
-                Ok(AstNode::Screen(Box::new(mode)))
+                let mut arguments = [None, None, None, None];
+                arguments[0] = self.parse_optional_argument()?;
+                for argument in arguments.iter_mut().skip(1) {
+                    if !matches!(self.current_token().token_type, TokenType::Comma) {
+                        break;
+                    }
+                    self.advance();
+                    *argument = self.parse_optional_argument()?;
+                }
+                let [mode, color_switch, active_page, visual_page] = arguments;
+                if mode.is_none() && color_switch.is_none() && active_page.is_none() && visual_page.is_none() {
+                    return Err(Error::SyntaxError("Expected arguments to SCREEN".to_string()));
+                }
+                Ok(AstNode::Screen(mode, color_switch, active_page, visual_page))
             }
             TokenType::Width => {
                 self.advance();
                 let width = self.parse_expression()?;
                 Ok(AstNode::Width(Box::new(width)))
             }
-            TokenType::Pset => {
+            TokenType::Pset | TokenType::Preset => {
+                let preset = matches!(self.current_token().token_type, TokenType::Preset);
                 self.advance();
-                if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
-                }
-                let x = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                }
-                let y = self.parse_expression()?;
-                if let TokenType::RightParen = self.current_token().token_type {
-                    self.advance();
-                }
+                let coord = self.parse_coord()?;
                 let color = if let TokenType::Comma = self.current_token().token_type {
                     self.advance();
                     Some(Box::new(self.parse_expression()?))
                 } else {
                     None
                 };
-                Ok(AstNode::Pset(Box::new(x), Box::new(y), color))
+                Ok(if preset { AstNode::Preset(coord, color) } else { AstNode::Pset(coord, color) })
             }
             TokenType::Circle => {
+                // CIRCLE [STEP](x, y), radius [, [color] [, [start] [, [end] [, aspect]]]]
                 self.advance();
-                if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
-                }
-                let x = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                }
-                let y = self.parse_expression()?;
-                if let TokenType::RightParen = self.current_token().token_type {
-                    self.advance();
-                }
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
+                let centre = self.parse_coord()?;
+                if !matches!(self.current_token().token_type, TokenType::Comma) {
+                    return Err(Error::SyntaxError("Expected radius in CIRCLE statement".to_string()));
                 }
+                self.advance();
                 let radius = self.parse_expression()?;
-                let color = if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    Some(Box::new(self.parse_expression()?))
-                } else {
-                    None
-                };
-                // Optional: start angle
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    let _ = self.parse_expression()?;
-                }
-                // Optional: end angle
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    let _ = self.parse_expression()?;
-                }
-                // Optional: aspect ratio
-                if let TokenType::Comma = self.current_token().token_type {
+                let mut options = [None, None, None, None];
+                for option in options.iter_mut() {
+                    if !matches!(self.current_token().token_type, TokenType::Comma) {
+                        break;
+                    }
                     self.advance();
-                    let _ = self.parse_expression()?;
+                    *option = self.parse_optional_argument()?;
                 }
-                Ok(AstNode::Circle(Box::new(x), Box::new(y), Box::new(radius), color))
+                let [color, start, end, aspect] = options;
+                Ok(AstNode::Circle(centre, Box::new(radius), color, start, end, aspect))
             }
-            TokenType::Line => {
+            TokenType::Line => self.parse_line_statement(),
+            TokenType::Get | TokenType::Put => self.parse_get_put(),
+            TokenType::View | TokenType::Window => self.parse_view_window(),
+            TokenType::Palette => {
+                // PALETTE [attribute, colour] or PALETTE USING array(index)
                 self.advance();
-                if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
-                }
-                let x1 = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                }
-                let y1 = self.parse_expression()?;
-                if let TokenType::RightParen = self.current_token().token_type {
-                    self.advance();
-                }
-                // Expect - or Minus token
-                if let TokenType::Minus = self.current_token().token_type {
-                    self.advance();
-                } else {
-                    return Err(Error::SyntaxError("Expected '-' in LINE statement".to_string()));
-                }
-                if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
-                }
-                let x2 = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
+                match self.current_token().token_type {
+                    TokenType::Colon | TokenType::Newline | TokenType::Eof | TokenType::Else => Ok(AstNode::Palette(None)),
+                    TokenType::Using => {
+                        self.advance();
+                        match self.parse_primary()? {
+                            AstNode::Variable(name) => Ok(AstNode::PaletteUsing(name, Vec::new())),
+                            AstNode::FunctionCall(name, indices) => Ok(AstNode::PaletteUsing(name, indices)),
+                            _ => Err(Error::SyntaxError("Expected array name".to_string())),
+                        }
+                    }
+                    _ => {
+                        let attribute = self.parse_expression()?;
+                        if !matches!(self.current_token().token_type, TokenType::Comma) {
+                            return Err(Error::SyntaxError("Expected , in PALETTE statement".to_string()));
+                        }
+                        self.advance();
+                        let color = self.parse_expression()?;
+                        Ok(AstNode::Palette(Some((Box::new(attribute), Box::new(color)))))
+                    }
                 }
-                let y2 = self.parse_expression()?;
-                if let TokenType::RightParen = self.current_token().token_type {
-                    self.advance();
+            }
+            TokenType::Pcopy => {
+                self.advance();
+                let from = self.parse_expression()?;
+                if !matches!(self.current_token().token_type, TokenType::Comma) {
+                    return Err(Error::SyntaxError("Expected , in PCOPY statement".to_string()));
                 }
-                let color = if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    Some(Box::new(self.parse_expression()?))
-                } else {
-                    None
-                };
-                Ok(AstNode::DrawLine(Box::new(x1), Box::new(y1), Box::new(x2), Box::new(y2), color))
+                self.advance();
+                let to = self.parse_expression()?;
+                Ok(AstNode::Pcopy(Box::new(from), Box::new(to)))
+            }
+            TokenType::Draw => {
+                self.advance();
+                let commands = self.parse_expression()?;
+                Ok(AstNode::Draw(Box::new(commands)))
             }
             TokenType::Paint => {
+                // PAINT [STEP](x, y) [, [paint] [, [border] [, background]]]
                 self.advance();
-                if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
-                }
-                let x = self.parse_expression()?;
-                if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                }
-                let y = self.parse_expression()?;
-                if let TokenType::RightParen = self.current_token().token_type {
+                let point = self.parse_coord()?;
+                let mut options = [None, None, None];
+                for option in options.iter_mut() {
+                    if !matches!(self.current_token().token_type, TokenType::Comma) {
+                        break;
+                    }
                     self.advance();
+                    *option = self.parse_optional_argument()?;
                 }
-                let paint_color = if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    Some(Box::new(self.parse_expression()?))
-                } else {
-                    None
-                };
-                let border_color = if let TokenType::Comma = self.current_token().token_type {
-                    self.advance();
-                    Some(Box::new(self.parse_expression()?))
-                } else {
-                    None
-                };
-                Ok(AstNode::Paint(Box::new(x), Box::new(y), paint_color, border_color))
+                let [paint, border, background] = options;
+                Ok(AstNode::Paint(point, paint, border, background))
             }
             
+            // Debugging
+            TokenType::Tron => {
+                self.advance();
+                Ok(AstNode::Tron)
+            }
+            TokenType::Troff => {
+                self.advance();
+                Ok(AstNode::Troff)
+            }
+
             // Sound
             TokenType::Beep => {
                 self.advance();
@@ -694,6 +830,16 @@ impl Parser {
         }
     }
 
+    fn parse_filename(&mut self, statement: &str) -> Result<String> {
+        if let TokenType::String(name) = &self.current_token().token_type {
+            let name = name.clone();
+            self.advance();
+            Ok(name)
+        } else {
+            Err(Error::SyntaxError(format!("Expected filename after {}", statement)))
+        }
+    }
+
     fn parse_print(&mut self) -> Result<AstNode> {
         self.advance(); // Skip PRINT
         
@@ -707,38 +853,83 @@ impl Parser {
                 self.advance();
             }
             
-            let mut expressions = Vec::new();
+            let items = self.parse_print_items()?;
+            return Ok(AstNode::PrintFile(Box::new(file_num), items));
+        }
+        
+        // Regular PRINT
+        let items = self.parse_print_items()?;
+        Ok(AstNode::Print(items))
+    }
+
+    /// Parse a PRINT list up to the end of the statement, keeping the
+    /// separators since they control spacing and the final newline
+    fn parse_print_items(&mut self) -> Result<Vec<PrintItem>> {
+        let mut items = Vec::new();
+
+        if let TokenType::Using = self.current_token().token_type {
+            self.advance();
+            let format = self.parse_expression()?;
+            if !matches!(self.current_token().token_type, TokenType::Semicolon) {
+                return Err(Error::SyntaxError("Expected ; after USING format".to_string()));
+            }
+            self.advance();
+
+            // Either separator just moves on to the next value
+            let mut values = Vec::new();
+            let mut trailing_separator = false;
             while !self.is_at_end() {
                 match &self.current_token().token_type {
                     TokenType::Eof | TokenType::Newline | TokenType::Colon | TokenType::Else => break,
                     TokenType::Semicolon | TokenType::Comma => {
                         self.advance();
+                        trailing_separator = true;
                     }
                     _ => {
-                        expressions.push(self.parse_expression()?);
+                        values.push(self.parse_expression()?);
+                        trailing_separator = false;
                     }
                 }
             }
-
-            return Ok(AstNode::PrintFile(Box::new(file_num), expressions));
+            items.push(PrintItem::Using(format, values));
+            if trailing_separator {
+                items.push(PrintItem::Semicolon);
+            }
+            return Ok(items);
         }
-        
-        // Regular PRINT
-        let mut expressions = Vec::new();
 
         while !self.is_at_end() {
             match &self.current_token().token_type {
                 TokenType::Eof | TokenType::Newline | TokenType::Colon | TokenType::Else => break,
-                TokenType::Semicolon | TokenType::Comma => {
+                TokenType::Semicolon => {
+                    self.advance();
+                    items.push(PrintItem::Semicolon);
+                }
+                TokenType::Comma => {
                     self.advance();
+                    items.push(PrintItem::Comma);
+                }
+                TokenType::Identifier(name)
+                    if (name == "TAB" || name == "SPC")
+                        && matches!(self.peek_token_type(), Some(TokenType::LeftParen)) =>
+                {
+                    let is_tab = name == "TAB";
+                    self.advance();
+                    self.advance(); // Skip (
+                    let arg = self.parse_expression()?;
+                    if !matches!(self.current_token().token_type, TokenType::RightParen) {
+                        return Err(Error::SyntaxError("Expected ) after TAB/SPC argument".to_string()));
+                    }
+                    self.advance();
+                    items.push(if is_tab { PrintItem::Tab(arg) } else { PrintItem::Spc(arg) });
                 }
                 _ => {
-                    expressions.push(self.parse_expression()?);
+                    items.push(PrintItem::Expr(self.parse_expression()?));
                 }
             }
         }
 
-        Ok(AstNode::Print(expressions))
+        Ok(items)
     }
 
     fn parse_let(&mut self) -> Result<AstNode> {
@@ -793,22 +984,253 @@ impl Parser {
 
         let condition = self.parse_expression()?;
 
-        if let TokenType::Then = self.current_token().token_type {
+        let then_statements = match self.current_token().token_type {
+            TokenType::Then => {
+                self.advance();
+                self.parse_branch()?
+            }
+            // IF ... GOTO line works without THEN
+            TokenType::Goto => self.parse_statements()?,
+            _ => return Err(Error::SyntaxError("Expected THEN after IF condition".to_string())),
+        };
+
+        // ELSE belongs to the nearest IF on the line that has none yet, which
+        // the innermost parse_if has already claimed by the time we get here
+        let else_statements = if let TokenType::Else = self.current_token().token_type {
+            self.advance();
+            Some(self.parse_branch()?)
+        } else {
+            None
+        };
+
+        Ok(AstNode::If(Box::new(condition), then_statements, else_statements))
+    }
+
+    /// LINE [[STEP](x1, y1)]-[STEP](x2, y2) [,[color] [,[B|BF] [,style]]]
+    fn parse_line_statement(&mut self) -> Result<AstNode> {
+        self.advance(); // Skip LINE
+
+        let start = if let TokenType::Minus = self.current_token().token_type {
+            None
+        } else {
+            Some(self.parse_coord()?)
+        };
+        if let TokenType::Minus = self.current_token().token_type {
             self.advance();
         } else {
-            return Err(Error::SyntaxError("Expected THEN after IF condition".to_string()));
+            return Err(Error::SyntaxError("Expected '-' in LINE statement".to_string()));
         }
+        let end = self.parse_coord()?;
 
-        let then_statements = self.parse_statements()?;
+        let mut color = None;
+        let mut shape = None;
+        let mut style = None;
+        if let TokenType::Comma = self.current_token().token_type {
+            self.advance();
+            color = self.parse_optional_argument()?;
+            if let TokenType::Comma = self.current_token().token_type {
+                self.advance();
+                if let TokenType::Identifier(name) = &self.current_token().token_type {
+                    shape = match name.to_uppercase().as_str() {
+                        "B" => Some(LineBox::Outline),
+                        "BF" => Some(LineBox::Filled),
+                        _ => return Err(Error::SyntaxError("Expected B or BF in LINE statement".to_string())),
+                    };
+                    self.advance();
+                }
+                if let TokenType::Comma = self.current_token().token_type {
+                    self.advance();
+                    style = self.parse_optional_argument()?;
+                }
+            }
+        }
 
-        let else_statements = if let TokenType::Else = self.current_token().token_type {
+        Ok(AstNode::DrawLine(start, end, color, shape, style))
+    }
+
+    /// GET and PUT, either graphics (`GET (x1,y1)-(x2,y2),A`,
+    /// `PUT (x,y),A[,action]`) or random-access file records (`GET #n[,r]`)
+    fn parse_get_put(&mut self) -> Result<AstNode> {
+        let put = matches!(self.current_token().token_type, TokenType::Put);
+        self.advance();
+
+        if !matches!(self.current_token().token_type, TokenType::LeftParen | TokenType::Step) {
+            if let TokenType::Hash = self.current_token().token_type {
+                self.advance();
+            }
+            let file_num = Box::new(self.parse_expression()?);
+            let record = if let TokenType::Comma = self.current_token().token_type {
+                self.advance();
+                Some(Box::new(self.parse_expression()?))
+            } else {
+                None
+            };
+            return Ok(if put { AstNode::FilePut(file_num, record) } else { AstNode::FileGet(file_num, record) });
+        }
+
+        let start = self.parse_coord()?;
+        let end = if put {
+            None
+        } else {
+            if !matches!(self.current_token().token_type, TokenType::Minus) {
+                return Err(Error::SyntaxError("Expected '-' in GET statement".to_string()));
+            }
             self.advance();
-            Some(self.parse_statements()?)
+            Some(self.parse_coord()?)
+        };
+        if !matches!(self.current_token().token_type, TokenType::Comma) {
+            return Err(Error::SyntaxError("Expected array name".to_string()));
+        }
+        self.advance();
+        let (array, indices) = match self.parse_primary()? {
+            AstNode::Variable(name) => (name, Vec::new()),
+            AstNode::FunctionCall(name, indices) => (name, indices),
+            _ => return Err(Error::SyntaxError("Expected array name".to_string())),
+        };
+
+        let Some(end) = end else {
+            let action = if let TokenType::Comma = self.current_token().token_type {
+                self.advance();
+                let action = match self.current_token().token_type {
+                    TokenType::Pset => PutAction::Pset,
+                    TokenType::Preset => PutAction::Preset,
+                    TokenType::And => PutAction::And,
+                    TokenType::Or => PutAction::Or,
+                    TokenType::Xor => PutAction::Xor,
+                    _ => return Err(Error::SyntaxError("Expected PSET, PRESET, AND, OR or XOR".to_string())),
+                };
+                self.advance();
+                Some(action)
+            } else {
+                None
+            };
+            return Ok(AstNode::GraphicsPut(start, array, indices, action));
+        };
+        Ok(AstNode::GraphicsGet(start, end, array, indices))
+    }
+
+    /// VIEW [[SCREEN] (x1,y1)-(x2,y2) [,[fill] [,border]]],
+    /// VIEW PRINT [top TO bottom] and WINDOW [[SCREEN] (x1,y1)-(x2,y2)]
+    fn parse_view_window(&mut self) -> Result<AstNode> {
+        let view = matches!(self.current_token().token_type, TokenType::View);
+        self.advance();
+        if view && matches!(self.current_token().token_type, TokenType::Print) {
+            self.advance();
+            let Some(top) = self.parse_optional_argument()? else {
+                return Ok(AstNode::ViewPrint(None));
+            };
+            if !matches!(self.current_token().token_type, TokenType::To) {
+                return Err(Error::SyntaxError("Expected TO in VIEW PRINT".to_string()));
+            }
+            self.advance();
+            let bottom = self.parse_expression()?;
+            return Ok(AstNode::ViewPrint(Some((top, Box::new(bottom)))));
+        }
+        let screen = if let TokenType::Screen = self.current_token().token_type {
+            self.advance();
+            true
+        } else {
+            false
+        };
+
+        let corners = if let TokenType::LeftParen = self.current_token().token_type {
+            let first = self.parse_coord()?;
+            if !matches!(self.current_token().token_type, TokenType::Minus) {
+                return Err(Error::SyntaxError("Expected '-' between corners".to_string()));
+            }
+            self.advance();
+            let second = self.parse_coord()?;
+            if first.step || second.step {
+                return Err(Error::SyntaxError("STEP is not allowed here".to_string()));
+            }
+            Some((first, second))
+        } else if screen {
+            return Err(Error::SyntaxError("Expected ( before coordinates".to_string()));
         } else {
             None
         };
+        if !view {
+            return Ok(AstNode::Window(corners, screen));
+        }
 
-        Ok(AstNode::If(Box::new(condition), then_statements, else_statements))
+        let mut fill = None;
+        let mut border = None;
+        if corners.is_some() {
+            if let TokenType::Comma = self.current_token().token_type {
+                self.advance();
+                fill = self.parse_optional_argument()?;
+                if let TokenType::Comma = self.current_token().token_type {
+                    self.advance();
+                    border = self.parse_optional_argument()?;
+                }
+            }
+        }
+        Ok(AstNode::View(corners, screen, fill, border))
+    }
+
+    /// Graphics coordinates: [STEP](x, y)
+    fn parse_coord(&mut self) -> Result<Coord> {
+        let step = if let TokenType::Step = self.current_token().token_type {
+            self.advance();
+            true
+        } else {
+            false
+        };
+        if !matches!(self.current_token().token_type, TokenType::LeftParen) {
+            return Err(Error::SyntaxError("Expected ( before coordinates".to_string()));
+        }
+        self.advance();
+        let x = self.parse_expression()?;
+        if !matches!(self.current_token().token_type, TokenType::Comma) {
+            return Err(Error::SyntaxError("Expected , between coordinates".to_string()));
+        }
+        self.advance();
+        let y = self.parse_expression()?;
+        if !matches!(self.current_token().token_type, TokenType::RightParen) {
+            return Err(Error::SyntaxError("Expected ) after coordinates".to_string()));
+        }
+        self.advance();
+        Ok(Coord { x: Box::new(x), y: Box::new(y), step })
+    }
+
+    /// Parenthesised, comma-separated arguments of a function call
+    fn parse_call_arguments(&mut self) -> Result<Vec<AstNode>> {
+        self.advance(); // Skip (
+        let mut args = Vec::new();
+        if let TokenType::RightParen = self.current_token().token_type {
+            self.advance();
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse_expression()?);
+
+            match &self.current_token().token_type {
+                TokenType::Comma => self.advance(),
+                TokenType::RightParen => {
+                    self.advance();
+                    return Ok(args);
+                }
+                _ => return Err(Error::SyntaxError("Expected ',' or ')' in function call".to_string())),
+            }
+        }
+    }
+
+    /// An argument that may be left out, as in `LINE (0,0)-(9,9),,B`
+    fn parse_optional_argument(&mut self) -> Result<Option<Box<AstNode>>> {
+        match self.current_token().token_type {
+            TokenType::Comma | TokenType::Colon | TokenType::Newline | TokenType::Eof | TokenType::Else => Ok(None),
+            _ => Ok(Some(Box::new(self.parse_expression()?))),
+        }
+    }
+
+    /// Statements after THEN or ELSE, where a bare line number means GOTO
+    fn parse_branch(&mut self) -> Result<Vec<AstNode>> {
+        if let Some(line) = self.line_number_literal() {
+            self.advance();
+            return Ok(vec![AstNode::Goto(line)]);
+        }
+        self.parse_statements()
     }
 
     fn parse_for(&mut self) -> Result<AstNode> {
@@ -874,9 +1296,9 @@ impl Parser {
     fn parse_goto(&mut self) -> Result<AstNode> {
         self.advance(); // Skip GOTO
 
-        if let TokenType::Integer(line) = self.current_token().token_type {
+        if let Some(line) = self.line_number_literal() {
             self.advance();
-            Ok(AstNode::Goto(line as u32))
+            Ok(AstNode::Goto(line))
         } else {
             Err(Error::SyntaxError("Expected line number after GOTO".to_string()))
         }
@@ -885,9 +1307,9 @@ impl Parser {
     fn parse_gosub(&mut self) -> Result<AstNode> {
         self.advance(); // Skip GOSUB
 
-        if let TokenType::Integer(line) = self.current_token().token_type {
+        if let Some(line) = self.line_number_literal() {
             self.advance();
-            Ok(AstNode::Gosub(line as u32))
+            Ok(AstNode::Gosub(line))
         } else {
             Err(Error::SyntaxError("Expected line number after GOSUB".to_string()))
         }
@@ -1130,7 +1552,12 @@ impl Parser {
                 self.advance();
                 Ok(node)
             }
-            TokenType::Float(val) => {
+            TokenType::Single(val) => {
+                let node = AstNode::Literal(Value::Single(*val));
+                self.advance();
+                Ok(node)
+            }
+            TokenType::Double(val) => {
                 let node = AstNode::Literal(Value::Double(*val));
                 self.advance();
                 Ok(node)
@@ -1146,30 +1573,48 @@ impl Parser {
 
                 // Check for function call
                 if let TokenType::LeftParen = self.current_token().token_type {
-                    self.advance();
+                    let args = self.parse_call_arguments()?;
+                    Ok(AstNode::FunctionCall(name, args))
+                } else if name == "CSRLIN" {
+                    // CSRLIN takes no arguments
+                    Ok(AstNode::FunctionCall(name, Vec::new()))
+                } else {
+                    Ok(AstNode::Variable(name))
+                }
+            }
+            TokenType::Screen => {
+                // SCREEN(row, col [, z]) reads the text screen
+                self.advance();
+                if !matches!(self.current_token().token_type, TokenType::LeftParen) {
+                    return Err(Error::SyntaxError("Expected ( after SCREEN".to_string()));
+                }
+                let args = self.parse_call_arguments()?;
+                Ok(AstNode::FunctionCall("SCREEN".to_string(), args))
+            }
+            TokenType::Fn => {
+                // FN is lexed on its own; the call keeps its FNname form
+                self.advance();
+                if let TokenType::Identifier(name) = &self.current_token().token_type {
+                    let name = format!("FN{}", name);
                     let mut args = Vec::new();
-
-                    if let TokenType::RightParen = self.current_token().token_type {
+                    self.advance();
+                    if let TokenType::LeftParen = self.current_token().token_type {
                         self.advance();
-                        return Ok(AstNode::FunctionCall(name, args));
-                    }
-
-                    loop {
-                        args.push(self.parse_expression()?);
-
-                        match &self.current_token().token_type {
-                            TokenType::Comma => self.advance(),
-                            TokenType::RightParen => {
-                                self.advance();
-                                break;
+                        loop {
+                            args.push(self.parse_expression()?);
+                            match &self.current_token().token_type {
+                                TokenType::Comma => self.advance(),
+                                TokenType::RightParen => {
+                                    self.advance();
+                                    break;
+                                }
+                                _ => return Err(Error::SyntaxError("Expected ',' or ')' in function call".to_string())),
                             }
-                            _ => return Err(Error::SyntaxError("Expected ',' or ')' in function call".to_string())),
                         }
                     }
-
                     Ok(AstNode::FunctionCall(name, args))
                 } else {
-                    Ok(AstNode::Variable(name))
+                    Err(Error::SyntaxError("Expected function name after FN".to_string()))
                 }
             }
             TokenType::LeftParen => {
@@ -1187,10 +1632,25 @@ impl Parser {
         }
     }
 
+    /// Line number at the current token, if there is one. Numbers above
+    /// 32767 lex as single-precision constants, so those count as well.
+    fn line_number_literal(&self) -> Option<u32> {
+        match self.current_token().token_type {
+            TokenType::Integer(n) if n >= 0 => Some(n as u32),
+            TokenType::Single(f) if f.fract() == 0.0 && (0.0..=65529.0).contains(&f) => Some(f as u32),
+            _ => None,
+        }
+    }
+
     fn current_token(&self) -> &Token {
         &self.tokens[self.position]
     }
 
+    /// Type of the token after the current one
+    fn peek_token_type(&self) -> Option<&TokenType> {
+        self.tokens.get(self.position + 1).map(|token| &token.token_type)
+    }
+
     fn advance(&mut self) {
         if self.position < self.tokens.len() - 1 {
             self.position += 1;
@@ -1211,7 +1671,6 @@ mod tests {
     fn test_parser_initialization() {
         let tokens = vec![Token::new(TokenType::Eof, 1, 1)];
         let _parser = Parser::new(tokens);
-        assert!(true);
     }
 
     #[test]
@@ -1235,6 +1694,263 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_print_separators() {
+        let mut lexer = Lexer::new("PRINT A;B,TAB(5);SPC(2) \"X\";");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        match ast {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Print(items) => {
+                    assert_eq!(items.len(), 9);
+                    assert_eq!(items[1], PrintItem::Semicolon);
+                    assert_eq!(items[3], PrintItem::Comma);
+                    assert_eq!(items[4], PrintItem::Tab(AstNode::Literal(Value::Integer(5))));
+                    assert_eq!(items[6], PrintItem::Spc(AstNode::Literal(Value::Integer(2))));
+                    assert_eq!(items[8], PrintItem::Semicolon);
+                }
+                _ => panic!("Expected Print node"),
+            },
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_if_line_numbers() {
+        let parse_if = |source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            match Parser::new(tokens).parse().unwrap() {
+                AstNode::Program(mut lines) => lines.remove(0),
+                _ => panic!("Expected Program node"),
+            }
+        };
+        let goto = |line| vec![AstNode::Goto(line)];
+
+        match parse_if("IF X = 0 THEN 200 ELSE 300") {
+            AstNode::If(_, then_branch, else_branch) => {
+                assert_eq!(then_branch, goto(200));
+                assert_eq!(else_branch, Some(goto(300)));
+            }
+            other => panic!("Expected If node, got {:?}", other),
+        }
+
+        match parse_if("IF X GOTO 200 ELSE PRINT 1") {
+            AstNode::If(_, then_branch, Some(else_branch)) => {
+                assert_eq!(then_branch, goto(200));
+                assert!(matches!(else_branch[0], AstNode::Print(_)));
+            }
+            other => panic!("Expected If node with ELSE, got {:?}", other),
+        }
+
+        // Each ELSE pairs with the nearest IF still without one
+        match parse_if("IF A THEN IF B THEN 10 ELSE 20 ELSE 30") {
+            AstNode::If(_, then_branch, else_branch) => {
+                assert_eq!(then_branch, vec![AstNode::If(
+                    Box::new(AstNode::Variable("B".to_string())),
+                    goto(10),
+                    Some(goto(20)),
+                )]);
+                assert_eq!(else_branch, Some(goto(30)));
+            }
+            other => panic!("Expected If node, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_line_statement() {
+        let parse_line = |source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            match Parser::new(tokens).parse().unwrap() {
+                AstNode::Program(mut lines) => lines.remove(0),
+                _ => panic!("Expected Program node"),
+            }
+        };
+        let int = |n| Box::new(AstNode::Literal(Value::Integer(n)));
+
+        match parse_line("LINE (10,10)-(100,50),2,BF") {
+            AstNode::DrawLine(Some(start), end, color, shape, style) => {
+                assert_eq!(start, Coord { x: int(10), y: int(10), step: false });
+                assert_eq!(end, Coord { x: int(100), y: int(50), step: false });
+                assert_eq!(color, Some(int(2)));
+                assert_eq!(shape, Some(LineBox::Filled));
+                assert_eq!(style, None);
+            }
+            other => panic!("Expected DrawLine node, got {:?}", other),
+        }
+
+        match parse_line("LINE -(200,100)") {
+            AstNode::DrawLine(None, end, None, None, None) => assert!(!end.step),
+            other => panic!("Expected DrawLine without start, got {:?}", other),
+        }
+
+        match parse_line("LINE STEP(5,5)-STEP(10,0)") {
+            AstNode::DrawLine(Some(start), end, None, None, None) => assert!(start.step && end.step),
+            other => panic!("Expected DrawLine with STEP, got {:?}", other),
+        }
+
+        match parse_line("LINE (0,0)-(319,199),1,,&HAAAA") {
+            AstNode::DrawLine(Some(_), _, Some(_), None, Some(style)) => {
+                assert!(matches!(*style, AstNode::Literal(Value::Integer(_))));
+            }
+            other => panic!("Expected DrawLine with style, got {:?}", other),
+        }
+
+        let tokens = Lexer::new("LINE (0,0)-(9,9),1,X").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_circle_statement() {
+        let tokens = Lexer::new("CIRCLE STEP(160,100),50,2,-0.5,-2.1,0.8").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Circle(centre, _, Some(_), Some(start), Some(_), Some(aspect)) => {
+                    assert!(centre.step);
+                    assert!(matches!(**start, AstNode::UnaryOp(UnaryOperator::Negate, _)));
+                    assert_eq!(**aspect, AstNode::Literal(Value::Single(0.8)));
+                }
+                other => panic!("Expected Circle node, got {:?}", other),
+            },
+            _ => panic!("Expected Program node"),
+        }
+
+        let tokens = Lexer::new("CIRCLE (10,10),5,,,,2").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::Circle(_, _, None, None, None, Some(_))));
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_draw_statement() {
+        let tokens = Lexer::new("DRAW \"X\" + VARPTR$(A$)").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Draw(commands) => match &**commands {
+                    AstNode::BinaryOp(BinaryOperator::Add, _, pointer) => {
+                        assert!(matches!(&**pointer, AstNode::FunctionCall(name, _) if name == "VARPTR$"));
+                    }
+                    other => panic!("Expected concatenation, got {:?}", other),
+                },
+                other => panic!("Expected Draw node, got {:?}", other),
+            },
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_get_put_statements() {
+        let tokens = Lexer::new("GET (0,0)-STEP(9,9),SPRITE%(2)\nPUT (5,5),SPRITE%,PSET\nPUT(1,1),B%").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                match &lines[0] {
+                    AstNode::GraphicsGet(start, end, array, indices) => {
+                        assert!(!start.step && end.step);
+                        assert_eq!(array, "SPRITE%");
+                        assert_eq!(indices.len(), 1);
+                    }
+                    other => panic!("Expected GraphicsGet node, got {:?}", other),
+                }
+                assert!(matches!(&lines[1], AstNode::GraphicsPut(_, _, indices, Some(PutAction::Pset)) if indices.is_empty()));
+                assert!(matches!(&lines[2], AstNode::GraphicsPut(_, _, _, None)));
+            }
+            _ => panic!("Expected Program node"),
+        }
+
+        let tokens = Lexer::new("GET #1, 3").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => assert!(matches!(&lines[0], AstNode::FileGet(_, Some(_)))),
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_view_window() {
+        let tokens = Lexer::new("VIEW SCREEN (10,10)-(100,80),,2\nWINDOW (-1,-1)-(1,1)\nVIEW\nWINDOW SCREEN (0,0)-(9,9)").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::View(Some(_), true, None, Some(_))));
+                assert!(matches!(&lines[1], AstNode::Window(Some(_), false)));
+                assert!(matches!(&lines[2], AstNode::View(None, false, None, None)));
+                assert!(matches!(&lines[3], AstNode::Window(Some(_), true)));
+            }
+            _ => panic!("Expected Program node"),
+        }
+        assert!(Parser::new(Lexer::new("VIEW SCREEN").tokenize().unwrap()).parse().is_err());
+
+        let tokens = Lexer::new("VIEW PRINT 5 TO 20\nVIEW PRINT").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::ViewPrint(Some(_))));
+                assert!(matches!(&lines[1], AstNode::ViewPrint(None)));
+            }
+            _ => panic!("Expected Program node"),
+        }
+        assert!(Parser::new(Lexer::new("VIEW PRINT 5").tokenize().unwrap()).parse().is_err());
+    }
+
+    #[test]
+    fn test_parse_locate_statement() {
+        let tokens = Lexer::new("LOCATE 5, 10\nLOCATE ,,1,0,7\nLOCATE 3").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::Locate([Some(_), Some(_), None, None, None])));
+                assert!(matches!(&lines[1], AstNode::Locate([None, None, Some(_), Some(_), Some(_)])));
+                assert!(matches!(&lines[2], AstNode::Locate([Some(_), None, None, None, None])));
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_screen_statement() {
+        let tokens = Lexer::new("SCREEN 9\nSCREEN ,,1,0\nSCREEN 1,0").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::Screen(Some(_), None, None, None)));
+                assert!(matches!(&lines[1], AstNode::Screen(None, None, Some(_), Some(_))));
+                assert!(matches!(&lines[2], AstNode::Screen(Some(_), Some(_), None, None)));
+            }
+            _ => panic!("Expected Program node"),
+        }
+        assert!(Parser::new(Lexer::new("SCREEN").tokenize().unwrap()).parse().is_err());
+
+        let tokens = Lexer::new("PCOPY 1, 0").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => assert!(matches!(&lines[0], AstNode::Pcopy(_, _))),
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_palette_statement() {
+        let tokens = Lexer::new("PALETTE 1, 20\nPALETTE USING P%(4)\nPALETTE").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => {
+                assert!(matches!(&lines[0], AstNode::Palette(Some(_))));
+                assert!(matches!(&lines[1], AstNode::PaletteUsing(name, indices) if name == "P%" && indices.len() == 1));
+                assert!(matches!(&lines[2], AstNode::Palette(None)));
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_paint_statement() {
+        let tokens = Lexer::new("PAINT STEP(5,5),CHR$(&HAA),,CHR$(0)").tokenize().unwrap();
+        match Parser::new(tokens).parse().unwrap() {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Paint(point, Some(_), None, Some(_)) => assert!(point.step),
+                other => panic!("Expected Paint node, got {:?}", other),
+            },
+            _ => panic!("Expected Program node"),
+        }
+    }
+
     #[test]
     fn test_parse_let_statement() {
         let mut lexer = Lexer::new("LET A = 10");
@@ -1271,7 +1987,7 @@ mod tests {
                         assert_eq!(exprs.len(), 1);
                         // Should be a binary operation
                         match &exprs[0] {
-                            AstNode::BinaryOp(BinaryOperator::Add, _, _) => {},
+                            PrintItem::Expr(AstNode::BinaryOp(BinaryOperator::Add, _, _)) => {},
                             _ => panic!("Expected binary operation"),
                         }
                     }
@@ -1293,7 +2009,7 @@ mod tests {
             AstNode::Program(lines) => {
                 assert_eq!(lines.len(), 1);
                 match &lines[0] {
-                    AstNode::Line(num, _) => {
+                    AstNode::Line(num, _, _) => {
                         assert_eq!(*num, 10);
                     }
                     _ => panic!("Expected Line node"),
@@ -1380,7 +2096,7 @@ mod tests {
                 assert_eq!(lines.len(), 1);
                 match &lines[0] {
                     AstNode::If(_, then_stmts, else_stmts) => {
-                        assert!(then_stmts.len() > 0);
+                        assert!(!then_stmts.is_empty());
                         assert!(else_stmts.is_none());
                     }
                     _ => panic!("Expected If node, got {:?}", lines[0]),
@@ -1402,7 +2118,7 @@ mod tests {
                 assert_eq!(lines.len(), 1);
                 match &lines[0] {
                     AstNode::If(_, then_stmts, else_stmts) => {
-                        assert!(then_stmts.len() > 0);
+                        assert!(!then_stmts.is_empty());
                         assert!(else_stmts.is_some());
                     }
                     _ => panic!("Expected If node, got {:?}", lines[0]),
@@ -1496,4 +2212,124 @@ mod tests {
             _ => panic!("Expected Program node"),
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parse_load_and_save() {
+        let mut lexer = Lexer::new("LOAD \"GAME\",R\nSAVE \"GAME.BAS\",A");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        match ast {
+            AstNode::Program(lines) => {
+                assert_eq!(lines[0], AstNode::Load("GAME".to_string(), true));
+                assert_eq!(lines[1], AstNode::Save("GAME.BAS".to_string(), Some("A".to_string())));
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_typed_literals() {
+        let mut lexer = Lexer::new("X = 1.5 + 2# + &H10\nGOTO 40000");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        match ast {
+            AstNode::Program(lines) => {
+                let expected = AstNode::BinaryOp(
+                    BinaryOperator::Add,
+                    Box::new(AstNode::BinaryOp(
+                        BinaryOperator::Add,
+                        Box::new(AstNode::Literal(Value::Single(1.5))),
+                        Box::new(AstNode::Literal(Value::Double(2.0))),
+                    )),
+                    Box::new(AstNode::Literal(Value::Integer(16))),
+                );
+                assert_eq!(lines[0], AstNode::Let("X".to_string(), Box::new(expected)));
+                assert_eq!(lines[1], AstNode::Goto(40000));
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let mut lexer = Lexer::new("10 X = 1: PRINT X");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let ast = parser.parse().unwrap();
+
+        match ast {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Line(10, statements, spans) => {
+                    assert_eq!(statements.len(), 2);
+                    assert_eq!((spans[0].start_column, spans[0].end_column), (4, 9));
+                    assert_eq!((spans[1].start_column, spans[1].end_column), (11, 18));
+                    assert_eq!(spans[1].statement, 1);
+                    assert_eq!(spans[1].line_number, Some(10));
+                }
+                other => panic!("Expected Line node, got {:?}", other),
+            },
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_error_location() {
+        let mut lexer = Lexer::new("10 PRINT 1\n20 IF X > 5 PRINT \"hi\"");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let err = parser.parse().unwrap_err();
+
+        let span = err.span().unwrap();
+        assert_eq!(span.line, 2);
+        assert_eq!(span.line_number, Some(20));
+        assert_eq!((span.start_column, span.end_column), (13, 18));
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let mut lexer = Lexer::new("10 PRINT 1: X = : PRINT 2\n20 IF X PRINT\n30 END");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let (ast, errors) = parser.parse_with_errors();
+
+        let lines: Vec<Option<u32>> = errors.iter().map(|e| e.span().unwrap().line_number).collect();
+        assert_eq!(lines, vec![Some(10), Some(20)]);
+
+        match ast {
+            AstNode::Program(lines) => {
+                assert_eq!(lines.len(), 3);
+                match &lines[0] {
+                    AstNode::Line(10, statements, spans) => {
+                        assert_eq!(statements.len(), 3);
+                        assert_eq!(spans.len(), 3);
+                        assert!(matches!(statements[1], AstNode::Invalid(_)));
+                        assert!(matches!(statements[2], AstNode::Print(_)));
+                    }
+                    other => panic!("Expected Line node, got {:?}", other),
+                }
+            }
+            _ => panic!("Expected Program node"),
+        }
+    }
+
+    #[test]
+    fn test_stray_else() {
+        let tokens = Lexer::new("10 PRINT 1 ELSE 20: PRINT 3\n20 END").tokenize().unwrap();
+        let (ast, errors) = Parser::new(tokens).parse_with_errors();
+        assert_eq!(errors.len(), 1);
+        match ast {
+            AstNode::Program(lines) => match &lines[0] {
+                AstNode::Line(10, statements, _) => {
+                    assert!(matches!(statements[1], AstNode::Invalid(_)));
+                    assert!(matches!(statements[2], AstNode::Print(_)));
+                }
+                other => panic!("Expected Line node, got {:?}", other),
+            },
+            _ => panic!("Expected Program node"),
+        }
+    }
+}
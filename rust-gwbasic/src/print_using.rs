@@ -0,0 +1,443 @@
+//! PRINT USING formatting for GW-BASIC
+//!
+//! A format string holds literal text and fields. String fields are `!`
+//! (first character), `&` (whole string) and `\  \` (as many characters as
+//! the field is wide). Numeric fields are built from `#` digit positions with
+//! an optional `.`, `,` thousands separators, a leading `+`, a trailing `+`
+//! or `-`, `**` asterisk fill, `$$` or `**$` floating dollar and `^^^^`
+//! exponent. `_` prints the next character literally. A number too wide for
+//! its field is printed in full after a `%`.
+
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// A field in a format string
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    /// `!`: the first character of a string
+    FirstChar,
+
+    /// `&`: the whole string
+    WholeString,
+
+    /// `\  \`: a fixed number of characters
+    String(usize),
+
+    Number(NumberField),
+}
+
+/// Layout of a numeric field
+#[derive(Debug, Clone, PartialEq, Default)]
+struct NumberField {
+    /// Characters the field takes up in the format string
+    width: usize,
+
+    /// Digit positions in front of the decimal point
+    digits_before: usize,
+
+    /// Digit positions after the decimal point
+    decimals: usize,
+
+    /// Whether the field has a decimal point
+    point: bool,
+
+    /// Leading `+`: always print the sign in front
+    leading_plus: bool,
+
+    /// Trailing `+` or `-`: print the sign (or a space for `-`) after
+    trailing_sign: Option<char>,
+
+    /// `**`: fill leading space with asterisks
+    asterisk: bool,
+
+    /// `$$` or `**$`: dollar sign in front of the number
+    dollar: bool,
+
+    /// `,` before the point: separate thousands
+    comma: bool,
+
+    /// Exponent digits for `^^^^` (2) or `^^^^^` (3), 0 for fixed point
+    exponent: usize,
+}
+
+/// Format `values` with a PRINT USING format string. The format is reused
+/// from the start while values remain; output stops at the first field
+/// that has no value left.
+pub fn format_using(format: &str, values: &[Value]) -> Result<String> {
+    let chars: Vec<char> = format.chars().collect();
+    let mut output = String::new();
+    let mut values = values.iter();
+    let mut next = values.next();
+
+    loop {
+        let mut pos = 0;
+        let mut used_field = false;
+        while pos < chars.len() {
+            if let Some((field, len)) = parse_field(&chars, pos) {
+                let Some(value) = next else {
+                    return Ok(output);
+                };
+                output.push_str(&format_field(&field, value)?);
+                next = values.next();
+                used_field = true;
+                pos += len;
+            } else if chars[pos] == '_' && pos + 1 < chars.len() {
+                output.push(chars[pos + 1]);
+                pos += 2;
+            } else {
+                output.push(chars[pos]);
+                pos += 1;
+            }
+        }
+        if next.is_none() {
+            return Ok(output);
+        }
+        if !used_field {
+            // Values left over but nowhere to put them
+            return Err(Error::RuntimeError("Illegal function call".to_string()));
+        }
+    }
+}
+
+/// Recognise a field starting at `pos`, returning it with its length
+fn parse_field(chars: &[char], pos: usize) -> Option<(Field, usize)> {
+    match chars[pos] {
+        '!' => Some((Field::FirstChar, 1)),
+        '&' => Some((Field::WholeString, 1)),
+        '\\' => {
+            let spaces = chars[pos + 1..].iter().take_while(|&&c| c == ' ').count();
+            if chars.get(pos + 1 + spaces) == Some(&'\\') {
+                Some((Field::String(spaces + 2), spaces + 2))
+            } else {
+                None
+            }
+        }
+        _ => parse_number_field(chars, pos).map(|field| {
+            let width = field.width;
+            (Field::Number(field), width)
+        }),
+    }
+}
+
+fn parse_number_field(chars: &[char], start: usize) -> Option<NumberField> {
+    let at = |i: usize| chars.get(i).copied();
+    let mut field = NumberField::default();
+    let mut pos = start;
+
+    if at(pos) == Some('+') {
+        field.leading_plus = true;
+        pos += 1;
+    }
+
+    // ** and $$ come in pairs; **$ adds a dollar sign to the asterisks
+    match (at(pos), at(pos + 1)) {
+        (Some('*'), Some('*')) => {
+            field.asterisk = true;
+            field.digits_before += 2;
+            pos += 2;
+            if at(pos) == Some('$') {
+                field.dollar = true;
+                pos += 1;
+            }
+        }
+        (Some('$'), Some('$')) => {
+            field.dollar = true;
+            field.digits_before += 1;
+            pos += 2;
+        }
+        (Some('*'), _) | (Some('$'), _) => return None,
+        _ => {}
+    }
+
+    // Digits, commas and the decimal point
+    if matches!(at(pos), Some('#') | Some('.')) {
+        while let Some(c) = at(pos) {
+            match c {
+                '.' if !field.point => field.point = true,
+                '#' if field.point => field.decimals += 1,
+                '#' => field.digits_before += 1,
+                ',' if !field.point => {
+                    field.comma = true;
+                    field.digits_before += 1;
+                }
+                _ => break,
+            }
+            pos += 1;
+        }
+    }
+
+    // A bare sign or point is literal text
+    if field.digits_before == 0 && field.decimals == 0 {
+        return None;
+    }
+
+    if (pos..pos + 5).all(|i| at(i) == Some('^')) {
+        field.exponent = 3;
+        pos += 5;
+    } else if (pos..pos + 4).all(|i| at(i) == Some('^')) {
+        field.exponent = 2;
+        pos += 4;
+    }
+
+    if !field.leading_plus {
+        if let Some(sign @ ('+' | '-')) = at(pos) {
+            field.trailing_sign = Some(sign);
+            pos += 1;
+        }
+    }
+
+    field.width = pos - start;
+    Some(field)
+}
+
+fn format_field(field: &Field, value: &Value) -> Result<String> {
+    let type_mismatch = || Error::TypeError("Type mismatch".to_string());
+    match field {
+        Field::Number(field) => {
+            if !value.is_numeric() {
+                return Err(type_mismatch());
+            }
+            format_number(field, value)
+        }
+        _ => {
+            let Value::String(s) = value else {
+                return Err(type_mismatch());
+            };
+            Ok(match field {
+                Field::FirstChar => format!("{:<1.1}", s),
+                Field::String(width) => format!("{:<width$.width$}", s, width = width),
+                _ => s.clone(),
+            })
+        }
+    }
+}
+
+fn format_number(field: &NumberField, value: &Value) -> Result<String> {
+    if field.digits_before + field.decimals > 24 {
+        return Err(Error::RuntimeError("Illegal function call".to_string()));
+    }
+    let (digits, point, negative) = decimal_digits(value);
+
+    let sign = if negative { "-" } else { "+" };
+    let mut prefix = String::new();
+    let mut suffix = String::new();
+    match field.trailing_sign {
+        Some('+') => suffix.push_str(sign),
+        Some(_) => suffix.push(if negative { '-' } else { ' ' }),
+        None if field.leading_plus => prefix.push_str(sign),
+        None if negative => prefix.push('-'),
+        None => {}
+    }
+    if field.dollar {
+        prefix.push('$');
+    }
+
+    let text = if field.exponent > 0 {
+        // One digit position holds the sign unless the field has its own
+        let reserved = usize::from(!field.leading_plus && field.trailing_sign.is_none());
+        let before = field.digits_before.saturating_sub(reserved);
+        format!("{}{}{}", prefix, scientific(&digits, point, before, field), suffix)
+    } else {
+        let with_zero = format!("{}{}{}", prefix, fixed(&digits, point, field, field.digits_before > 0), suffix);
+        if with_zero.chars().count() > field.width && field.digits_before > 0 {
+            // Drop the leading zero of a fraction before giving up
+            format!("{}{}{}", prefix, fixed(&digits, point, field, false), suffix)
+        } else {
+            with_zero
+        }
+    };
+
+    let len = text.chars().count();
+    if len > field.width {
+        Ok(format!("%{}", text))
+    } else {
+        let fill = if field.asterisk { "*" } else { " " };
+        Ok(format!("{}{}", fill.repeat(field.width - len), text))
+    }
+}
+
+/// Fixed-point digits of a number, without the sign
+fn fixed(digits: &[u8], point: i32, field: &NumberField, leading_zero: bool) -> String {
+    let (digits, point) = round_digits(digits, point, point + field.decimals as i32);
+    let digit = |i: i32| {
+        if i >= 0 && (i as usize) < digits.len() {
+            (b'0' + digits[i as usize]) as char
+        } else {
+            '0'
+        }
+    };
+
+    let mut whole: String = (0..point.max(0)).map(digit).collect();
+    if field.comma {
+        whole = group_thousands(&whole);
+    }
+    if whole.is_empty() && leading_zero {
+        whole.push('0');
+    }
+
+    let mut text = whole;
+    if field.point {
+        text.push('.');
+        text.extend((0..field.decimals as i32).map(|i| digit(point + i)));
+    }
+    text
+}
+
+/// Mantissa and exponent of a number, without the sign
+fn scientific(digits: &[u8], point: i32, before: usize, field: &NumberField) -> String {
+    let significant = (before + field.decimals).max(1) as i32;
+    let (digits, point) = round_digits(digits, point, significant);
+    let exponent = if digits.is_empty() { 0 } else { point - before as i32 };
+    let digit = |i: usize| (b'0' + digits.get(i).copied().unwrap_or(0)) as char;
+
+    let mut text: String = (0..before).map(digit).collect();
+    if field.point {
+        text.push('.');
+        text.extend((before..before + field.decimals).map(digit));
+    }
+    let exp_sign = if exponent < 0 { '-' } else { '+' };
+    text.push_str(&format!(
+        "E{}{:0width$}",
+        exp_sign,
+        exponent.abs(),
+        width = field.exponent
+    ));
+    text
+}
+
+/// Decimal digits of a number, the position of the decimal point relative
+/// to them (value = 0.DIGITS x 10^point) and whether it is negative
+fn decimal_digits(value: &Value) -> (Vec<u8>, i32, bool) {
+    // Singles keep only the digits they can hold, as GW-BASIC shows them
+    let (text, negative) = match value {
+        Value::Single(f) => (format!("{:e}", f.abs()), *f < 0.0),
+        Value::Integer(i) => (format!("{:e}", (*i as f64).abs()), *i < 0),
+        other => {
+            let d = other.as_double().unwrap_or(0.0);
+            (format!("{:e}", d.abs()), d < 0.0)
+        }
+    };
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    let mut digits: Vec<u8> = mantissa.bytes().filter(u8::is_ascii_digit).map(|b| b - b'0').collect();
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+    if digits.is_empty() {
+        return (digits, 0, false);
+    }
+    (digits, exponent.parse::<i32>().unwrap_or(0) + 1, negative)
+}
+
+/// Round to the first `keep` digits, halves going up
+fn round_digits(digits: &[u8], mut point: i32, keep: i32) -> (Vec<u8>, i32) {
+    if keep < 0 {
+        return (Vec::new(), 0);
+    }
+    let keep = keep as usize;
+    let mut rounded = digits[..keep.min(digits.len())].to_vec();
+    if digits.get(keep).is_some_and(|&d| d >= 5) {
+        let mut i = keep;
+        loop {
+            if i == 0 {
+                rounded.insert(0, 1);
+                point += 1;
+                break;
+            }
+            i -= 1;
+            if rounded[i] == 9 {
+                rounded[i] = 0;
+            } else {
+                rounded[i] += 1;
+                break;
+            }
+        }
+    }
+    while rounded.last() == Some(&0) {
+        rounded.pop();
+    }
+    if rounded.is_empty() {
+        point = 0;
+    }
+    (rounded, point)
+}
+
+fn group_thousands(whole: &str) -> String {
+    let mut grouped = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(text: &str) -> Value {
+        Value::String(text.to_string())
+    }
+
+    fn n(value: f32) -> Value {
+        Value::Single(value)
+    }
+
+    #[test]
+    fn test_numeric_fields() {
+        // Examples from the GW-BASIC manual
+        let cases: &[(&str, Vec<Value>, &str)] = &[
+            ("##.##", vec![n(0.78)], " 0.78"),
+            ("###.##", vec![n(987.654)], "987.65"),
+            ("##.## ", vec![n(10.2), n(5.3), n(66.789), n(0.234)], "10.20  5.30 66.79  0.23 "),
+            ("+##.## ", vec![n(-68.95), n(2.4), n(55.6), n(-0.9)], "-68.95  +2.40 +55.60  -0.90 "),
+            ("##.##- ", vec![n(-68.95), n(22.449), n(-7.01)], "68.95- 22.45   7.01- "),
+            ("**#.# ", vec![n(12.39), n(-0.9), n(765.1)], "*12.4 *-0.9 765.1 "),
+            ("$$###.##", vec![n(456.78)], " $456.78"),
+            ("**$##.##", vec![n(2.34)], "***$2.34"),
+            ("####,.##", vec![n(1234.5)], "1,234.50"),
+            ("##.##^^^^", vec![n(234.56)], " 2.35E+02"),
+            (".####^^^^-", vec![n(-888888.0)], ".8889E+06-"),
+            ("+.##^^^^", vec![n(123.0)], "+.12E+03"),
+            ("##.##", vec![n(111.22)], "%111.22"),
+            (".##", vec![n(0.999)], "%1.00"),
+            ("_!##.##_!", vec![n(12.34)], "!12.34!"),
+            ("###", vec![Value::Integer(-12)], "-12"),
+            ("###", vec![Value::Double(2.5)], "  3"),
+            ("#.##", vec![n(-0.5)], "-.50"),
+            ("##.##^^^^^", vec![n(0.0)], " 0.00E+000"),
+        ];
+        for (format, values, expected) in cases {
+            assert_eq!(&format_using(format, values).unwrap(), expected, "format {:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_string_fields() {
+        let cases: &[(&str, Vec<Value>, &str)] = &[
+            ("!", vec![s("Lookout")], "L"),
+            ("\\  \\", vec![s("ABCDEF")], "ABCD"),
+            ("\\  \\|", vec![s("AB")], "AB  |"),
+            ("&!", vec![s("abc"), s("xyz")], "abcx"),
+            ("Name: & ", vec![s("A"), s("B")], "Name: A Name: B "),
+            ("\\ x", vec![], "\\ x"),
+        ];
+        for (format, values, expected) in cases {
+            assert_eq!(&format_using(format, values).unwrap(), expected, "format {:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_output_stops_at_unfilled_field() {
+        assert_eq!(format_using("Total: ### and ###", &[n(5.0)]).unwrap(), "Total:   5 and ");
+        assert_eq!(format_using("$ + .", &[]).unwrap(), "$ + .");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(format_using("##", &[s("x")]).is_err());
+        assert!(format_using("&", &[n(1.0)]).is_err());
+        assert!(format_using("abc", &[n(1.0)]).is_err());
+    }
+}
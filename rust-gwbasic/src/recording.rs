@@ -0,0 +1,326 @@
+//! Recording how a program draws over time
+//!
+//! A recorder takes a snapshot of the screen every so many statements or
+//! so much wall-clock time, and whenever the screen is about to be cleared
+//! or another page is shown. All frames are the size of the first, later
+//! screens of another SCREEN mode being stretched to it as a monitor would.
+//! The frames are saved as an animated GIF or as a numbered sequence of PNG
+//! images.
+
+use crate::error::{Error, Result};
+use crate::graphics::Screen;
+use crate::snapshot::Snapshot;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Largest code a GIF LZW stream can use
+const MAX_CODES: u16 = 4096;
+
+/// How often a frame is taken while the program runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordInterval {
+    /// After every so many statements
+    Statements(u32),
+
+    /// After so much wall-clock time, checked between statements
+    Time(Duration),
+}
+
+impl Default for RecordInterval {
+    fn default() -> Self {
+        RecordInterval::Statements(100)
+    }
+}
+
+/// Frames taken from a screen as a program runs
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    interval: RecordInterval,
+
+    /// How long each frame is shown when played back
+    frame_delay: Duration,
+
+    frames: Vec<Snapshot>,
+    statements: u32,
+    last_frame: Instant,
+}
+
+impl Recorder {
+    /// A recorder with the given interval. Frames play back at that
+    /// interval if it is a time, and every 100 ms otherwise.
+    pub fn new(interval: RecordInterval) -> Self {
+        let frame_delay = match interval {
+            RecordInterval::Time(time) => time,
+            RecordInterval::Statements(_) => Duration::from_millis(100),
+        };
+        Recorder {
+            interval,
+            frame_delay,
+            frames: Vec::new(),
+            statements: 0,
+            last_frame: Instant::now(),
+        }
+    }
+
+    /// Show each frame for `delay` when played back
+    pub fn with_frame_delay(mut self, delay: Duration) -> Self {
+        self.frame_delay = delay;
+        self
+    }
+
+    pub fn frames(&self) -> &[Snapshot] {
+        &self.frames
+    }
+
+    /// Count a statement, taking a frame if the interval is up
+    pub fn tick(&mut self, screen: &Screen) {
+        self.statements += 1;
+        let due = match self.interval {
+            RecordInterval::Statements(count) => self.statements >= count.max(1),
+            RecordInterval::Time(time) => self.last_frame.elapsed() >= time,
+        };
+        if due {
+            self.capture(screen);
+        }
+    }
+
+    /// Take a frame now, unless the screen has not changed since the last
+    pub fn capture(&mut self, screen: &Screen) {
+        self.statements = 0;
+        self.last_frame = Instant::now();
+        let mut snapshot = screen.snapshot();
+        if let Some(first) = self.frames.first() {
+            if (snapshot.width, snapshot.height) != (first.width, first.height) {
+                snapshot = snapshot.resized(first.width, first.height);
+            }
+        }
+        if self.frames.last() != Some(&snapshot) {
+            self.frames.push(snapshot);
+        }
+    }
+
+    /// Write the frames to a file: an animated GIF if the name ends in
+    /// .gif, otherwise numbered PNG images, `out.png` becoming
+    /// `out-0000.png`, `out-0001.png` and so on
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let write_error = |e: std::io::Error| Error::IoError(format!("Cannot write {}: {}", path.display(), e));
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gif")) {
+            return fs::write(path, self.to_gif()?).map_err(write_error);
+        }
+
+        let stem = path.file_stem().map_or("frame".into(), |stem| stem.to_string_lossy());
+        for (number, frame) in self.frames.iter().enumerate() {
+            frame.save(path.with_file_name(format!("{}-{:04}.png", stem, number)))?;
+        }
+        Ok(())
+    }
+
+    /// An animated GIF of the frames that loops forever. All frames share
+    /// one colour table, so together they may use at most 256 colours.
+    pub fn to_gif(&self) -> Result<Vec<u8>> {
+        let mut colors: Vec<u32> = Vec::new();
+        let mut indices: HashMap<u32, u8> = HashMap::new();
+        for &rgb in self.frames.iter().flat_map(|frame| &frame.pixels) {
+            if let Entry::Vacant(entry) = indices.entry(rgb) {
+                if colors.len() == 256 {
+                    return Err(Error::RuntimeError("Too many colours for a GIF".to_string()));
+                }
+                entry.insert(colors.len() as u8);
+                colors.push(rgb);
+            }
+        }
+
+        // The colour table holds 2^(bits + 1) entries
+        let bits = (colors.len().max(2) - 1).ilog2() as u8;
+        colors.resize(2 << bits, 0);
+
+        let (width, height) = self.frames.first().map_or((1, 1), |frame| (frame.width, frame.height));
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend((width as u16).to_le_bytes());
+        gif.extend((height as u16).to_le_bytes());
+        // Global colour table of 8-bit colours, no background, square pixels
+        gif.extend([0xF0 | bits, 0, 0]);
+        for rgb in &colors {
+            gif.extend([(rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8]);
+        }
+        // Loop forever
+        gif.extend(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        let delay = (self.frame_delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+        let min_code_size = (bits + 1).max(2);
+        for frame in &self.frames {
+            // Graphic control extension with the frame delay
+            gif.extend([0x21, 0xF9, 0x04, 0x00]);
+            gif.extend(delay.to_le_bytes());
+            gif.extend([0x00, 0x00]);
+
+            // Image at the top left corner, using the global colour table
+            gif.push(0x2C);
+            gif.extend([0, 0, 0, 0]);
+            gif.extend((frame.width as u16).to_le_bytes());
+            gif.extend((frame.height as u16).to_le_bytes());
+            gif.push(0);
+
+            let pixels: Vec<u8> = frame.pixels.iter().map(|rgb| indices[rgb]).collect();
+            gif.push(min_code_size);
+            for block in lzw_encode(&pixels, min_code_size).chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend(block);
+            }
+            gif.push(0);
+        }
+        gif.push(0x3B);
+        Ok(gif)
+    }
+}
+
+/// Codes packed into bytes from the lowest bit up
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.bits |= (code as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.bits as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Compress colour indices with the variable-width LZW of GIF
+fn lzw_encode(pixels: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut out = BitWriter { bytes: Vec::new(), bits: 0, count: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_code_size + 1;
+    let mut next = end + 1;
+    out.write(clear, size);
+
+    let Some((&first, rest)) = pixels.split_first() else {
+        out.write(end, size);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &pixel in rest {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        if next >= 1 << size && size < 12 {
+            size += 1;
+        }
+        if next < MAX_CODES {
+            table.insert((prefix, pixel), next);
+            next += 1;
+        } else {
+            // The table is full: start again
+            out.write(clear, size);
+            table.clear();
+            size = min_code_size + 1;
+            next = end + 1;
+        }
+        prefix = pixel as u16;
+    }
+    out.write(prefix, size);
+    if next >= 1 << size && size < 12 {
+        size += 1;
+    }
+    out.write(end, size);
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode a GIF LZW stream the way a viewer would
+    fn lzw_decode(bytes: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let (mut bits, mut count, mut input) = (0u32, 0u8, bytes.iter());
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        loop {
+            while count < size {
+                bits |= (*input.next().unwrap() as u32) << count;
+                count += 8;
+            }
+            let code = (bits & ((1 << size) - 1)) as u16;
+            bits >>= size;
+            count -= size;
+
+            if code == clear {
+                table = (0..clear + 2).map(|c| vec![c as u8]).collect();
+                size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match table.get(code as usize) {
+                Some(entry) => entry.clone(),
+                None => {
+                    let mut entry = previous.clone().unwrap();
+                    entry.push(entry[0]);
+                    entry
+                }
+            };
+            if let Some(mut previous) = previous {
+                if table.len() < MAX_CODES as usize {
+                    previous.push(entry[0]);
+                    table.push(previous);
+                }
+            }
+            if table.len() == 1 << size && size < 12 {
+                size += 1;
+            }
+            out.extend(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        let short = [1, 1, 1, 1, 2, 3, 1, 1, 2];
+        assert_eq!(lzw_decode(&lzw_encode(&short, 2), 2), short);
+
+        // Enough varied data to fill the code table several times
+        let long: Vec<u8> = (0..200_000u64).map(|i| ((i * i) >> 7) as u8 % 16).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&long, 4), 4), long);
+    }
+
+    #[test]
+    fn test_gif_frames() {
+        let frame = |rgb| Snapshot { width: 2, height: 2, pixels: vec![0, rgb, rgb, 0] };
+        let mut recorder = Recorder::new(RecordInterval::default());
+        recorder.frames = vec![frame(0xFF0000), frame(0x00FF00)];
+
+        let gif = recorder.to_gif().unwrap();
+        assert_eq!(&gif[..10], b"GIF89a\x02\x00\x02\x00");
+        // Three colours make a table of four
+        assert_eq!(gif[10], 0xF1);
+        assert_eq!(&gif[13..22], b"\x00\x00\x00\xFF\x00\x00\x00\xFF\x00");
+        assert_eq!(gif.last(), Some(&0x3B));
+    }
+}
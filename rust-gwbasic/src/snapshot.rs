@@ -0,0 +1,167 @@
+//! Screenshots of the graphics screen
+//!
+//! A snapshot holds the colours on display, as 0xRRGGBB, and can be written
+//! as a binary PPM or a PNG. PNG data is stored without compression, which
+//! keeps the encoder short and the output byte-for-byte reproducible.
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::path::Path;
+
+/// Largest block of data zlib can store uncompressed
+const STORED_BLOCK: usize = 0xFFFF;
+
+/// The colours of every pixel on the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub width: usize,
+    pub height: usize,
+
+    /// Colour of each pixel as 0xRRGGBB, row by row
+    pub pixels: Vec<u32>,
+}
+
+impl Snapshot {
+    /// Colour of the pixel at (x, y)
+    pub fn rgb(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// The snapshot stretched or shrunk to `width` by `height`, each pixel
+    /// taking the colour of the nearest one
+    pub fn resized(&self, width: usize, height: usize) -> Snapshot {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.rgb(x * self.width / width, y * self.height / height))
+            .collect();
+        Snapshot { width, height, pixels }
+    }
+
+    /// Red, green and blue bytes of every pixel
+    fn rgb_bytes(&self) -> impl Iterator<Item = [u8; 3]> + '_ {
+        self.pixels.iter().map(|&rgb| [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+    }
+
+    /// A binary (P6) PPM image
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.rgb_bytes().flatten());
+        ppm
+    }
+
+    /// An 8-bit RGB PNG image
+    pub fn to_png(&self) -> Vec<u8> {
+        // Each row starts with filter type 0, no filtering
+        let mut raw = Vec::with_capacity(self.height * (self.width * 3 + 1));
+        let mut rows = self.rgb_bytes();
+        for _ in 0..self.height {
+            raw.push(0);
+            raw.extend(rows.by_ref().take(self.width).flatten());
+        }
+
+        let mut header = Vec::new();
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // Bit depth 8, colour type 2 (RGB), deflate, no filter, no interlace
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Write the snapshot to a file: PPM if the name ends in .ppm, PNG
+    /// otherwise
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let ppm = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ppm"));
+        let data = if ppm { self.to_ppm() } else { self.to_png() };
+        fs::write(path, data).map_err(|e| Error::IoError(format!("Cannot write {}: {}", path.display(), e)))
+    }
+}
+
+/// Append a PNG chunk: length, type, data and a CRC of type and data
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream holding the data in uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(data).to_be_bytes());
+    zlib
+}
+
+/// CRC-32 as used by PNG chunks
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Adler-32 checksum that ends a zlib stream
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ppm() {
+        let snapshot = Snapshot { width: 2, height: 1, pixels: vec![0xFF0000, 0x0000AA] };
+        assert_eq!(snapshot.to_ppm(), b"P6\n2 1\n255\n\xFF\x00\x00\x00\x00\xAA".to_vec());
+    }
+
+    #[test]
+    fn test_resized() {
+        let snapshot = Snapshot { width: 2, height: 1, pixels: vec![0xFF0000, 0x0000AA] };
+        let wide = snapshot.resized(4, 2);
+        assert_eq!(wide.pixels, [0xFF0000, 0xFF0000, 0x0000AA, 0x0000AA].repeat(2));
+        assert_eq!(wide.resized(2, 1), snapshot);
+    }
+
+    #[test]
+    fn test_png() {
+        let snapshot = Snapshot { width: 1, height: 1, pixels: vec![0x123456] };
+        let png = snapshot.to_png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1A\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        // The IEND chunk always has the same CRC
+        assert_eq!(&png[png.len() - 8..], b"IEND\xAE\x42\x60\x82");
+        // Filter byte then the pixel, in one final stored block
+        let idat = 8 + 25;
+        assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+        assert_eq!(&png[idat + 8..idat + 23], b"\x78\x01\x01\x04\x00\xFB\xFF\x00\x12\x34\x56\x00\xF8\x00\x9D");
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+}
@@ -0,0 +1,256 @@
+//! Character-cell model of the text screen
+//!
+//! The screen is a grid of characters, each with a colour attribute, and a
+//! cursor. Text is written at the cursor, wrapping at the right edge and
+//! scrolling the text window set by VIEW PRINT when it runs off the bottom.
+//! The last row is kept for the function key display, so the window starts
+//! out as every row but that one.
+
+use crate::error::{Error, Result};
+
+/// Rows of the text screen in every SCREEN mode
+pub const TEXT_ROWS: usize = 25;
+
+/// Attribute of text written before any COLOR: light grey on black
+const DEFAULT_ATTRIBUTE: u8 = 7;
+
+fn illegal_function_call() -> Error {
+    Error::RuntimeError("Illegal function call".to_string())
+}
+
+/// One character position on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// Character code
+    pub ch: u8,
+
+    /// Colour attribute: in SCREEN 0 the foreground in bits 0-3, the
+    /// background in bits 4-6 and blinking in bit 7; in graphics modes the
+    /// foreground colour
+    pub attribute: u8,
+}
+
+impl Cell {
+    fn blank(attribute: u8) -> Self {
+        Cell { ch: b' ', attribute }
+    }
+}
+
+/// The characters on the screen and the text cursor
+#[derive(Debug, Clone)]
+pub struct TextScreen {
+    columns: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+
+    /// Cursor position, counting from 0. The column reaches `columns`
+    /// after the last one is written, and wraps on the next character.
+    row: usize,
+    column: usize,
+
+    /// First and last row of the text window, counting from 0
+    top: usize,
+    bottom: usize,
+
+    /// Attribute given to characters as they are written
+    attribute: u8,
+
+    /// Whether the cursor shows, and its first and last scan lines
+    cursor_visible: bool,
+    cursor_shape: (u8, u8),
+}
+
+impl TextScreen {
+    pub fn new(columns: usize, rows: usize) -> Self {
+        TextScreen {
+            columns,
+            rows,
+            cells: vec![Cell::blank(DEFAULT_ATTRIBUTE); columns * rows],
+            row: 0,
+            column: 0,
+            top: 0,
+            bottom: rows.saturating_sub(2),
+            attribute: DEFAULT_ATTRIBUTE,
+            cursor_visible: false,
+            cursor_shape: (6, 7),
+        }
+    }
+
+    /// Change the size, which clears the screen and the text window
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        let attribute = self.attribute;
+        *self = TextScreen::new(columns, rows);
+        self.attribute = attribute;
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Cursor row and column, counting from 0
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.column)
+    }
+
+    /// Whether the cursor shows, and its first and last scan lines
+    pub fn cursor_shape(&self) -> (bool, u8, u8) {
+        (self.cursor_visible, self.cursor_shape.0, self.cursor_shape.1)
+    }
+
+    /// First and last row of the text window, counting from 0
+    pub fn window(&self) -> (usize, usize) {
+        (self.top, self.bottom)
+    }
+
+    /// The cell at `row` and `column`, counting from 0
+    pub fn cell(&self, row: usize, column: usize) -> Option<Cell> {
+        if row < self.rows && column < self.columns {
+            Some(self.cells[row * self.columns + column])
+        } else {
+            None
+        }
+    }
+
+    /// Text of one row, without trailing spaces
+    pub fn row_text(&self, row: usize) -> String {
+        let cells = &self.cells[row * self.columns..(row + 1) * self.columns];
+        let text: String = cells.iter().map(|cell| cell.ch as char).collect();
+        text.trim_end().to_string()
+    }
+
+    pub fn attribute(&self) -> u8 {
+        self.attribute
+    }
+
+    pub fn set_attribute(&mut self, attribute: u8) {
+        self.attribute = attribute;
+    }
+
+    /// Write a character at the cursor and move the cursor on
+    pub fn put(&mut self, ch: u8) {
+        if self.column >= self.columns {
+            self.newline();
+        }
+        let row = self.row.min(self.rows - 1);
+        self.cells[row * self.columns + self.column] = Cell { ch, attribute: self.attribute };
+        self.column += 1;
+    }
+
+    /// Move the cursor to the start of the next row, scrolling the text
+    /// window if the cursor is on its last row (or below it)
+    pub fn newline(&mut self) {
+        self.column = 0;
+        if self.row < self.bottom {
+            self.row += 1;
+        } else {
+            self.row = self.bottom;
+            self.scroll();
+        }
+    }
+
+    /// Move the rows of the text window up one, blanking the last
+    fn scroll(&mut self) {
+        let (start, end) = (self.top * self.columns, (self.bottom + 1) * self.columns);
+        self.cells.copy_within(start + self.columns..end, start);
+        self.cells[end - self.columns..end].fill(Cell::blank(self.attribute));
+    }
+
+    /// Blank the text window and move the cursor to its top left (CLS)
+    pub fn clear(&mut self) {
+        let (start, end) = (self.top * self.columns, (self.bottom + 1) * self.columns);
+        self.cells[start..end].fill(Cell::blank(self.attribute));
+        self.row = self.top;
+        self.column = 0;
+    }
+
+    /// Move the cursor to `row` and `column` (counting from 0), keeping
+    /// either where it is if None
+    pub fn locate(&mut self, row: Option<usize>, column: Option<usize>) -> Result<()> {
+        let row = row.unwrap_or(self.row);
+        let column = column.unwrap_or(self.column.min(self.columns - 1));
+        if row >= self.rows || column >= self.columns {
+            return Err(illegal_function_call());
+        }
+        self.row = row;
+        self.column = column;
+        Ok(())
+    }
+
+    /// Show or hide the cursor and set its scan lines, keeping any not
+    /// given; scan lines go from 0 to 31
+    pub fn set_cursor(&mut self, visible: Option<bool>, start: Option<u8>, stop: Option<u8>) -> Result<()> {
+        if start.or(stop).is_some_and(|line| line > 31) {
+            return Err(illegal_function_call());
+        }
+        if let Some(visible) = visible {
+            self.cursor_visible = visible;
+        }
+        let start = start.unwrap_or(self.cursor_shape.0);
+        self.cursor_shape = (start, stop.unwrap_or(start.max(self.cursor_shape.1)));
+        Ok(())
+    }
+
+    /// Set the text window to rows `top` to `bottom` (counting from 0), or
+    /// back to every row but the last for None, and home the cursor in it
+    pub fn set_window(&mut self, rows: Option<(usize, usize)>) -> Result<()> {
+        let (top, bottom) = rows.unwrap_or((0, self.rows.saturating_sub(2)));
+        if top > bottom || bottom + 1 >= self.rows {
+            return Err(illegal_function_call());
+        }
+        self.top = top;
+        self.bottom = bottom;
+        self.row = top;
+        self.column = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(screen: &mut TextScreen, text: &str) {
+        for byte in text.bytes() {
+            screen.put(byte);
+        }
+    }
+
+    #[test]
+    fn test_wrap_and_scroll() {
+        let mut screen = TextScreen::new(4, 4);
+        write(&mut screen, "ABCDEF");
+        assert_eq!((screen.row_text(0), screen.row_text(1)), ("ABCD".to_string(), "EF".to_string()));
+        assert_eq!(screen.cursor(), (1, 2));
+
+        // Rows 0 to 2 make the window; row 3 stays put
+        screen.locate(Some(3), Some(0)).unwrap();
+        write(&mut screen, "KEYS");
+        screen.locate(Some(2), Some(0)).unwrap();
+        write(&mut screen, "GH");
+        screen.newline();
+        assert_eq!(screen.row_text(0), "EF");
+        assert_eq!(screen.row_text(1), "GH");
+        assert_eq!(screen.row_text(3), "KEYS");
+        assert_eq!(screen.cursor(), (2, 0));
+    }
+
+    #[test]
+    fn test_text_window() {
+        let mut screen = TextScreen::new(10, 25);
+        write(&mut screen, "TITLE");
+        screen.set_window(Some((4, 9))).unwrap();
+        assert_eq!(screen.cursor(), (4, 0));
+        screen.set_attribute(0x1E);
+        write(&mut screen, "X");
+        screen.clear();
+        assert_eq!(screen.row_text(0), "TITLE");
+        assert_eq!(screen.cell(4, 0), Some(Cell { ch: b' ', attribute: 0x1E }));
+        assert!(screen.set_window(Some((4, 24))).is_err());
+        assert!(screen.locate(Some(25), None).is_err());
+        assert!(screen.set_cursor(None, Some(32), None).is_err());
+    }
+}
@@ -0,0 +1,1156 @@
+//! Tokenized program file codec for GW-BASIC
+//!
+//! GW-BASIC saves programs in a compact binary form unless `SAVE "file",A`
+//! is used. The file starts with a 0xFF header byte followed by one record
+//! per line:
+//!
+//! - a 2-byte link to the next line (0 terminates the program)
+//! - the 2-byte line number
+//! - the crunched line text, terminated by a 0 byte
+//!
+//! Inside a line, reserved words are stored as one-byte tokens (0x81-0xF4)
+//! or as two-byte tokens prefixed by 0xFD, 0xFE or 0xFF. Numeric constants
+//! are stored in binary behind a type marker: 0x0B octal, 0x0C hex,
+//! 0x0E line number, 0x0F one-byte integer, 0x11-0x1A the digits 0-9,
+//! 0x1C integer, 0x1D single and 0x1F double (Microsoft Binary Format).
+//!
+//! The crunching rules follow the CRUNCH routine in GWMAIN.ASM and the
+//! reserved word tables in IBMRES.ASM.
+//!
+//! A program saved with `SAVE "file",P` has a 0xFE header instead, and the
+//! rest of the image is scrambled with two rotating keys of 13 and 11 bytes.
+
+use crate::error::{Error, Result};
+use crate::lexer::{Lexer, Token};
+use crate::value::{format_double, format_single};
+
+/// Header byte of an ordinary tokenized program
+pub const TOKENIZED_HEADER: u8 = 0xFF;
+
+/// Header byte of a program saved with `SAVE "file",P`
+pub const PROTECTED_HEADER: u8 = 0xFE;
+
+/// DOS end-of-file marker written after the program text
+const EOF_MARKER: u8 = 0x1A;
+
+/// Address the line links are computed from. GW-BASIC relinks a program
+/// when it is loaded, so the links only have to be self-consistent.
+const LINK_BASE: u16 = 0x126E;
+
+/// Keys of the protection cipher; the pattern repeats every 13 * 11 bytes
+const PROTECT_KEY_13: [u8; 13] = [
+    0xA9, 0x84, 0x8D, 0xCD, 0x75, 0x83, 0x43, 0x63, 0x24, 0x83, 0x19, 0xF7, 0x9A,
+];
+const PROTECT_KEY_11: [u8; 11] = [
+    0x1E, 0x1D, 0xC4, 0x77, 0x26, 0x97, 0xE0, 0x74, 0x59, 0x88, 0x7C,
+];
+
+/// Highest line number GW-BASIC accepts
+const MAX_LINE_NUMBER: u32 = 65529;
+
+// Numeric constant markers
+const OCTAL_CONST: u8 = 0x0B;
+const HEX_CONST: u8 = 0x0C;
+const LINE_POINTER: u8 = 0x0D;
+const LINE_NUMBER: u8 = 0x0E;
+const BYTE_CONST: u8 = 0x0F;
+const DIGIT_ZERO: u8 = 0x11;
+const DIGIT_NINE: u8 = 0x1A;
+const INT_CONST: u8 = 0x1C;
+const SINGLE_CONST: u8 = 0x1D;
+const DOUBLE_CONST: u8 = 0x1F;
+
+// Tokens with special crunching rules
+const TOKEN_DATA: u8 = 0x84;
+const TOKEN_REM: u8 = 0x8F;
+const TOKEN_PRINT: u8 = 0x91;
+const TOKEN_ELSE: u8 = 0xA1;
+const TOKEN_WHILE: u8 = 0xB1;
+const TOKEN_QUOTE: u8 = 0xD9;
+const TOKEN_PLUS: u8 = 0xE9;
+
+/// Reserved words and their token bytes, in the order of the IBMRES tables
+const KEYWORDS: &[(&str, &[u8])] = &[
+    ("END", &[0x81]),
+    ("FOR", &[0x82]),
+    ("NEXT", &[0x83]),
+    ("DATA", &[0x84]),
+    ("INPUT", &[0x85]),
+    ("DIM", &[0x86]),
+    ("READ", &[0x87]),
+    ("LET", &[0x88]),
+    ("GOTO", &[0x89]),
+    ("RUN", &[0x8A]),
+    ("IF", &[0x8B]),
+    ("RESTORE", &[0x8C]),
+    ("GOSUB", &[0x8D]),
+    ("RETURN", &[0x8E]),
+    ("REM", &[0x8F]),
+    ("STOP", &[0x90]),
+    ("PRINT", &[0x91]),
+    ("CLEAR", &[0x92]),
+    ("LIST", &[0x93]),
+    ("NEW", &[0x94]),
+    ("ON", &[0x95]),
+    ("WAIT", &[0x96]),
+    ("DEF", &[0x97]),
+    ("POKE", &[0x98]),
+    ("CONT", &[0x99]),
+    ("OUT", &[0x9C]),
+    ("LPRINT", &[0x9D]),
+    ("LLIST", &[0x9E]),
+    ("WIDTH", &[0xA0]),
+    ("ELSE", &[0xA1]),
+    ("TRON", &[0xA2]),
+    ("TROFF", &[0xA3]),
+    ("SWAP", &[0xA4]),
+    ("ERASE", &[0xA5]),
+    ("EDIT", &[0xA6]),
+    ("ERROR", &[0xA7]),
+    ("RESUME", &[0xA8]),
+    ("DELETE", &[0xA9]),
+    ("AUTO", &[0xAA]),
+    ("RENUM", &[0xAB]),
+    ("DEFSTR", &[0xAC]),
+    ("DEFINT", &[0xAD]),
+    ("DEFSNG", &[0xAE]),
+    ("DEFDBL", &[0xAF]),
+    ("LINE", &[0xB0]),
+    ("WHILE", &[0xB1]),
+    ("WEND", &[0xB2]),
+    ("CALL", &[0xB3]),
+    ("WRITE", &[0xB7]),
+    ("OPTION", &[0xB8]),
+    ("RANDOMIZE", &[0xB9]),
+    ("OPEN", &[0xBA]),
+    ("CLOSE", &[0xBB]),
+    ("LOAD", &[0xBC]),
+    ("MERGE", &[0xBD]),
+    ("SAVE", &[0xBE]),
+    ("COLOR", &[0xBF]),
+    ("CLS", &[0xC0]),
+    ("MOTOR", &[0xC1]),
+    ("BSAVE", &[0xC2]),
+    ("BLOAD", &[0xC3]),
+    ("SOUND", &[0xC4]),
+    ("BEEP", &[0xC5]),
+    ("PSET", &[0xC6]),
+    ("PRESET", &[0xC7]),
+    ("SCREEN", &[0xC8]),
+    ("KEY", &[0xC9]),
+    ("LOCATE", &[0xCA]),
+    ("TO", &[0xCC]),
+    ("THEN", &[0xCD]),
+    ("TAB(", &[0xCE]),
+    ("STEP", &[0xCF]),
+    ("USR", &[0xD0]),
+    ("FN", &[0xD1]),
+    ("SPC(", &[0xD2]),
+    ("NOT", &[0xD3]),
+    ("ERL", &[0xD4]),
+    ("ERR", &[0xD5]),
+    ("STRING$", &[0xD6]),
+    ("USING", &[0xD7]),
+    ("INSTR", &[0xD8]),
+    ("'", &[0xD9]),
+    ("VARPTR", &[0xDA]),
+    ("CSRLIN", &[0xDB]),
+    ("POINT", &[0xDC]),
+    ("OFF", &[0xDD]),
+    ("INKEY$", &[0xDE]),
+    (">", &[0xE6]),
+    ("=", &[0xE7]),
+    ("<", &[0xE8]),
+    ("+", &[0xE9]),
+    ("-", &[0xEA]),
+    ("*", &[0xEB]),
+    ("/", &[0xEC]),
+    ("^", &[0xED]),
+    ("AND", &[0xEE]),
+    ("OR", &[0xEF]),
+    ("XOR", &[0xF0]),
+    ("EQV", &[0xF1]),
+    ("IMP", &[0xF2]),
+    ("MOD", &[0xF3]),
+    ("\\", &[0xF4]),
+    // Extended functions
+    ("CVI", &[0xFD, 0x81]),
+    ("CVS", &[0xFD, 0x82]),
+    ("CVD", &[0xFD, 0x83]),
+    ("MKI$", &[0xFD, 0x84]),
+    ("MKS$", &[0xFD, 0x85]),
+    ("MKD$", &[0xFD, 0x86]),
+    ("EXTERR", &[0xFD, 0x8B]),
+    // Extended statements
+    ("FILES", &[0xFE, 0x81]),
+    ("FIELD", &[0xFE, 0x82]),
+    ("SYSTEM", &[0xFE, 0x83]),
+    ("NAME", &[0xFE, 0x84]),
+    ("LSET", &[0xFE, 0x85]),
+    ("RSET", &[0xFE, 0x86]),
+    ("KILL", &[0xFE, 0x87]),
+    ("PUT", &[0xFE, 0x88]),
+    ("GET", &[0xFE, 0x89]),
+    ("RESET", &[0xFE, 0x8A]),
+    ("COMMON", &[0xFE, 0x8B]),
+    ("CHAIN", &[0xFE, 0x8C]),
+    ("DATE$", &[0xFE, 0x8D]),
+    ("TIME$", &[0xFE, 0x8E]),
+    ("PAINT", &[0xFE, 0x8F]),
+    ("COM", &[0xFE, 0x90]),
+    ("CIRCLE", &[0xFE, 0x91]),
+    ("DRAW", &[0xFE, 0x92]),
+    ("PLAY", &[0xFE, 0x93]),
+    ("TIMER", &[0xFE, 0x94]),
+    ("ERDEV", &[0xFE, 0x95]),
+    ("IOCTL", &[0xFE, 0x96]),
+    ("CHDIR", &[0xFE, 0x97]),
+    ("MKDIR", &[0xFE, 0x98]),
+    ("RMDIR", &[0xFE, 0x99]),
+    ("SHELL", &[0xFE, 0x9A]),
+    ("ENVIRON", &[0xFE, 0x9B]),
+    ("VIEW", &[0xFE, 0x9C]),
+    ("WINDOW", &[0xFE, 0x9D]),
+    ("PMAP", &[0xFE, 0x9E]),
+    ("PALETTE", &[0xFE, 0x9F]),
+    ("LCOPY", &[0xFE, 0xA0]),
+    ("CALLS", &[0xFE, 0xA1]),
+    ("PCOPY", &[0xFE, 0xA5]),
+    ("LOCK", &[0xFE, 0xA7]),
+    ("UNLOCK", &[0xFE, 0xA8]),
+    // Functions
+    ("LEFT$", &[0xFF, 0x81]),
+    ("RIGHT$", &[0xFF, 0x82]),
+    ("MID$", &[0xFF, 0x83]),
+    ("SGN", &[0xFF, 0x84]),
+    ("INT", &[0xFF, 0x85]),
+    ("ABS", &[0xFF, 0x86]),
+    ("SQR", &[0xFF, 0x87]),
+    ("RND", &[0xFF, 0x88]),
+    ("SIN", &[0xFF, 0x89]),
+    ("LOG", &[0xFF, 0x8A]),
+    ("EXP", &[0xFF, 0x8B]),
+    ("COS", &[0xFF, 0x8C]),
+    ("TAN", &[0xFF, 0x8D]),
+    ("ATN", &[0xFF, 0x8E]),
+    ("FRE", &[0xFF, 0x8F]),
+    ("INP", &[0xFF, 0x90]),
+    ("POS", &[0xFF, 0x91]),
+    ("LEN", &[0xFF, 0x92]),
+    ("STR$", &[0xFF, 0x93]),
+    ("VAL", &[0xFF, 0x94]),
+    ("ASC", &[0xFF, 0x95]),
+    ("CHR$", &[0xFF, 0x96]),
+    ("PEEK", &[0xFF, 0x97]),
+    ("SPACE$", &[0xFF, 0x98]),
+    ("OCT$", &[0xFF, 0x99]),
+    ("HEX$", &[0xFF, 0x9A]),
+    ("LPOS", &[0xFF, 0x9B]),
+    ("CINT", &[0xFF, 0x9C]),
+    ("CSNG", &[0xFF, 0x9D]),
+    ("CDBL", &[0xFF, 0x9E]),
+    ("FIX", &[0xFF, 0x9F]),
+    ("PEN", &[0xFF, 0xA0]),
+    ("STICK", &[0xFF, 0xA1]),
+    ("STRIG", &[0xFF, 0xA2]),
+    ("EOF", &[0xFF, 0xA3]),
+    ("LOC", &[0xFF, 0xA4]),
+    ("LOF", &[0xFF, 0xA5]),
+];
+
+/// Reserved words that may be followed by line numbers (LINRES in GWMAIN.ASM)
+const LINE_NUMBER_KEYWORDS: &[&str] = &[
+    "RESTORE", "AUTO", "RENUM", "DELETE", "EDIT", "RESUME", "ERL", "ELSE",
+    "RUN", "LIST", "LLIST", "GOTO", "RETURN", "THEN", "GOSUB",
+];
+
+/// On-disk format of a BASIC program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramFormat {
+    /// Plain text, as written by `SAVE "file",A`
+    Ascii,
+    /// Tokenized image with a 0xFF header
+    Tokenized,
+    /// Encrypted tokenized image with a 0xFE header
+    Protected,
+}
+
+/// Work out which format a program file is stored in
+pub fn detect_format(bytes: &[u8]) -> ProgramFormat {
+    match bytes.first() {
+        Some(&TOKENIZED_HEADER) => ProgramFormat::Tokenized,
+        Some(&PROTECTED_HEADER) => ProgramFormat::Protected,
+        _ => ProgramFormat::Ascii,
+    }
+}
+
+/// Decode a program file in any format into its source listing
+pub fn decode_program(bytes: &[u8]) -> Result<String> {
+    match detect_format(bytes) {
+        ProgramFormat::Tokenized => detokenize(bytes),
+        ProgramFormat::Protected => detokenize(&unprotect(bytes)?),
+        ProgramFormat::Ascii => {
+            let end = bytes.iter().position(|&b| b == EOF_MARKER).unwrap_or(bytes.len());
+            let text = &bytes[..end];
+            Ok(match std::str::from_utf8(text) {
+                Ok(s) => s.to_string(),
+                Err(_) => text.iter().map(|&b| b as char).collect(),
+            })
+        }
+    }
+}
+
+/// Decode a program file straight into the lexer's token stream
+pub fn decode_tokens(bytes: &[u8]) -> Result<Vec<Token>> {
+    let source = decode_program(bytes)?;
+    Lexer::new(&source).tokenize()
+}
+
+/// Recover the tokenized image (with a 0xFF header) from a protected file
+pub fn unprotect(bytes: &[u8]) -> Result<Vec<u8>> {
+    if bytes.first() != Some(&PROTECTED_HEADER) {
+        return Err(Error::IoError("Not a protected program".to_string()));
+    }
+    let body = &bytes[1..];
+    let body = body.strip_suffix(&[EOF_MARKER]).unwrap_or(body);
+
+    let mut image = vec![TOKENIZED_HEADER];
+    image.extend(body.iter().enumerate().map(|(i, &b)| {
+        let (i13, i11) = (i % 13, i % 11);
+        (b.wrapping_sub(11 - i11 as u8) ^ PROTECT_KEY_13[i13] ^ PROTECT_KEY_11[i11])
+            .wrapping_add(13 - i13 as u8)
+    }));
+    Ok(image)
+}
+
+/// Scramble a tokenized image (with its 0xFF header) into protected form
+pub fn protect(image: &[u8]) -> Result<Vec<u8>> {
+    if image.first() != Some(&TOKENIZED_HEADER) {
+        return Err(Error::IoError("Not a tokenized program".to_string()));
+    }
+    let body = &image[1..];
+    let body = body.strip_suffix(&[EOF_MARKER]).unwrap_or(body);
+
+    let mut bytes = vec![PROTECTED_HEADER];
+    bytes.extend(body.iter().enumerate().map(|(i, &b)| {
+        let (i13, i11) = (i % 13, i % 11);
+        (b.wrapping_sub(13 - i13 as u8) ^ PROTECT_KEY_11[i11] ^ PROTECT_KEY_13[i13])
+            .wrapping_add(11 - i11 as u8)
+    }));
+    bytes.push(EOF_MARKER);
+    Ok(bytes)
+}
+
+/// Turn a tokenized program image (including its 0xFF header) into a listing
+pub fn detokenize(bytes: &[u8]) -> Result<String> {
+    if bytes.first() != Some(&TOKENIZED_HEADER) {
+        return Err(Error::IoError("Not a tokenized program".to_string()));
+    }
+
+    let mut listing = String::new();
+    let mut pos = 1;
+    while pos + 4 <= bytes.len() {
+        let link = u16::from_le_bytes([bytes[pos], bytes[pos + 1]]);
+        if link == 0 {
+            break;
+        }
+        let number = u16::from_le_bytes([bytes[pos + 2], bytes[pos + 3]]);
+        pos += 4;
+
+        let length = line_length(&bytes[pos..])?;
+        let text = list_line(&bytes[pos..pos + length])?;
+        pos += length + 1;
+
+        listing.push_str(&number.to_string());
+        listing.push(' ');
+        listing.push_str(&text);
+        listing.push('\n');
+    }
+
+    Ok(listing)
+}
+
+/// Find the 0 byte ending a crunched line, skipping over binary constants
+fn line_length(bytes: &[u8]) -> Result<usize> {
+    let mut pos = 0;
+    let mut raw = false;
+    let mut quoted = false;
+    while pos < bytes.len() {
+        let b = bytes[pos];
+        if b == 0 {
+            return Ok(pos);
+        }
+        if quoted {
+            quoted = b != b'"';
+            pos += 1;
+            continue;
+        }
+        if b == b'"' {
+            quoted = true;
+            pos += 1;
+            continue;
+        }
+        if raw {
+            pos += 1;
+            continue;
+        }
+        pos += match b {
+            TOKEN_REM | TOKEN_QUOTE => {
+                raw = true;
+                1
+            }
+            OCTAL_CONST | HEX_CONST | LINE_POINTER | LINE_NUMBER | INT_CONST => 3,
+            BYTE_CONST => 2,
+            SINGLE_CONST => 5,
+            DOUBLE_CONST => 9,
+            0xFD..=0xFF => 2,
+            _ => 1,
+        };
+    }
+    Err(Error::IoError("Unterminated program line".to_string()))
+}
+
+/// Expand one crunched line (without its link and line number) into text
+pub fn list_line(bytes: &[u8]) -> Result<String> {
+    let mut out = String::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let b = bytes[pos];
+        match b {
+            b'"' => {
+                let end = bytes[pos + 1..]
+                    .iter()
+                    .position(|&c| c == b'"')
+                    .map(|i| pos + 2 + i)
+                    .unwrap_or(bytes.len());
+                push_raw(&mut out, &bytes[pos..end]);
+                pos = end;
+            }
+            b':' if bytes.get(pos + 1) == Some(&TOKEN_ELSE) => {
+                // Hidden colon in front of ELSE
+                pos += 1;
+            }
+            b':' if bytes.get(pos + 1) == Some(&TOKEN_REM)
+                && bytes.get(pos + 2) == Some(&TOKEN_QUOTE) =>
+            {
+                out.push('\'');
+                push_raw(&mut out, &bytes[pos + 3..]);
+                pos = bytes.len();
+            }
+            TOKEN_REM | TOKEN_QUOTE => {
+                out.push_str(keyword_for(&bytes[pos..pos + 1])?);
+                push_raw(&mut out, &bytes[pos + 1..]);
+                pos = bytes.len();
+            }
+            TOKEN_DATA => {
+                out.push_str("DATA");
+                pos += 1;
+                let mut quoted = false;
+                while pos < bytes.len() && (quoted || bytes[pos] != b':') {
+                    if bytes[pos] == b'"' {
+                        quoted = !quoted;
+                    }
+                    out.push(bytes[pos] as char);
+                    pos += 1;
+                }
+            }
+            TOKEN_WHILE => {
+                out.push_str("WHILE");
+                pos += 1;
+                if bytes.get(pos) == Some(&TOKEN_PLUS) {
+                    pos += 1;
+                }
+            }
+            OCTAL_CONST => {
+                out.push_str(&format!("&O{:o}", read_u16(bytes, pos + 1)?));
+                pos += 3;
+            }
+            HEX_CONST => {
+                out.push_str(&format!("&H{:X}", read_u16(bytes, pos + 1)?));
+                pos += 3;
+            }
+            LINE_NUMBER => {
+                out.push_str(&read_u16(bytes, pos + 1)?.to_string());
+                pos += 3;
+            }
+            LINE_POINTER => {
+                return Err(Error::IoError(
+                    "Unresolved line pointer in program file".to_string(),
+                ));
+            }
+            BYTE_CONST => {
+                let value = *bytes.get(pos + 1).ok_or_else(truncated)?;
+                out.push_str(&value.to_string());
+                pos += 2;
+            }
+            DIGIT_ZERO..=DIGIT_NINE => {
+                out.push_str(&(b - DIGIT_ZERO).to_string());
+                pos += 1;
+            }
+            INT_CONST => {
+                out.push_str(&(read_u16(bytes, pos + 1)? as i16).to_string());
+                pos += 3;
+            }
+            SINGLE_CONST => {
+                let raw = bytes.get(pos + 1..pos + 5).ok_or_else(truncated)?;
+                let value = mbf_to_f32([raw[0], raw[1], raw[2], raw[3]]);
+                let text = format_single(value);
+                out.push_str(&text);
+                if !text.contains(['.', 'E']) {
+                    out.push('!');
+                }
+                pos += 5;
+            }
+            DOUBLE_CONST => {
+                let raw = bytes.get(pos + 1..pos + 9).ok_or_else(truncated)?;
+                let mut mbf = [0u8; 8];
+                mbf.copy_from_slice(raw);
+                let text = format_double(mbf_to_f64(mbf));
+                out.push_str(&text);
+                if !text.contains('D') {
+                    out.push('#');
+                }
+                pos += 9;
+            }
+            0xFD..=0xFF => {
+                let token = bytes.get(pos..pos + 2).ok_or_else(truncated)?;
+                out.push_str(keyword_for(token)?);
+                pos += 2;
+            }
+            0x81..=0xF4 => {
+                out.push_str(keyword_for(&bytes[pos..pos + 1])?);
+                pos += 1;
+            }
+            _ => {
+                out.push(b as char);
+                pos += 1;
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn truncated() -> Error {
+    Error::IoError("Truncated constant in program file".to_string())
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Result<u16> {
+    match bytes.get(pos..pos + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(truncated()),
+    }
+}
+
+fn push_raw(out: &mut String, bytes: &[u8]) {
+    out.extend(bytes.iter().map(|&b| b as char));
+}
+
+fn keyword_for(token: &[u8]) -> Result<&'static str> {
+    KEYWORDS
+        .iter()
+        .find(|(_, bytes)| *bytes == token)
+        .map(|(word, _)| *word)
+        .ok_or_else(|| Error::IoError(format!("Unknown token {:02X?} in program file", token)))
+}
+
+/// Build a tokenized program image from a source listing
+pub fn tokenize(source: &str) -> Result<Vec<u8>> {
+    let mut lines: Vec<(u16, Vec<u8>)> = Vec::new();
+
+    for text in source.lines() {
+        let text = text.trim_start();
+        if text.is_empty() {
+            continue;
+        }
+        let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return Err(Error::SyntaxError(format!("Line number expected: {}", text)));
+        }
+        let number: u32 = text[..digits]
+            .parse()
+            .map_err(|_| Error::LineNumberError(format!("Invalid line number: {}", &text[..digits])))?;
+        if number > MAX_LINE_NUMBER {
+            return Err(Error::LineNumberError(format!("Line number {} out of range", number)));
+        }
+        let body = &text[digits..];
+        let body = body.strip_prefix(' ').unwrap_or(body);
+        let crunched = crunch_line(body)?;
+
+        lines.retain(|(n, _)| *n as u32 != number);
+        lines.push((number as u16, crunched));
+    }
+    lines.sort_by_key(|(n, _)| *n);
+
+    let mut image = vec![TOKENIZED_HEADER];
+    let mut address = LINK_BASE;
+    for (number, crunched) in &lines {
+        address = address.wrapping_add(crunched.len() as u16 + 5);
+        image.extend_from_slice(&address.to_le_bytes());
+        image.extend_from_slice(&number.to_le_bytes());
+        image.extend_from_slice(crunched);
+        image.push(0);
+    }
+    image.extend_from_slice(&[0, 0, EOF_MARKER]);
+    Ok(image)
+}
+
+/// What a number met during crunching turns into (DONUM in GWMAIN.ASM)
+#[derive(Clone, Copy, PartialEq)]
+enum NumberMode {
+    Constant,
+    LineNumber,
+    Variable,
+}
+
+/// Crunch the text of one line (without its line number) into tokens
+pub fn crunch_line(text: &str) -> Result<Vec<u8>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut pos = 0;
+    let mut mode = NumberMode::Constant;
+    let mut in_data = false;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+
+        if ch == '"' {
+            let end = chars[pos + 1..]
+                .iter()
+                .position(|&c| c == '"')
+                .map(|i| pos + 2 + i)
+                .unwrap_or(chars.len());
+            push_chars(&mut out, &chars[pos..end]);
+            pos = end;
+            continue;
+        }
+
+        if ch == ' ' || in_data {
+            out.push(char_byte(ch));
+            pos += 1;
+            if ch == ':' {
+                in_data = false;
+                mode = NumberMode::Constant;
+            }
+            continue;
+        }
+
+        if ch == '?' {
+            out.push(TOKEN_PRINT);
+            mode = NumberMode::Constant;
+            pos += 1;
+            continue;
+        }
+
+        if ch.is_ascii_alphabetic() {
+            if let Some((word, len)) = match_go(&chars[pos..]).or_else(|| match_keyword(&chars[pos..])) {
+                let token = KEYWORDS.iter().find(|(w, _)| *w == word).map(|(_, t)| *t).unwrap_or(&[]);
+                pos += len;
+
+                if token[0] == TOKEN_ELSE {
+                    out.push(b':');
+                }
+                out.extend_from_slice(token);
+
+                mode = if matches!(token[0], 0xFD..=0xFF) {
+                    NumberMode::Constant
+                } else if LINE_NUMBER_KEYWORDS.contains(&word) {
+                    NumberMode::LineNumber
+                } else {
+                    NumberMode::Constant
+                };
+
+                match token[0] {
+                    TOKEN_WHILE => out.push(TOKEN_PLUS),
+                    TOKEN_DATA => in_data = true,
+                    TOKEN_REM => {
+                        push_chars(&mut out, &chars[pos..]);
+                        pos = chars.len();
+                    }
+                    _ => {}
+                }
+            } else {
+                // Variable name: letters, digits and dots, stored in upper case
+                while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '.') {
+                    out.push(chars[pos].to_ascii_uppercase() as u8);
+                    pos += 1;
+                }
+                mode = NumberMode::Variable;
+            }
+            continue;
+        }
+
+        if ch.is_ascii_digit() || ch == '.' {
+            match mode {
+                NumberMode::Variable => {
+                    out.push(ch as u8);
+                    pos += 1;
+                }
+                NumberMode::LineNumber if ch == '.' => {
+                    out.push(b'.');
+                    pos += 1;
+                }
+                NumberMode::LineNumber => {
+                    let digits: String = chars[pos..].iter().take_while(|c| c.is_ascii_digit()).collect();
+                    let number: u32 = digits.parse().unwrap_or(u32::MAX);
+                    if number > MAX_LINE_NUMBER {
+                        return Err(Error::LineNumberError(format!("Line number {} out of range", digits)));
+                    }
+                    out.push(LINE_NUMBER);
+                    out.extend_from_slice(&(number as u16).to_le_bytes());
+                    pos += digits.len();
+                }
+                NumberMode::Constant => {
+                    pos += crunch_constant(&chars[pos..], &mut out)?;
+                }
+            }
+            continue;
+        }
+
+        if ch == '&' {
+            pos += crunch_constant(&chars[pos..], &mut out)?;
+            continue;
+        }
+
+        if ch == '\'' {
+            out.extend_from_slice(&[b':', TOKEN_REM, TOKEN_QUOTE]);
+            push_chars(&mut out, &chars[pos + 1..]);
+            break;
+        }
+
+        if let Some((_, token)) = KEYWORDS.iter().find(|(w, _)| w.len() == 1 && w.starts_with(ch)) {
+            out.extend_from_slice(token);
+        } else if ch == ':' {
+            out.push(b':');
+            mode = NumberMode::Constant;
+            pos += 1;
+            continue;
+        } else if (ch as u32) < 32 && ch != '\t' && ch != '\n' {
+            out.push(b' ');
+        } else {
+            out.push(char_byte(ch));
+        }
+        if mode == NumberMode::Variable {
+            mode = NumberMode::Constant;
+        }
+        pos += 1;
+    }
+
+    Ok(out)
+}
+
+fn char_byte(ch: char) -> u8 {
+    if (ch as u32) < 256 {
+        ch as u8
+    } else {
+        b'?'
+    }
+}
+
+fn push_chars(out: &mut Vec<u8>, chars: &[char]) {
+    out.extend(chars.iter().map(|&c| char_byte(c)));
+}
+
+/// Match "GO TO" and "GO SUB" written with spaces
+fn match_go(chars: &[char]) -> Option<(&'static str, usize)> {
+    let upper: String = chars.iter().take(3).map(|c| c.to_ascii_uppercase()).collect();
+    if upper != "GO " {
+        return None;
+    }
+    let mut pos = 3;
+    while pos < chars.len() && chars[pos] == ' ' {
+        pos += 1;
+    }
+    let rest: String = chars[pos..].iter().take(3).map(|c| c.to_ascii_uppercase()).collect();
+    if rest.starts_with("TO") {
+        Some(("GOTO", pos + 2))
+    } else if rest == "SUB" {
+        Some(("GOSUB", pos + 3))
+    } else {
+        None
+    }
+}
+
+/// Find the reserved word at the start of `chars`. As in CRUNCH, a word
+/// followed directly by a letter, digit or dot is part of a variable name,
+/// except for FN, USR and words ending in '('. The lexer crunches text
+/// source by the same rule.
+pub(crate) fn match_keyword(chars: &[char]) -> Option<(&'static str, usize)> {
+    KEYWORDS
+        .iter()
+        .map(|(word, _)| *word)
+        .filter(|word| word.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .filter(|word| {
+            word.len() <= chars.len()
+                && word
+                    .chars()
+                    .zip(chars.iter())
+                    .all(|(w, c)| w == c.to_ascii_uppercase())
+        })
+        .filter(|word| {
+            if word.ends_with('(') || *word == "FN" || *word == "USR" {
+                return true;
+            }
+            match chars.get(word.len()) {
+                Some(c) => !(c.is_ascii_alphanumeric() || *c == '.'),
+                None => true,
+            }
+        })
+        .max_by_key(|word| word.len())
+        .map(|word| (word, word.len()))
+}
+
+/// A numeric constant, typed as GW-BASIC types it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Constant {
+    Integer(u16),
+    Single(f32),
+    Double(f64),
+    Octal(u16),
+    Hex(u16),
+}
+
+/// Read the numeric constant at the start of `chars`, returning it and the
+/// number of characters it takes up. Both CRUNCH and the lexer type
+/// constants this way: a `%`, `!` or `#` suffix decides, then a D exponent
+/// or more than seven digits make a double, a point, an E exponent or a
+/// value beyond 32767 a single, and anything else is an integer.
+pub(crate) fn read_constant(chars: &[char]) -> Result<(Constant, usize)> {
+    if chars.first() == Some(&'&') {
+        return read_radix_constant(chars);
+    }
+
+    let mut pos = 0;
+    let mut text = String::new();
+    let mut is_float = false;
+    let mut is_double = false;
+
+    while pos < chars.len() && (chars[pos].is_ascii_digit() || (chars[pos] == '.' && !is_float)) {
+        if chars[pos] == '.' {
+            is_float = true;
+        }
+        text.push(chars[pos]);
+        pos += 1;
+    }
+    let digit_count = text.chars().filter(|c| c.is_ascii_digit()).count();
+
+    // Exponent: E for single, D for double, optionally signed
+    if let Some(&e) = chars.get(pos) {
+        let e = e.to_ascii_uppercase();
+        if e == 'E' || e == 'D' {
+            let mut end = pos + 1;
+            if matches!(chars.get(end), Some('+') | Some('-')) {
+                end += 1;
+            }
+            if chars.get(end).is_some_and(|c| c.is_ascii_digit()) {
+                text.push('E');
+                text.extend(&chars[pos + 1..end]);
+                while end < chars.len() && chars[end].is_ascii_digit() {
+                    text.push(chars[end]);
+                    end += 1;
+                }
+                is_float = true;
+                is_double = e == 'D';
+                pos = end;
+            }
+        }
+    }
+
+    let value: f64 = text
+        .parse()
+        .map_err(|_| Error::SyntaxError(format!("Invalid number: {}", text)))?;
+
+    let constant = match chars.get(pos) {
+        Some('%') => {
+            pos += 1;
+            if is_float {
+                return Err(Error::SyntaxError(format!("Invalid integer: {}%", text)));
+            }
+            if value > 32767.0 {
+                return Err(Error::RuntimeError("Overflow".to_string()));
+            }
+            Constant::Integer(value as u16)
+        }
+        Some('!') => {
+            pos += 1;
+            Constant::Single(value as f32)
+        }
+        Some('#') => {
+            pos += 1;
+            Constant::Double(value)
+        }
+        _ if is_double || digit_count > 7 => Constant::Double(value),
+        _ if is_float || value > 32767.0 => Constant::Single(value as f32),
+        _ => Constant::Integer(value as u16),
+    };
+    Ok((constant, pos))
+}
+
+/// Read an &H hex or &O / & octal constant. These are 16-bit values; a
+/// lone `&` is 0.
+fn read_radix_constant(chars: &[char]) -> Result<(Constant, usize)> {
+    let (radix, mut pos) = match chars.get(1).map(|c| c.to_ascii_uppercase()) {
+        Some('H') => (16, 2),
+        Some('O') => (8, 2),
+        _ => (8, 1),
+    };
+    let start = pos;
+    while pos < chars.len() && chars[pos].is_digit(radix) {
+        pos += 1;
+    }
+    let digits: String = chars[start..pos].iter().collect();
+    let value = if digits.is_empty() {
+        0
+    } else {
+        u16::from_str_radix(&digits, radix)
+            .map_err(|_| Error::RuntimeError("Overflow".to_string()))?
+    };
+    let constant = if radix == 16 { Constant::Hex(value) } else { Constant::Octal(value) };
+    Ok((constant, pos))
+}
+
+/// Crunch the numeric constant at the start of `chars`, returning the
+/// number of characters consumed
+fn crunch_constant(chars: &[char], out: &mut Vec<u8>) -> Result<usize> {
+    let (constant, len) = read_constant(chars)?;
+    match constant {
+        Constant::Integer(value) => push_integer(value, out),
+        Constant::Single(value) => push_single(value, out),
+        Constant::Double(value) => push_double(value, out),
+        Constant::Octal(value) => {
+            out.push(OCTAL_CONST);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        Constant::Hex(value) => {
+            out.push(HEX_CONST);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    Ok(len)
+}
+
+fn push_integer(value: u16, out: &mut Vec<u8>) {
+    if value <= 9 {
+        out.push(DIGIT_ZERO + value as u8);
+    } else if value < 256 {
+        out.push(BYTE_CONST);
+        out.push(value as u8);
+    } else {
+        out.push(INT_CONST);
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn push_single(value: f32, out: &mut Vec<u8>) {
+    out.push(SINGLE_CONST);
+    out.extend_from_slice(&f32_to_mbf(value));
+}
+
+fn push_double(value: f64, out: &mut Vec<u8>) {
+    out.push(DOUBLE_CONST);
+    out.extend_from_slice(&f64_to_mbf(value));
+}
+
+/// Convert a Microsoft Binary Format single to an IEEE float
+pub fn mbf_to_f32(bytes: [u8; 4]) -> f32 {
+    if bytes[3] == 0 {
+        return 0.0;
+    }
+    let mantissa = 0x80_0000 | ((bytes[2] as u32 & 0x7F) << 16) | ((bytes[1] as u32) << 8) | bytes[0] as u32;
+    let value = mantissa as f64 * 2f64.powi(bytes[3] as i32 - 128 - 24);
+    let value = if bytes[2] & 0x80 != 0 { -value } else { value };
+    value as f32
+}
+
+/// Convert a Microsoft Binary Format double to an IEEE float
+pub fn mbf_to_f64(bytes: [u8; 8]) -> f64 {
+    if bytes[7] == 0 {
+        return 0.0;
+    }
+    let mut mantissa: u64 = 0x80 | (bytes[6] as u64 & 0x7F);
+    for &b in bytes[..6].iter().rev() {
+        mantissa = (mantissa << 8) | b as u64;
+    }
+    let value = mantissa as f64 * 2f64.powi(bytes[7] as i32 - 128 - 56);
+    if bytes[6] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Convert an IEEE float to a Microsoft Binary Format single
+pub fn f32_to_mbf(value: f32) -> [u8; 4] {
+    let bytes = f64_to_mbf(value as f64);
+    // Round the 56-bit mantissa down to 24 bits
+    let mut mantissa = ((bytes[6] as u32 | 0x80) << 16) | ((bytes[5] as u32) << 8) | bytes[4] as u32;
+    let mut exponent = bytes[7] as u32;
+    if exponent == 0 {
+        return [0; 4];
+    }
+    if bytes[3] & 0x80 != 0 {
+        mantissa += 1;
+        if mantissa > 0xFF_FFFF {
+            mantissa >>= 1;
+            exponent += 1;
+        }
+    }
+    let sign = bytes[6] & 0x80;
+    [
+        mantissa as u8,
+        (mantissa >> 8) as u8,
+        ((mantissa >> 16) as u8 & 0x7F) | sign,
+        exponent.min(255) as u8,
+    ]
+}
+
+/// Convert an IEEE float to a Microsoft Binary Format double
+pub fn f64_to_mbf(value: f64) -> [u8; 8] {
+    if value == 0.0 || !value.is_finite() {
+        return [0; 8];
+    }
+    let bits = value.to_bits();
+    let sign = if bits >> 63 != 0 { 0x80u8 } else { 0 };
+    let ieee_exponent = ((bits >> 52) & 0x7FF) as i32;
+    if ieee_exponent == 0 {
+        // Denormals are far below the MBF range
+        return [0; 8];
+    }
+    let exponent = ieee_exponent - 1023 + 129;
+    if exponent <= 0 {
+        return [0; 8];
+    }
+    if exponent > 255 {
+        return [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F | sign, 0xFF];
+    }
+    // 52 IEEE fraction bits become the top of the 55 MBF fraction bits
+    let fraction = (bits & 0x000F_FFFF_FFFF_FFFF) << 3;
+    let mut out = [0u8; 8];
+    for (i, byte) in out.iter_mut().take(7).enumerate() {
+        *byte = (fraction >> (8 * i)) as u8;
+    }
+    out[6] = (out[6] & 0x7F) | sign;
+    out[7] = exponent as u8;
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, TokenType};
+
+    #[test]
+    fn test_crunch_keywords_and_constants() {
+        let bytes = crunch_line("PRINT 5+300:GOTO 10").unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x91, b' ', 0x16, 0xE9, 0x1C, 0x2C, 0x01, b':', 0x89, b' ', 0x0E, 10, 0]
+        );
+    }
+
+    #[test]
+    fn test_crunch_else_quote_and_while() {
+        assert_eq!(crunch_line("ELSE").unwrap(), vec![b':', 0xA1]);
+        assert_eq!(crunch_line("'hi").unwrap(), vec![b':', 0x8F, 0xD9, b'h', b'i']);
+        assert_eq!(crunch_line("WHILE").unwrap(), vec![0xB1, 0xE9]);
+    }
+
+    #[test]
+    fn test_keyword_followed_by_letter_is_variable() {
+        assert_eq!(crunch_line("FORM").unwrap(), b"FORM".to_vec());
+        assert_eq!(crunch_line("fna(1)").unwrap()[0], 0xD1);
+        // A type suffix does not stop a reserved word: INPUT$ is INPUT, `$`
+        assert_eq!(crunch_line("INPUT$(1)").unwrap()[..2], [0x85, b'$']);
+    }
+
+    #[test]
+    fn test_list_line() {
+        let bytes = [0x91, b' ', 0x0F, 42, b';', 0x1D, 0x00, 0x00, 0x40, 0x81, 0xFF, 0x96];
+        assert_eq!(list_line(&bytes).unwrap(), "PRINT 42;1.5CHR$");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "10 PRINT \"Hello\", 1.5E-03\n20 IF A = 1 THEN 10 ELSE 30\n30 X = &HB800 ' done\n";
+        let image = tokenize(source).unwrap();
+        assert_eq!(image[0], TOKENIZED_HEADER);
+        assert_eq!(detect_format(&image), ProgramFormat::Tokenized);
+        assert_eq!(detokenize(&image).unwrap(), source);
+    }
+
+    #[test]
+    fn test_constants_round_trip() {
+        // Every type of constant lists back as it crunched, and the lexer
+        // reads the listing as it reads the source
+        let source = "10 PRINT 7, 200, 1000, 40000, 1.5, 2.5E+03, 1.5D+03, 123456789, 7%, 3!, 4#, 1.5E+10, &HFFFF, &O17, &17\n";
+        let listed = detokenize(&tokenize(source).unwrap()).unwrap();
+        assert_eq!(
+            listed,
+            "10 PRINT 7, 200, 1000, 40000!, 1.5, 2500!, 1500#, 123456789#, 7, 3!, 4#, 1.5E+10, &HFFFF, &O17, &O17\n"
+        );
+        let token_types = |text: &str| -> Vec<TokenType> {
+            Lexer::new(text).tokenize().unwrap().into_iter().map(|token| token.token_type).collect()
+        };
+        let constants: Vec<TokenType> = token_types(source)
+            .into_iter()
+            .filter(|token_type| matches!(token_type, TokenType::Integer(_) | TokenType::Single(_) | TokenType::Double(_)))
+            .collect();
+        assert_eq!(
+            constants,
+            [
+                TokenType::Integer(7),
+                TokenType::Integer(200),
+                TokenType::Integer(1000),
+                TokenType::Single(40000.0),
+                TokenType::Single(1.5),
+                TokenType::Single(2500.0),
+                TokenType::Double(1500.0),
+                TokenType::Double(123456789.0),
+                TokenType::Integer(7),
+                TokenType::Single(3.0),
+                TokenType::Double(4.0),
+                TokenType::Single(1.5E+10),
+                TokenType::Integer(-1),
+                TokenType::Integer(15),
+                TokenType::Integer(15),
+            ]
+        );
+        assert_eq!(token_types(&listed), token_types(source));
+        assert!(matches!(crunch_line("40000%"), Err(Error::RuntimeError(_))));
+    }
+
+    #[test]
+    fn test_protected_round_trip() {
+        let image = tokenize("10 PRINT \"SECRET\"\n20 GOTO 10\n").unwrap();
+        let protected = protect(&image).unwrap();
+        assert_eq!(detect_format(&protected), ProgramFormat::Protected);
+        assert_ne!(&protected[1..image.len() - 1], &image[1..image.len() - 1]);
+        assert_eq!(unprotect(&protected).unwrap(), image[..image.len() - 1]);
+        assert_eq!(decode_program(&protected).unwrap(), "10 PRINT \"SECRET\"\n20 GOTO 10\n");
+    }
+
+    #[test]
+    fn test_protect_known_bytes() {
+        let image = tokenize("10 END").unwrap();
+        assert_eq!(
+            protect(&image).unwrap(),
+            vec![0xFE, 0xDB, 0xA9, 0xBF, 0x54, 0x32, 0xF2, 0x5F, 0xF1, 0x1A]
+        );
+    }
+
+    #[test]
+    fn test_mbf_conversion() {
+        for value in [1.0f32, -2.5, 0.1, 40000.0, 1e-10] {
+            assert_eq!(mbf_to_f32(f32_to_mbf(value)), value);
+        }
+        for value in [1.0f64, -1234.5678, 1.0 / 3.0] {
+            assert_eq!(mbf_to_f64(f64_to_mbf(value)), value);
+        }
+    }
+
+    #[test]
+    fn test_decode_ascii() {
+        assert_eq!(decode_program(b"10 END\r\n\x1A").unwrap(), "10 END\r\n");
+    }
+}
@@ -68,6 +68,23 @@ impl Value {
         matches!(self, Value::String(_))
     }
     
+    /// Text of the value as PRINT shows it: numbers are followed by a
+    /// space and positive numbers are preceded by one (where the sign goes)
+    pub fn to_print_string(&self) -> String {
+        let number = match self {
+            Value::Integer(i) => i.to_string(),
+            Value::Single(f) => format_single(*f),
+            Value::Double(d) => format_double(*d),
+            Value::String(s) => return s.clone(),
+            Value::Nil => return String::new(),
+        };
+        if number.starts_with('-') {
+            format!("{} ", number)
+        } else {
+            format!(" {} ", number)
+        }
+    }
+
     /// Convert value to string with Result
     pub fn as_string_result(&self) -> Result<String> {
         match self {
@@ -77,6 +94,61 @@ impl Value {
     }
 }
 
+/// Bytes of a string built from CHR$ codes, such as a PAINT tile or the
+/// result of VARPTR$
+pub fn string_bytes(text: &str) -> Vec<u8> {
+    text.chars().map(|ch| ch as u32 as u8).collect()
+}
+
+/// Format a single-precision number the way GW-BASIC prints it
+/// (7 significant digits, no leading zero, `E` exponent when needed)
+pub fn format_single(value: f32) -> String {
+    format_float(value as f64, 7, 'E')
+}
+
+/// Format a double-precision number the way GW-BASIC prints it
+/// (16 significant digits, `D` exponent when needed)
+pub fn format_double(value: f64) -> String {
+    format_float(value, 16, 'D')
+}
+
+fn format_float(value: f64, digits: usize, exp_char: char) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let sign = if value < 0.0 { "-" } else { "" };
+
+    // Round to the available precision and split into digits and exponent
+    let sci = format!("{:.*e}", digits - 1, value.abs());
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let mut sig: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+    while sig.len() > 1 && sig.ends_with('0') {
+        sig.pop();
+    }
+
+    // Number of digits in front of the decimal point
+    let point = exponent + 1;
+    let body = if point >= -1 && point <= digits as i32 {
+        if point <= 0 {
+            format!(".{}{}", "0".repeat((-point) as usize), sig)
+        } else if point as usize >= sig.len() {
+            format!("{}{}", sig, "0".repeat(point as usize - sig.len()))
+        } else {
+            format!("{}.{}", &sig[..point as usize], &sig[point as usize..])
+        }
+    } else {
+        let mantissa = if sig.len() > 1 {
+            format!("{}.{}", &sig[..1], &sig[1..])
+        } else {
+            sig
+        };
+        let exp_sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}{}{}{:02}", mantissa, exp_char, exp_sign, exponent.abs())
+    };
+    format!("{}{}", sign, body)
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -117,6 +189,25 @@ mod tests {
         assert_eq!(val.to_string(), "123");
     }
 
+    #[test]
+    fn test_format_numbers() {
+        assert_eq!(format_single(0.5), ".5");
+        assert_eq!(format_single(-2.25), "-2.25");
+        assert_eq!(format_single(0.01), ".01");
+        assert_eq!(format_single(0.001), "1E-03");
+        assert_eq!(format_single(1_000_000.0), "1000000");
+        assert_eq!(format_single(1.5e10), "1.5E+10");
+        assert_eq!(format_double(2e20), "2D+20");
+        assert_eq!(format_double(1.0 / 3.0), ".3333333333333333");
+    }
+
+    #[test]
+    fn test_print_string() {
+        assert_eq!(Value::Integer(5).to_print_string(), " 5 ");
+        assert_eq!(Value::Single(-0.5).to_print_string(), "-.5 ");
+        assert_eq!(Value::String("A".to_string()).to_print_string(), "A");
+    }
+
     #[test]
     fn test_nil_value() {
         let val = Value::Nil;
@@ -0,0 +1,311 @@
+//! VIEW and WINDOW coordinate transforms
+//!
+//! Graphics statements use logical coordinates. WINDOW maps a logical
+//! rectangle onto the viewport, with y growing upwards unless WINDOW SCREEN
+//! is used; without WINDOW, logical coordinates are physical ones. Physical
+//! coordinates count pixels from the top left corner of the viewport set by
+//! VIEW, or of the screen for VIEW SCREEN. All drawing is clipped to the
+//! viewport.
+
+use crate::error::{Error, Result};
+use crate::graphics_backend::{
+    draw_circle, draw_line, draw_rect, ellipse_radii, CircleShape, GraphicsBackend,
+};
+
+fn illegal_function_call() -> Error {
+    Error::RuntimeError("Illegal function call".to_string())
+}
+
+/// A logical coordinate system set by WINDOW
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    /// Smaller and larger logical x
+    x1: f64,
+    x2: f64,
+
+    /// Smaller and larger logical y
+    y1: f64,
+    y2: f64,
+
+    /// WINDOW SCREEN: y grows downwards as on the screen
+    screen: bool,
+}
+
+/// The viewport and logical coordinate system of the graphics screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    /// Screen pixels that drawing is clipped to: left, top, right, bottom
+    clip: (i32, i32, i32, i32),
+
+    /// Screen pixel of physical (0, 0)
+    origin: (i32, i32),
+
+    window: Option<Window>,
+}
+
+impl Viewport {
+    /// The whole screen, with physical coordinates
+    pub fn full(width: usize, height: usize) -> Self {
+        Viewport {
+            clip: (0, 0, width as i32 - 1, height as i32 - 1),
+            origin: (0, 0),
+            window: None,
+        }
+    }
+
+    /// Set the viewport to the screen pixels (x1, y1)-(x2, y2), or the
+    /// whole screen for None. Physical coordinates count from the corner
+    /// of the viewport unless `screen` is set (VIEW SCREEN).
+    pub fn set_view(&mut self, corners: Option<((i32, i32), (i32, i32))>, screen: bool, size: (usize, usize)) -> Result<()> {
+        let (width, height) = (size.0 as i32, size.1 as i32);
+        let Some(((x1, y1), (x2, y2))) = corners else {
+            self.clip = (0, 0, width - 1, height - 1);
+            self.origin = (0, 0);
+            return Ok(());
+        };
+        let (left, right) = (x1.min(x2), x1.max(x2));
+        let (top, bottom) = (y1.min(y2), y1.max(y2));
+        if left < 0 || top < 0 || right >= width || bottom >= height {
+            return Err(illegal_function_call());
+        }
+        self.clip = (left, top, right, bottom);
+        self.origin = if screen { (0, 0) } else { (left, top) };
+        Ok(())
+    }
+
+    /// Map the logical rectangle (x1, y1)-(x2, y2) onto the viewport, or
+    /// go back to physical coordinates for None
+    pub fn set_window(&mut self, corners: Option<((f64, f64), (f64, f64))>, screen: bool) -> Result<()> {
+        self.window = match corners {
+            None => None,
+            Some(((x1, y1), (x2, y2))) => {
+                if x1 == x2 || y1 == y2 {
+                    return Err(illegal_function_call());
+                }
+                Some(Window {
+                    x1: x1.min(x2),
+                    x2: x1.max(x2),
+                    y1: y1.min(y2),
+                    y2: y1.max(y2),
+                    screen,
+                })
+            }
+        };
+        Ok(())
+    }
+
+    /// Screen pixels that drawing is clipped to: left, top, right, bottom
+    pub fn clip(&self) -> (i32, i32, i32, i32) {
+        self.clip
+    }
+
+    /// Physical size of the viewport less one, the span WINDOW maps onto
+    fn span(&self) -> (f64, f64) {
+        let (left, top, right, bottom) = self.clip;
+        ((right - left) as f64, (bottom - top) as f64)
+    }
+
+    /// Physical pixels per logical unit along x and y
+    pub fn scale(&self) -> (f64, f64) {
+        let (span_x, span_y) = self.span();
+        match self.window {
+            Some(w) => (span_x / (w.x2 - w.x1), span_y / (w.y2 - w.y1)),
+            None => (1.0, 1.0),
+        }
+    }
+
+    /// Physical coordinates of the viewport's top left corner, where
+    /// WINDOW starts: (0, 0) except after VIEW SCREEN
+    fn corner(&self) -> (f64, f64) {
+        ((self.clip.0 - self.origin.0) as f64, (self.clip.1 - self.origin.1) as f64)
+    }
+
+    /// Logical x to physical x
+    pub fn physical_x(&self, x: f64) -> f64 {
+        match self.window {
+            Some(w) => self.corner().0 + (x - w.x1) * self.scale().0,
+            None => x,
+        }
+    }
+
+    /// Logical y to physical y
+    pub fn physical_y(&self, y: f64) -> f64 {
+        match self.window {
+            Some(w) if w.screen => self.corner().1 + (y - w.y1) * self.scale().1,
+            Some(w) => self.corner().1 + (w.y2 - y) * self.scale().1,
+            None => y,
+        }
+    }
+
+    /// Physical x to logical x
+    pub fn logical_x(&self, x: f64) -> f64 {
+        match self.window {
+            Some(w) => w.x1 + (x - self.corner().0) / self.scale().0,
+            None => x,
+        }
+    }
+
+    /// Physical y to logical y
+    pub fn logical_y(&self, y: f64) -> f64 {
+        match self.window {
+            Some(w) if w.screen => w.y1 + (y - self.corner().1) / self.scale().1,
+            Some(w) => w.y2 - (y - self.corner().1) / self.scale().1,
+            None => y,
+        }
+    }
+
+    /// Screen pixel of a physical point
+    pub fn device(&self, x: f64, y: f64) -> (i32, i32) {
+        (x.round() as i32 + self.origin.0, y.round() as i32 + self.origin.1)
+    }
+
+    /// Logical centre of the viewport, where drawing starts
+    pub fn centre(&self) -> (f64, f64) {
+        let (left, top, right, bottom) = self.clip;
+        let x = ((left + right + 1) / 2 - self.origin.0) as f64;
+        let y = ((top + bottom + 1) / 2 - self.origin.1) as f64;
+        (self.logical_x(x), self.logical_y(y))
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (left, top, right, bottom) = self.clip;
+        (left..=right).contains(&x) && (top..=bottom).contains(&y)
+    }
+}
+
+/// A backend seen through the viewport: pixels outside it are neither
+/// drawn nor read, so every primitive drawn on it is clipped. Primitives
+/// that lie wholly inside the viewport are passed on whole, for backends
+/// that draw them as shapes rather than pixels.
+pub struct Clipped<'a> {
+    pub backend: &'a mut dyn GraphicsBackend,
+    pub viewport: Viewport,
+}
+
+impl GraphicsBackend for Clipped<'_> {
+    fn pset(&mut self, x: i32, y: i32, color: u8) -> Result<()> {
+        if self.viewport.contains(x, y) {
+            self.backend.pset(x, y, color)?;
+        }
+        Ok(())
+    }
+
+    fn point(&self, x: i32, y: i32) -> Option<u8> {
+        if self.viewport.contains(x, y) {
+            self.backend.point(x, y)
+        } else {
+            None
+        }
+    }
+
+    fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        if self.viewport.contains(x1, y1) && self.viewport.contains(x2, y2) {
+            self.backend.line(x1, y1, x2, y2, color, style)
+        } else {
+            draw_line(self, x1, y1, x2, y2, color, style)
+        }
+    }
+
+    fn rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8, style: u16) -> Result<()> {
+        if self.viewport.contains(x1, y1) && self.viewport.contains(x2, y2) {
+            self.backend.rect(x1, y1, x2, y2, color, style)
+        } else {
+            draw_rect(self, x1, y1, x2, y2, color, style)
+        }
+    }
+
+    fn fill_rect(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u8) -> Result<()> {
+        let (left, top, right, bottom) = self.viewport.clip();
+        let (x1, x2) = (x1.min(x2).max(left), x1.max(x2).min(right));
+        let (y1, y2) = (y1.min(y2).max(top), y1.max(y2).min(bottom));
+        if x1 > x2 || y1 > y2 {
+            return Ok(());
+        }
+        self.backend.fill_rect(x1, y1, x2, y2, color)
+    }
+
+    fn circle(&mut self, x: i32, y: i32, radius: i32, color: u8, shape: CircleShape) -> Result<()> {
+        let (rx, ry) = ellipse_radii(radius, shape.aspect);
+        let (rx, ry) = (rx.abs(), ry.abs());
+        if self.viewport.contains(x - rx, y - ry) && self.viewport.contains(x + rx, y + ry) {
+            self.backend.circle(x, y, radius, color, shape)
+        } else {
+            draw_circle(self, x, y, radius, color, shape)
+        }
+    }
+
+    fn cls(&mut self) {
+        self.backend.cls();
+    }
+
+    fn locate(&mut self, row: usize, col: usize) -> Result<()> {
+        self.backend.locate(row, col)
+    }
+
+    fn color(&mut self, fg: Option<u8>, bg: Option<u8>) {
+        self.backend.color(fg, bg);
+    }
+
+    fn display(&mut self) {
+        self.backend.display();
+    }
+
+    fn get_size(&self) -> (usize, usize) {
+        self.backend.get_size()
+    }
+
+    fn get_cursor(&self) -> (usize, usize) {
+        self.backend.get_cursor()
+    }
+
+    fn should_close(&self) -> bool {
+        self.backend.should_close()
+    }
+
+    fn update(&mut self) -> Result<()> {
+        self.backend.update()
+    }
+
+    fn set_palette(&mut self, colors: &[u32]) {
+        self.backend.set_palette(colors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_transform() {
+        let mut viewport = Viewport::full(201, 101);
+        viewport.set_window(Some(((-1.0, -1.0), (1.0, 1.0))), false).unwrap();
+        assert_eq!(viewport.device(viewport.physical_x(-1.0), viewport.physical_y(1.0)), (0, 0));
+        assert_eq!(viewport.device(viewport.physical_x(0.5), viewport.physical_y(-0.5)), (150, 75));
+        assert_eq!(viewport.logical_y(100.0), -1.0);
+
+        viewport.set_window(Some(((0.0, 0.0), (20.0, 10.0))), true).unwrap();
+        assert_eq!(viewport.physical_y(2.0), 20.0);
+        assert!(viewport.set_window(Some(((0.0, 1.0), (5.0, 1.0))), false).is_err());
+    }
+
+    #[test]
+    fn test_view_origin_and_clip() {
+        let mut viewport = Viewport::full(100, 50);
+        viewport.set_view(Some(((10, 5), (59, 24))), false, (100, 50)).unwrap();
+        assert_eq!(viewport.device(0.0, 0.0), (10, 5));
+        assert!(viewport.contains(59, 24) && !viewport.contains(60, 24));
+        assert_eq!(viewport.centre(), (25.0, 10.0));
+
+        viewport.set_view(Some(((10, 5), (59, 24))), true, (100, 50)).unwrap();
+        assert_eq!(viewport.device(0.0, 0.0), (0, 0));
+
+        // WINDOW maps onto the viewport, wherever VIEW SCREEN put it
+        viewport.set_window(Some(((0.0, 0.0), (1.0, 1.0))), true).unwrap();
+        assert_eq!(viewport.physical_x(0.0), 10.0);
+        assert_eq!(viewport.physical_y(1.0), 24.0);
+        assert_eq!(viewport.logical_x(59.0), 1.0);
+        assert_eq!(viewport.logical_y(5.0), 0.0);
+        assert!(viewport.contains(viewport.physical_x(0.5).round() as i32, viewport.physical_y(0.5).round() as i32));
+        assert!(viewport.set_view(Some(((0, 0), (100, 10))), false, (100, 50)).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 //! Integration tests for GW-BASIC interpreter
 
-use rust_gwbasic::{Lexer, Parser, Interpreter};
+use rust_gwbasic::{Diagnostic, Lexer, Parser, Interpreter, RecordInterval, Recorder};
 
 fn run_program(code: &str) -> Result<String, String> {
     // Trim each line to remove leading/trailing whitespace
@@ -23,6 +23,24 @@ fn run_program(code: &str) -> Result<String, String> {
     Ok("Success".to_string())
 }
 
+/// Run a program with output captured, panicking on any error, and return
+/// the interpreter to look at what it did
+fn run_captured(code: &str) -> Interpreter {
+    run_captured_on(Interpreter::new(), code)
+}
+
+/// `run_captured` with an interpreter set up by the test
+fn run_captured_on(mut interpreter: Interpreter, code: &str) -> Interpreter {
+    let code: Vec<&str> = code.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let tokens = Lexer::new(&code.join("\n")).tokenize().unwrap();
+    let ast = Parser::new(tokens).parse().unwrap();
+
+    interpreter.capture_output();
+    interpreter.execute(ast).unwrap();
+    interpreter.run_stored_program().unwrap();
+    interpreter
+}
+
 #[test]
 fn test_hello_world() {
     let code = r#"
@@ -78,6 +96,7 @@ fn test_if_then_else() {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn test_input_with_prompt() {
     // This should parse correctly even in non-interactive mode
     let code = r#"
@@ -94,6 +113,7 @@ fn test_input_with_prompt() {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn test_randomize_timer() {
     let code = r#"
         10 RANDOMIZE TIMER
@@ -108,6 +128,7 @@ fn test_randomize_timer() {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn test_array_assignment() {
     let code = r#"
         10 DIM A(10)
@@ -148,3 +169,366 @@ fn test_graphics_circle() {
     "#;
     assert!(run_program(code).is_ok());
 }
+
+#[test]
+fn test_graphics_paint() {
+    let code = r#"
+        10 SCREEN 1
+        20 CIRCLE (160, 100), 40, 2
+        30 PAINT (160, 100), 1, 2
+        40 PAINT STEP(0, 0), CHR$(&HAA) + CHR$(&H55), 2, CHR$(0)
+        50 END
+    "#;
+    assert!(run_program(code).is_ok());
+    assert!(run_program("10 PAINT (1, 1), \"\"").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_graphics_draw() {
+    let code = r#"
+        10 SCREEN 1
+        20 SIDE$ = "R20 D20": N% = 20
+        30 DRAW "BM100,50 C2 X" + VARPTR$(SIDE$) + " L=N%; U20"
+        40 DRAW "TA45 S8 NR10 BF2 P1,2"
+        50 END
+    "#;
+    assert!(run_program(code).is_ok());
+    assert!(run_program("10 DRAW \"M10\"").unwrap_err().contains("Illegal function call"));
+
+    // Names in the string are not case sensitive either
+    let code = "10 SCREEN 1\n20 n% = 5: D = 3\n30 DRAW \"BM0,0 C2 R=N%; D=d;\"\n40 PRINT POINT(5, 0); POINT(5, 3)";
+    assert_eq!(run_captured(code).take_output(), " 2  2 \n");
+}
+
+#[test]
+fn test_graphics_get_put() {
+    let code = r#"
+        10 SCREEN 1
+        20 DIM SPRITE%(10): DIM FLOATS(10)
+        30 LINE (0, 0)-(7, 3), 3, BF
+        40 GET (0, 0)-(7, 3), SPRITE%
+        50 IF SPRITE%(0) <> 16 OR SPRITE%(1) <> 4 OR SPRITE%(2) <> -1 THEN X = 1 / 0
+        60 PUT (100, 100), SPRITE%, PSET
+        70 PUT (100, 100), SPRITE%
+        80 GET (0, 0)-(7, 3), FLOATS
+        90 PUT (50, 50), FLOATS, OR
+        100 END
+    "#;
+    assert_eq!(run_program(code).unwrap_or_else(|e| panic!("{}", e)), "Success");
+    assert!(run_program("10 DIM A%(2)\n20 GET (0, 0)-(7, 7), A%").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_graphics_view_window() {
+    let code = r#"
+        10 SCREEN 1
+        20 VIEW (20, 20)-(219, 119), 1, 3
+        30 WINDOW (-1, -1)-(1, 1)
+        40 IF PMAP(-1, 0) <> 0 OR PMAP(1, 1) <> 0 OR PMAP(99, 3) <> -1 THEN X = 1 / 0
+        50 FOR I = -1 TO 1 STEP 0.25: PSET (I, I * I), 2: NEXT I
+        60 LINE (-2, -2)-(2, 2), 3
+        70 CIRCLE (0, 0), 0.5, 2
+        80 WINDOW SCREEN (0, 0)-(10, 10): VIEW SCREEN (0, 0)-(99, 99): VIEW
+        90 END
+    "#;
+    assert_eq!(run_program(code).unwrap_or_else(|e| panic!("{}", e)), "Success");
+    assert!(run_program("10 WINDOW (0, 0)-(0, 5)").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_graphics_point() {
+    let code = r#"
+        10 SCREEN 1
+        20 PSET (10, 20), 2
+        30 IF POINT(10, 20) <> 2 OR POINT(11, 20) <> 0 OR POINT(-1, 0) <> -1 THEN X = 1 / 0
+        40 IF POINT(0) <> 10 OR POINT(1) <> 20 THEN X = 1 / 0
+        50 WINDOW (0, 0)-(319, 199)
+        60 IF POINT(10, 179) <> 2 THEN X = 1 / 0
+        70 END
+    "#;
+    assert_eq!(run_program(code).unwrap_or_else(|e| panic!("{}", e)), "Success");
+}
+
+#[test]
+fn test_screen_modes() {
+    let code = "10 SCREEN 7, , 1, 0\n20 PRINT STRING$(45, \"*\")\n30 SCREEN 9\n40 PRINT POINT(639, 349)";
+    let mut interpreter = run_captured(code);
+
+    // SCREEN 7 has 40 text columns
+    assert_eq!(interpreter.take_output(), format!("{}\n{}\n 0 \n", "*".repeat(40), "*".repeat(5)));
+
+    assert!(run_program("10 SCREEN 3").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 SCREEN 1, 0, 1").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_text_screen() {
+    let code = [
+        "10 CLS",
+        "20 LOCATE 5, 10",
+        "30 COLOR 14, 1",
+        "40 PRINT \"HI\";",
+        "50 PRINT CSRLIN; POS(0)",
+        "60 PRINT SCREEN(5, 10); SCREEN(5, 10, 1); SCREEN(1, 1)",
+        "70 VIEW PRINT 10 TO 12",
+        "80 PRINT CSRLIN",
+        "90 PRINT \"A\": PRINT \"B\": PRINT \"C\"",
+        "100 PRINT SCREEN(10, 1); SCREEN(11, 1); SCREEN(12, 1)",
+    ]
+    .join("\n");
+    let mut interpreter = run_captured(&code);
+
+    // Yellow on blue is attribute &H1E; the window scrolls "A" away
+    let output = interpreter.take_output();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines[0], "HI 5  15 ");
+    assert_eq!(lines[1], " 72  30  32 ");
+    assert_eq!(lines[2], " 10 ");
+    assert_eq!(lines[6], " 66  67  32 ");
+
+    assert!(run_program("10 LOCATE 26, 1").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 LOCATE 1, 81").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 LOCATE , , 2").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 VIEW PRINT 20 TO 25").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 PRINT SCREEN(1, 0)").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_default_foreground() {
+    // Each mode draws in its own foreground until COLOR changes it
+    for (mode, foreground) in [(1, 3), (2, 1), (7, 15), (8, 15), (9, 15), (10, 3)] {
+        let code = format!("10 SCREEN {}\n20 PSET (5, 5)\n30 LINE (0, 9)-(9, 9)\n40 PRINT POINT(5, 5); POINT(9, 9)", mode);
+        assert_eq!(run_captured(&code).take_output(), format!(" {0}  {0} \n", foreground), "SCREEN {}", mode);
+    }
+    let code = "10 SCREEN 9\n20 COLOR 2\n30 PSET (5, 5)\n40 CIRCLE (50, 50), 10\n50 PRINT POINT(5, 5); POINT(60, 50)";
+    assert_eq!(run_captured(code).take_output(), " 2  2 \n");
+    // COLOR in SCREEN 1 sets the background and palette, not the foreground
+    let code = "10 SCREEN 1\n20 COLOR 1, 0\n30 PSET (5, 5)\n40 PRINT POINT(5, 5)";
+    assert_eq!(run_captured(code).take_output(), " 3 \n");
+}
+
+#[test]
+fn test_trace_and_beep() {
+    // TRON numbers each line on the screen; BEEP rings without moving the cursor
+    let code = "10 TRON\n20 BEEP\n30 PRINT POS(0)\n40 TROFF\n50 PRINT \"X\"";
+    assert_eq!(run_captured(code).take_output(), "[20]\x07[30] 9 \n[40]X\n");
+}
+
+#[test]
+fn test_colors_out_of_range() {
+    // Only attributes the mode has can be drawn
+    for code in [
+        "10 SCREEN 1\n20 PSET (5, 5), 9",
+        "10 SCREEN 1\n20 LINE (0, 0)-(5, 5), 256",
+        "10 SCREEN 2\n20 CIRCLE (50, 50), 10, -1",
+        "10 SCREEN 1\n20 DRAW \"C4 R5\"",
+        "10 SCREEN 9\n20 COLOR 16",
+        "10 SCREEN 9\n20 PAINT (5, 5), 3, 16",
+    ] {
+        assert!(run_program(code).unwrap_err().contains("Illegal function call"), "{}", code);
+    }
+    let code = "10 SCREEN 9\n20 COLOR 15, 63\n30 PSET (5, 5), 14\n40 PRINT POINT(5, 5)";
+    assert_eq!(run_captured(code).take_output(), " 14 \n");
+}
+
+#[test]
+fn test_video_pages() {
+    let code = r#"
+        10 SCREEN 7, , 1, 0
+        20 PSET (10, 10), 4
+        30 IF POINT(10, 10) <> 4 THEN X = 1 / 0
+        40 PCOPY 1, 0
+        50 SCREEN , , 0, 0
+        60 IF POINT(10, 10) <> 4 THEN X = 1 / 0
+        70 END
+    "#;
+    assert_eq!(run_program(code).unwrap_or_else(|e| panic!("{}", e)), "Success");
+    assert!(run_program("10 SCREEN 9\n20 PCOPY 0, 2").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_palette() {
+    let code = r#"
+        10 SCREEN 9
+        20 PALETTE 1, 63
+        30 DIM P%(15)
+        40 FOR I = 0 TO 15: LET P%(I) = 15 - I: NEXT I
+        50 PALETTE USING P%(0)
+        60 PALETTE
+        70 SCREEN 1
+        80 COLOR 1, 0
+        90 END
+    "#;
+    assert_eq!(run_program(code).unwrap_or_else(|e| panic!("{}", e)), "Success");
+    assert!(run_program("10 SCREEN 9\n20 PALETTE 1, 64").unwrap_err().contains("Illegal function call"));
+    assert!(run_program("10 SCREEN 9\n20 DIM P%(3)\n30 PALETTE USING P%(0)").unwrap_err().contains("Illegal function call"));
+}
+
+#[test]
+fn test_headless_screenshot() {
+    let code = "10 SCREEN 1\n20 PSET (5, 5), 3\n30 LINE (0, 199)-(319, 199), 1\n40 END";
+    let interpreter = run_captured_on(Interpreter::new_headless(), code);
+
+    // SCREEN 1 starts on palette 1: cyan, magenta and light grey
+    let snapshot = interpreter.screenshot();
+    assert_eq!((snapshot.width, snapshot.height), (320, 200));
+    assert_eq!(snapshot.rgb(5, 5), 0xAAAAAA);
+    assert_eq!(snapshot.rgb(100, 199), 0x00AAAA);
+    assert_eq!(snapshot.rgb(6, 5), 0x000000);
+
+    let path = std::env::temp_dir().join(format!("gwbasic-screenshot-{}.ppm", std::process::id()));
+    snapshot.save(&path).unwrap();
+    let ppm = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(ppm.starts_with(b"P6\n320 200\n255\n"));
+    assert_eq!(ppm.len(), 15 + 320 * 200 * 3);
+}
+
+#[test]
+fn test_recording() {
+    let code = r#"
+        10 SCREEN 1
+        20 FOR I = 0 TO 9
+        30 PSET (I, 0), 1
+        40 NEXT I
+        50 CLS
+        60 PSET (0, 1), 2
+    "#;
+    let mut interpreter = Interpreter::new_headless();
+    interpreter.start_recording(Recorder::new(RecordInterval::Statements(10)));
+    let mut interpreter = run_captured_on(interpreter, code);
+    let recorder = interpreter.finish_recording().unwrap();
+
+    // The screen after SCREEN 1, every 10 statements of the loop, the
+    // whole row just before CLS, and the screen at the end
+    let frames = recorder.frames();
+    let lit = |frame: usize| (0..10).filter(|&x| frames[frame].rgb(x, 0) != 0).count();
+    assert_eq!(frames.len(), 5);
+    assert!(frames.iter().all(|frame| (frame.width, frame.height) == (320, 200)));
+    assert_eq!((lit(0), lit(1), lit(2), lit(3), lit(4)), (0, 4, 9, 10, 0));
+    assert_eq!(frames[4].rgb(0, 1), 0xAA00AA);
+
+    let gif = recorder.to_gif().unwrap();
+    assert!(gif.starts_with(b"GIF89a"));
+    assert!(interpreter.finish_recording().is_none());
+}
+
+#[test]
+fn test_svg_output() {
+    let path = std::env::temp_dir().join(format!("gwbasic-{}.svg", std::process::id()));
+    let code = "10 SCREEN 2\n20 LINE (0, 0)-(100, 50)\n30 CIRCLE (320, 100), 40\n40 END";
+    run_captured_on(Interpreter::new_with_svg(&path), code);
+
+    let svg = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="640" height="200""#));
+    assert!(svg.contains(r##"<line x1="0.5" y1="0.5" x2="100.5" y2="50.5" stroke="#FFFFFF"/>"##));
+    assert!(svg.contains("<ellipse"));
+
+    // Flipping pages keeps the shapes rather than turning them into pixels
+    let code = "10 SCREEN 7, , 0, 0\n20 LINE (0, 0)-(100, 50), 2\n30 SCREEN 7, , 1, 1\n40 SCREEN 7, , 0, 0\n50 END";
+    run_captured_on(Interpreter::new_with_svg(&path), code);
+    let svg = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(svg.contains("<line"));
+    assert_eq!(svg.matches("<rect").count(), 1);
+}
+
+#[test]
+fn test_runtime_error_diagnostic() {
+    let code = "10 X = 1\n20 Y = X / 0";
+    let tokens = Lexer::new(code).tokenize().unwrap();
+    let ast = Parser::new(tokens).parse().unwrap();
+
+    let mut interpreter = Interpreter::new();
+    interpreter.execute(ast).unwrap();
+    let err = interpreter.run_stored_program().unwrap_err();
+
+    let diagnostic = Diagnostic::new(&err, code).unwrap();
+    assert_eq!(diagnostic.span.line_number, Some(20));
+    assert_eq!(diagnostic.source_line, "20 Y = X / 0");
+    assert!(diagnostic.render().ends_with("   ^^^^^^^^^"));
+}
+
+#[test]
+fn test_bad_line_is_stored() {
+    let code = "10 X = 5\n20 X = : Y = 1\n30 END";
+    let mut interpreter = Interpreter::new();
+    interpreter.load_program(code.as_bytes()).unwrap();
+
+    // The bad line is listed as entered and only fails once it is reached
+    assert!(interpreter.listing().unwrap().contains("20 X = : Y = 1"));
+    let err = interpreter.run_stored_program().unwrap_err();
+    assert_eq!(err.span().and_then(|span| span.line_number), Some(20));
+    assert!(matches!(err.without_span(), rust_gwbasic::Error::SyntaxError(_)));
+}
+
+#[test]
+fn test_print_separators_and_zones() {
+    let code = "10 PRINT 1;-2;\n20 PRINT \"A\",\"B\"\n30 PRINT \"X\";TAB(6);\"Y\";SPC(2);\"Z\"\n40 WIDTH 12\n50 PRINT \"HELLO\";\"WORLD\";123";
+    let mut interpreter = run_captured(code);
+
+    assert_eq!(
+        interpreter.take_output(),
+        " 1 -2 A       B\nX    Y  Z\nHELLOWORLD\n 123 \n"
+    );
+}
+
+#[test]
+fn test_print_using() {
+    let code = "10 F$ = \"##.## \"\n20 PRINT USING F$; 10.2, 5.3;\n30 PRINT USING \"&!\"; \"abc\", \"xyz\"\n40 LPRINT USING \"**$##.##\"; 2.34";
+    let mut interpreter = run_captured(code);
+
+    assert_eq!(interpreter.take_output(), "10.20  5.30 abcx\n");
+    assert_eq!(interpreter.take_printer_output(), "***$2.34\n");
+}
+
+#[test]
+fn test_printer_file() {
+    // LPRINT goes to its own sink, which capturing the screen leaves alone
+    let path = std::env::temp_dir().join(format!("gwbasic-printer-{}.txt", std::process::id()));
+    let mut interpreter = Interpreter::new();
+    interpreter.set_printer(Box::new(std::fs::File::create(&path).unwrap()));
+    let code = "10 PRINT \"SCREEN\"\n20 LPRINT \"PAPER\"; 1\n30 LPRINT TAB(5); \"X\"";
+    let mut interpreter = run_captured_on(interpreter, code);
+
+    assert_eq!(interpreter.take_output(), "SCREEN\n");
+    assert_eq!(interpreter.take_printer_output(), "");
+    drop(interpreter);
+    let printed = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(printed, "PAPER 1 \n    X\n");
+}
+
+#[test]
+fn test_if_then_line_number() {
+    let code = "10 X = 0\n20 IF X = 0 THEN 50 ELSE 70\n30 PRINT \"skipped\"\n50 IF X GOTO 70 ELSE PRINT \"zero\";\n60 IF X = 0 THEN IF X > 1 THEN 70 ELSE PRINT \"inner\"\n70 END";
+    let mut interpreter = run_captured(code);
+
+    assert_eq!(interpreter.take_output(), "zeroinner\n");
+
+    // Strings compare by character codes
+    let code = "10 A$ = \"Y\"\n20 IF A$=\"Y\" THEN 100 ELSE 300\n100 PRINT \"yes\"; \"AB\" < \"B\"; \"a\" > \"B\"\n110 END\n300 PRINT \"no\"";
+    assert_eq!(run_captured(code).take_output(), "yes-1 -1 \n");
+    assert!(run_program("10 IF \"1\" = 1 THEN 20\n20 END").unwrap_err().contains("Type mismatch"));
+}
+
+#[test]
+fn test_run_protected_file() {
+    let path = std::env::temp_dir().join(format!("gwbasic-protected-{}.bas", std::process::id()));
+    let image = rust_gwbasic::tokenized::tokenize("10 X = 1\n20 LIST\n30 PRINT \"AFTER\"\n").unwrap();
+    std::fs::write(&path, rust_gwbasic::tokenized::protect(&image).unwrap()).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rust-gwbasic"))
+        .arg(&path)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // LIST refuses, and the error does not show the program either
+    let (stdout, stderr) = (String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    assert!(!output.status.success());
+    assert!(!stdout.contains("X = 1") && !stdout.contains("AFTER"));
+    assert!(stderr.contains("Illegal function call") && !stderr.contains("LIST"));
+}